@@ -0,0 +1,40 @@
+/* C interface of the timekeeper library, built with the `ffi` feature */
+#ifndef TIMEKEEPER_H
+#define TIMEKEEPER_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The run finished or was stopped by the callback */
+#define TIMEKEEPER_OK 0
+/* options_json is missing, not UTF-8 or not valid options */
+#define TIMEKEEPER_INVALID_OPTIONS 1
+/* The run itself failed, e.g. exiftool is missing or the destination is full */
+#define TIMEKEEPER_RUN_FAILED 2
+/* The library panicked */
+#define TIMEKEEPER_PANIC 3
+
+/*
+ * Receives every event as a JSON object, valid only during the call:
+ *   {"event": "file", "source": ..., "destination": ..., "action": "copy",
+ *    "date_source": "metadata", "error": null}
+ *   {"event": "finish", "error": null, "stats": {...}}
+ * Return non-zero to stop the run after the files in flight.
+ */
+typedef int (*timekeeper_callback)(const char *event_json, void *user_data);
+
+/*
+ * Organize files as described by options_json, blocking until the run ends:
+ *   {"source": "/media/card", "destination": "/home/me/Photos",
+ *    "dry_run": false, "move": false, "preset": "immich", "layout": "year-month",
+ *    "exiftool": "/usr/bin/exiftool", "verify": false, "manifest": "sha256"}
+ * Only source and destination are required. callback may be NULL.
+ */
+int timekeeper_organize(const char *options_json, timekeeper_callback callback, void *user_data);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* TIMEKEEPER_H */
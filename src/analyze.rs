@@ -0,0 +1,250 @@
+use chrono::{DateTime, Datelike, Local, NaiveDate};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::Organizer;
+use crate::error::Result;
+use crate::outcome::DateSource;
+use crate::visited::OrganizedFolders;
+
+/// Dates cameras, operating systems and file systems fall back to when no clock was set
+const DEFAULT_DATES: [(i32, u32, u32, &str); 5] = [
+    (1904, 1, 1, "Mac epoch"),
+    (1970, 1, 1, "Unix epoch"),
+    (1980, 1, 1, "FAT/DOS epoch"),
+    (2000, 1, 1, "camera default date"),
+    (2001, 1, 1, "camera default date"),
+];
+
+/// A single day only counts as a spike with at least this many files...
+const SPIKE_MIN_FILES: usize = 50;
+
+/// ...and at least this many times the files of a typical (median) day
+const SPIKE_FACTOR: usize = 10;
+
+/// Widest histogram bar in characters
+const BAR_WIDTH: usize = 50;
+
+/// Files per month, split by where their date came from
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct MonthCount {
+    pub year: i32,
+    pub month: u32,
+    /// Dated from metadata
+    pub exif: usize,
+    /// Dated from the file time fallback
+    pub fallback: usize,
+}
+
+impl MonthCount {
+    pub fn total(&self) -> usize {
+        self.exif + self.fallback
+    }
+}
+
+/// A day holding suspiciously many files
+#[derive(Debug, Clone, Serialize)]
+pub struct Spike {
+    pub date: NaiveDate,
+    pub files: usize,
+    pub reason: String,
+}
+
+/// Date distribution of a source, gathered without moving anything
+#[derive(Debug, Default, Serialize)]
+pub struct DateAnalysis {
+    pub files: usize,
+    pub exif: usize,
+    pub fallback: usize,
+    pub months: Vec<MonthCount>,
+    pub spikes: Vec<Spike>,
+}
+
+/// Dates gathered so far, shared between the extraction threads
+#[derive(Default)]
+struct Tally {
+    months: BTreeMap<(i32, u32), MonthCount>,
+    days: HashMap<NaiveDate, usize>,
+}
+
+impl Organizer {
+    /// Extract the date of every media file in the source, the way a run would, and
+    /// summarize them per month. Nothing is copied or moved.
+    pub fn analyze(&self, terminate_flag: &AtomicBool) -> Result<DateAnalysis> {
+        let extractors = self.extractor_chain()?;
+        let tally = Mutex::new(Tally::default());
+
+        let files: Box<dyn Iterator<Item = _> + Send> = if self.source.is_dir() {
+            Box::new(
+                self.media_files(&extractors, &OrganizedFolders::default(), false)
+                    .filter_map(Result::ok)
+                    .map(ignore::DirEntry::into_path),
+            )
+        } else {
+            Box::new(std::iter::once(self.source.clone()))
+        };
+
+        files.par_bridge().for_each(|path| {
+            if terminate_flag.load(Ordering::SeqCst) {
+                return;
+            }
+            match extractors.extract(&path) {
+                Ok(date) => tally
+                    .lock()
+                    .unwrap()
+                    .add(&date.datetime, date.date_source == DateSource::Metadata),
+                Err(e) => eprintln!("[WARN] Could not date {}: {}", path.display(), e),
+            }
+        });
+
+        if let Some(cache) = &self.cache
+            && let Err(e) = cache.save()
+        {
+            eprintln!("[WARN] Could not save metadata cache: {}", e);
+        }
+
+        Ok(tally.into_inner().unwrap().into_analysis())
+    }
+}
+
+impl Tally {
+    fn add(&mut self, datetime: &DateTime<Local>, exif: bool) {
+        let month = self
+            .months
+            .entry((datetime.year(), datetime.month()))
+            .or_insert_with(|| MonthCount {
+                year: datetime.year(),
+                month: datetime.month(),
+                ..MonthCount::default()
+            });
+        if exif {
+            month.exif += 1;
+        } else {
+            month.fallback += 1;
+        }
+        *self.days.entry(datetime.date_naive()).or_default() += 1;
+    }
+
+    fn into_analysis(self) -> DateAnalysis {
+        let months: Vec<MonthCount> = self.months.into_values().collect();
+        let exif = months.iter().map(|month| month.exif).sum();
+        let fallback = months.iter().map(|month| month.fallback).sum();
+
+        DateAnalysis {
+            files: exif + fallback,
+            exif,
+            fallback,
+            months,
+            spikes: find_spikes(&self.days),
+        }
+    }
+}
+
+/// Days on a known default date, in the future, or far busier than a typical day
+fn find_spikes(days: &HashMap<NaiveDate, usize>) -> Vec<Spike> {
+    let mut counts: Vec<usize> = days.values().copied().collect();
+    counts.sort_unstable();
+    let median = counts.get(counts.len() / 2).copied().unwrap_or(0);
+    let today = Local::now().date_naive();
+
+    let mut spikes: Vec<Spike> = days
+        .iter()
+        .filter_map(|(&date, &files)| {
+            let default = DEFAULT_DATES.iter().find(|(year, month, day, _)| {
+                NaiveDate::from_ymd_opt(*year, *month, *day) == Some(date)
+            });
+            let reason = if let Some((_, _, _, name)) = default {
+                name.to_string()
+            } else if date > today {
+                "date in the future".to_string()
+            } else if files >= SPIKE_MIN_FILES && files >= median * SPIKE_FACTOR {
+                format!("{}x a typical day", files / median.max(1))
+            } else {
+                return None;
+            };
+            Some(Spike {
+                date,
+                files,
+                reason,
+            })
+        })
+        .collect();
+    spikes.sort_by_key(|spike| spike.date);
+    spikes
+}
+
+impl DateAnalysis {
+    /// Print the histogram, the date sources and any suspicious days
+    pub fn print(&self) {
+        let widest = self.months.iter().map(MonthCount::total).max().unwrap_or(0);
+
+        println!("\n=== FILES PER MONTH ===");
+        for month in &self.months {
+            let bar = month.total() * BAR_WIDTH / widest.max(1);
+            println!(
+                "{}-{:02}  {:<width$}  {}{}",
+                month.year,
+                month.month,
+                "#".repeat(bar.max(1)),
+                month.total(),
+                if month.fallback > 0 {
+                    format!(" ({} fallback)", month.fallback)
+                } else {
+                    String::new()
+                },
+                width = BAR_WIDTH
+            );
+        }
+
+        println!("\nFiles: {}", self.files);
+        if self.files > 0 {
+            println!(
+                "  - Dated from metadata: {} ({:.1}%)",
+                self.exif,
+                self.exif as f64 / self.files as f64 * 100.0
+            );
+            println!(
+                "  - Would use the fallback (ModTime): {} ({:.1}%)",
+                self.fallback,
+                self.fallback as f64 / self.files as f64 * 100.0
+            );
+        }
+
+        if !self.spikes.is_empty() {
+            println!("\nSuspicious dates:");
+            for spike in &self.spikes {
+                println!("  {}  {} files ({})", spike.date, spike.files, spike.reason);
+            }
+        }
+    }
+
+    /// Write the analysis as JSON, or as CSV (one row per month) for a `.csv` path
+    pub fn export(&self, path: &Path) -> std::io::Result<()> {
+        let is_csv = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+
+        let contents = if is_csv {
+            let mut csv = String::from("year,month,files,exif,fallback\n");
+            for month in &self.months {
+                csv.push_str(&format!(
+                    "{},{},{},{},{}\n",
+                    month.year,
+                    month.month,
+                    month.total(),
+                    month.exif,
+                    month.fallback
+                ));
+            }
+            csv
+        } else {
+            serde_json::to_string_pretty(self)?
+        };
+        fs::write(path, contents)
+    }
+}
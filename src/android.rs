@@ -0,0 +1,60 @@
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
+
+/// Folder below the destination `--android-trashed review` puts trashed files in
+pub const TRASH_REVIEW_DIR: &str = "Trash Review";
+
+/// What to do with the files Android keeps in its trash for 30 days
+/// (`.trashed-<expiry>-IMG_1234.jpg`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrashedFiles {
+    /// Leave them out like any other deleted file
+    #[default]
+    Ignore,
+    /// Organize them into a `Trash Review` folder to look through before they are gone
+    Review,
+}
+
+impl std::str::FromStr for TrashedFiles {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ignore" => Ok(TrashedFiles::Ignore),
+            "review" => Ok(TrashedFiles::Review),
+            _ => Err(format!(
+                "unknown handling '{}' for trashed files (expected ignore or review)",
+                s
+            )),
+        }
+    }
+}
+
+fn name_starts_with(path: &Path, prefix: &str) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with(prefix))
+}
+
+/// Whether the file is one Android's media store is still writing
+/// (`.pending-<expiry>-IMG_1234.jpg`), half a photo at best
+pub fn is_pending(path: &Path) -> bool {
+    name_starts_with(path, ".pending-")
+}
+
+/// Whether the file was deleted on the phone and sits in its trash
+pub fn is_trashed(path: &Path) -> bool {
+    name_starts_with(path, ".trashed-")
+}
+
+/// A trashed file's name as it was before it went to the trash: `IMG_1234.jpg` for
+/// `.trashed-1700000000-IMG_1234.jpg`. Other names are returned as they are.
+pub fn untrashed_name(name: &OsStr) -> OsString {
+    name.to_str()
+        .and_then(|name| name.strip_prefix(".trashed-"))
+        .and_then(|rest| rest.split_once('-'))
+        .filter(|(expiry, original)| {
+            expiry.bytes().all(|b| b.is_ascii_digit()) && !original.is_empty()
+        })
+        .map_or_else(|| name.to_os_string(), |(_, original)| original.into())
+}
@@ -0,0 +1,187 @@
+use rayon::prelude::*;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::Organizer;
+use crate::error::Result;
+use crate::exiftool;
+use crate::extract::{DateExtractor, EmbeddedExif, ExifTool};
+use crate::space::format_bytes;
+use crate::visited::OrganizedFolders;
+
+/// A way of reading capture dates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    /// One exiftool process per file, what a run falls back to
+    ExifTool,
+    /// One exiftool process for all the files, what `--metadata-json` dumps come from
+    ExifToolBatch,
+    /// The built-in EXIF parser, tried first on every file
+    Native,
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Engine::ExifTool => write!(f, "exiftool per file"),
+            Engine::ExifToolBatch => write!(f, "exiftool batch"),
+            Engine::Native => write!(f, "native parser"),
+        }
+    }
+}
+
+/// How one engine did on the sample
+#[derive(Debug, Clone)]
+pub struct EngineTiming {
+    pub engine: Engine,
+    /// Files it found a date in
+    pub dated: usize,
+    pub elapsed: Duration,
+}
+
+/// Timings of every engine over the same sample of files
+#[derive(Debug)]
+pub struct Benchmark {
+    pub source: PathBuf,
+    pub files: usize,
+    pub bytes: u64,
+    pub timings: Vec<EngineTiming>,
+}
+
+impl Organizer {
+    /// Read the dates of up to `sample` media files of the source with each engine
+    /// in turn, bypassing the metadata cache. Nothing is copied or moved.
+    pub fn bench(&self, sample: usize, terminate_flag: &AtomicBool) -> Result<Benchmark> {
+        let exiftool_path = exiftool::get_exiftool_path(self.exiftool_path.clone())?;
+        let files: Vec<PathBuf> = if self.source.is_dir() {
+            self.media_files(
+                &self.extractor_chain()?,
+                &OrganizedFolders::default(),
+                false,
+            )
+            .filter_map(|entry| entry.ok())
+            .map(ignore::DirEntry::into_path)
+            .take(sample)
+            .collect()
+        } else {
+            vec![self.source.clone()]
+        };
+        let bytes = files
+            .iter()
+            .filter_map(|file| std::fs::metadata(file).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+
+        let per_file = ExifTool {
+            path: exiftool_path.clone(),
+            cache: None,
+            quicktime_utc: self.quicktime_utc,
+        };
+        let mut timings = Vec::new();
+        for engine in [Engine::Native, Engine::ExifTool, Engine::ExifToolBatch] {
+            if terminate_flag.load(Ordering::SeqCst) {
+                break;
+            }
+            println!("[INFO] Timing {} on {} files...", engine, files.len());
+            let started = Instant::now();
+            let dated = match engine {
+                Engine::Native => time_extractor(&EmbeddedExif, &files, terminate_flag),
+                Engine::ExifTool => time_extractor(&per_file, &files, terminate_flag),
+                Engine::ExifToolBatch => {
+                    exiftool::extract_datetimes(&exiftool_path, &files, self.quicktime_utc)?.len()
+                }
+            };
+            timings.push(EngineTiming {
+                engine,
+                dated,
+                elapsed: started.elapsed(),
+            });
+        }
+
+        Ok(Benchmark {
+            source: self.source.clone(),
+            files: files.len(),
+            bytes,
+            timings,
+        })
+    }
+}
+
+/// Date the files on all cores, like a run does, returning how many had a date
+fn time_extractor(
+    extractor: &dyn DateExtractor,
+    files: &[PathBuf],
+    terminate_flag: &AtomicBool,
+) -> usize {
+    let dated = AtomicUsize::new(0);
+    files.par_iter().for_each(|file| {
+        if !terminate_flag.load(Ordering::SeqCst) && extractor.extract(file).is_ok() {
+            dated.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+    dated.into_inner()
+}
+
+impl EngineTiming {
+    pub fn files_per_second(&self, files: usize) -> f64 {
+        files as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl Benchmark {
+    /// Print a table of the timings and what they mean for organizing this library
+    pub fn print(&self) {
+        println!("\n=== EXTRACTION BENCHMARK ===");
+        println!(
+            "{} files ({}) from {}\n",
+            self.files,
+            format_bytes(self.bytes),
+            self.source.display()
+        );
+        println!(
+            "{:<18}  {:>11}  {:>9}  {:>9}",
+            "Engine", "Dated", "Time", "Files/s"
+        );
+        for timing in &self.timings {
+            println!(
+                "{:<18}  {:>11}  {:>8.2}s  {:>9.1}",
+                timing.engine.to_string(),
+                format!("{}/{}", timing.dated, self.files),
+                timing.elapsed.as_secs_f64(),
+                timing.files_per_second(self.files)
+            );
+        }
+
+        let timing = |engine| self.timings.iter().find(|t| t.engine == engine);
+        if let Some(native) = timing(Engine::Native) {
+            println!(
+                "\nThe native parser dates {} of these files by itself, the rest go to exiftool.",
+                native.dated
+            );
+        }
+        if let (Some(per_file), Some(batch)) =
+            (timing(Engine::ExifTool), timing(Engine::ExifToolBatch))
+            && batch.elapsed < per_file.elapsed
+        {
+            println!(
+                "exiftool batch is {:.1}x faster here. For a large library, dump the dates once and\n\
+                 organize from the dump:\n  exiftool -j -r {} > dates.json\n  timekeeper -s {} -d <destination> --metadata-json dates.json",
+                per_file.elapsed.as_secs_f64() / batch.elapsed.as_secs_f64().max(f64::EPSILON),
+                quoted(&self.source),
+                quoted(&self.source)
+            );
+        }
+    }
+}
+
+/// A path as it can be pasted into a shell
+fn quoted(path: &Path) -> String {
+    let text = path.display().to_string();
+    if text.contains(char::is_whitespace) {
+        format!("\"{}\"", text)
+    } else {
+        text
+    }
+}
@@ -0,0 +1,103 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::SystemTime;
+
+/// Extracted metadata for one file, valid while its size and mtime are unchanged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    size: u64,
+    modified: SystemTime,
+    /// `None` records that exiftool found no usable date, so it isn't asked again
+    datetime: Option<DateTime<Local>>,
+    /// Whether exiftool read the date's QuickTime tags as UTC
+    #[serde(default)]
+    quicktime_utc: bool,
+}
+
+/// On-disk cache of extracted metadata keyed by canonical path, size and mtime,
+/// so repeated runs over an unchanged library never call exiftool twice for a file.
+pub struct MetadataCache {
+    path: PathBuf,
+    entries: Mutex<HashMap<PathBuf, CacheEntry>>,
+    dirty: AtomicBool,
+}
+
+impl MetadataCache {
+    /// Default cache location inside the user's cache directory
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("timekeeper").join("metadata-cache.json"))
+    }
+
+    /// Load the cache from `path`. A missing or unreadable cache starts out empty,
+    /// it only ever costs extra exiftool calls.
+    pub fn load(path: PathBuf) -> Self {
+        let entries = fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+
+        MetadataCache {
+            path,
+            entries: Mutex::new(entries),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// Cached datetime for `file`, or `None` when the file is unknown or has changed.
+    /// `Some(None)` means the file is known to carry no date. Dates read with another
+    /// `quicktime_utc` setting don't count.
+    pub fn get(&self, file: &Path, quicktime_utc: bool) -> Option<Option<DateTime<Local>>> {
+        let (key, size, modified) = cache_key(file)?;
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(&key)?;
+
+        (entry.size == size && entry.modified == modified && entry.quicktime_utc == quicktime_utc)
+            .then_some(entry.datetime)
+    }
+
+    pub fn insert(&self, file: &Path, quicktime_utc: bool, datetime: Option<DateTime<Local>>) {
+        let Some((key, size, modified)) = cache_key(file) else {
+            return;
+        };
+
+        self.entries.lock().unwrap().insert(
+            key,
+            CacheEntry {
+                size,
+                modified,
+                datetime,
+                quicktime_utc,
+            },
+        );
+        self.dirty.store(true, Ordering::SeqCst);
+    }
+
+    /// Write the cache back to disk if anything changed, dropping entries for files
+    /// that no longer exist (moved or deleted). Written atomically via a temp file.
+    pub fn save(&self) -> std::io::Result<()> {
+        if !self.dirty.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|path, _| path.exists());
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec(&*entries)?)?;
+        fs::rename(&tmp_path, &self.path)
+    }
+}
+
+fn cache_key(file: &Path) -> Option<(PathBuf, u64, SystemTime)> {
+    let canonical = file.canonicalize().ok()?;
+    let metadata = fs::metadata(&canonical).ok()?;
+    Some((canonical, metadata.len(), metadata.modified().ok()?))
+}
@@ -0,0 +1,112 @@
+use chrono::TimeDelta;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use crate::extract::ShotInfo;
+
+/// How far a camera's clock was off, e.g. `-1h2m` for a body running an hour and two
+/// minutes fast. Units are `d`, `h`, `m` and `s`, with an optional sign up front.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ClockOffset(pub TimeDelta);
+
+impl FromStr for ClockOffset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid clock offset '{}' (expected e.g. -1h2m or 30s)", s);
+        let text = s.trim();
+        let (negative, mut rest) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        if rest.is_empty() {
+            return Err(invalid());
+        }
+
+        let mut seconds: i64 = 0;
+        while !rest.is_empty() {
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .ok_or_else(invalid)?;
+            let value: i64 = rest[..digits].parse().map_err(|_| invalid())?;
+            let unit = match rest[digits..].chars().next() {
+                Some('d') => 86_400,
+                Some('h') => 3_600,
+                Some('m') => 60,
+                Some('s') => 1,
+                _ => return Err(invalid()),
+            };
+            seconds = value
+                .checked_mul(unit)
+                .and_then(|part| seconds.checked_add(part))
+                .ok_or_else(invalid)?;
+            rest = &rest[digits + 1..];
+        }
+        let delta = TimeDelta::try_seconds(seconds).ok_or_else(invalid)?;
+        Ok(ClockOffset(if negative { -delta } else { delta }))
+    }
+}
+
+impl TryFrom<String> for ClockOffset {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Clock corrections for the cameras of a shoot, from the config's `[clock_offsets]`:
+///
+/// ```toml
+/// [clock_offsets]
+/// "NIKON D750:6012345" = "-1h2m"  # one body, by model and serial number
+/// "Canon EOS R5" = "+30s"         # every body of a model
+/// ```
+///
+/// A rule naming a serial number wins over one for the whole model.
+#[derive(Debug, Clone, Default)]
+pub struct ClockOffsets {
+    /// Lowercase model, serial number when the rule is for one body, offset
+    rules: Vec<(String, Option<String>, TimeDelta)>,
+}
+
+impl ClockOffsets {
+    pub fn new(offsets: &BTreeMap<String, ClockOffset>) -> Self {
+        let rules = offsets
+            .iter()
+            .map(|(camera, offset)| {
+                let (model, serial) = match camera.rsplit_once(':') {
+                    Some((model, serial)) => (model, Some(serial.trim().to_string())),
+                    None => (camera.as_str(), None),
+                };
+                (model.trim().to_lowercase(), serial, offset.0)
+            })
+            .collect();
+        ClockOffsets { rules }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The correction to add to dates from the camera that took a picture
+    pub fn offset_for(&self, shot: &ShotInfo) -> Option<TimeDelta> {
+        let camera = shot.model.as_deref()?.to_lowercase();
+        // Camera names get the make prepended when the model lacks it
+        // ("NIKON CORPORATION NIKON D750"), so a rule may name just the model
+        let same_model = |model: &str| {
+            model.is_empty() || camera == model || camera.ends_with(&format!(" {}", model))
+        };
+        let for_body = self.rules.iter().find(|(model, serial, _)| {
+            serial.is_some() && serial.as_deref() == shot.serial.as_deref() && same_model(model)
+        });
+        let for_model = || {
+            self.rules
+                .iter()
+                .find(|(model, serial, _)| serial.is_none() && same_model(model))
+        };
+        for_body.or_else(for_model).map(|(_, _, offset)| *offset)
+    }
+}
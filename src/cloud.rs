@@ -0,0 +1,74 @@
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use regex::Regex;
+use std::path::Path;
+
+lazy_static::lazy_static! {
+    // Dropbox camera uploads: 2020-01-31 12.34.56.jpg, 2020-01-31 12.34.56-1.jpg
+    static ref DROPBOX_UPLOAD: Regex =
+        Regex::new(r"^(\d{4})-(\d{2})-(\d{2}) (\d{2})\.(\d{2})\.(\d{2})").unwrap();
+    // OneDrive camera uploads from iPhones: 20200131_123456000_iOS.heic
+    static ref ONEDRIVE_UPLOAD: Regex =
+        Regex::new(r"^(\d{4})(\d{2})(\d{2})_(\d{2})(\d{2})(\d{2})(\d{3})_iOS").unwrap();
+}
+
+/// The capture date a Dropbox or OneDrive camera upload carries in its name. Both
+/// services name uploads after the capture time in UTC, and their copies often lost
+/// their EXIF on the way.
+pub fn camera_upload_datetime(path: &Path) -> Option<DateTime<Local>> {
+    let name = path.file_name()?.to_str()?;
+
+    let text = if let Some(caps) = DROPBOX_UPLOAD.captures(name) {
+        format!(
+            "{}-{}-{} {}:{}:{}",
+            &caps[1], &caps[2], &caps[3], &caps[4], &caps[5], &caps[6]
+        )
+    } else {
+        let caps = ONEDRIVE_UPLOAD.captures(name)?;
+        format!(
+            "{}-{}-{} {}:{}:{}.{}",
+            &caps[1], &caps[2], &caps[3], &caps[4], &caps[5], &caps[6], &caps[7]
+        )
+    };
+    let naive = NaiveDateTime::parse_from_str(&text, "%Y-%m-%d %H:%M:%S%.f").ok()?;
+
+    Some(Utc.from_utc_datetime(&naive).with_timezone(&Local))
+}
+
+/// Windows attributes of files whose contents stay in the cloud until opened
+#[cfg(windows)]
+const ONLINE_ONLY_ATTRIBUTES: u32 = 0x1000 // FILE_ATTRIBUTE_OFFLINE
+    | 0x4_0000 // FILE_ATTRIBUTE_RECALL_ON_OPEN
+    | 0x40_0000; // FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS
+
+/// `st_flags` bit of files iCloud Drive or the File Provider clients keep online only
+#[cfg(target_os = "macos")]
+const SF_DATALESS: u32 = 0x4000_0000;
+
+/// Whether the file is an online-only placeholder of a cloud sync client (OneDrive
+/// Files On-Demand, Dropbox online-only, iCloud Drive). Reading it downloads it, and
+/// moving it away breaks the client's sync state.
+#[cfg(windows)]
+pub fn is_placeholder(path: &Path) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    std::fs::symlink_metadata(path)
+        .is_ok_and(|metadata| metadata.file_attributes() & ONLINE_ONLY_ATTRIBUTES != 0)
+}
+
+#[cfg(target_os = "macos")]
+pub fn is_placeholder(path: &Path) -> bool {
+    use std::os::macos::fs::MetadataExt;
+
+    std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.st_flags() & SF_DATALESS != 0)
+}
+
+/// No sync client on other systems leaves placeholders the file system can tell apart
+#[cfg(not(any(windows, target_os = "macos")))]
+pub fn is_placeholder(_path: &Path) -> bool {
+    false
+}
+
+/// Download a placeholder's contents by reading it through once
+pub fn hydrate(path: &Path) -> std::io::Result<()> {
+    std::io::copy(&mut std::fs::File::open(path)?, &mut std::io::sink()).map(|_| ())
+}
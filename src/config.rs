@@ -0,0 +1,51 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::clock::ClockOffset;
+
+/// User settings read from `config.toml` in the user's config directory, e.g.
+///
+/// ```toml
+/// default_destination = "/home/me/Pictures/Library"
+///
+/// [clock_offsets]
+/// "NIKON D750:6012345" = "-1h2m"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Where imports go when no destination is given
+    pub default_destination: Option<PathBuf>,
+    /// Clock corrections per camera model, or model and serial number
+    #[serde(default)]
+    pub clock_offsets: BTreeMap<String, ClockOffset>,
+}
+
+impl Config {
+    /// Default config location inside the user's config directory
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("timekeeper").join("config.toml"))
+    }
+
+    /// Load the config from its default location. A missing file is an empty config,
+    /// a malformed one is an error so typos don't silently fall back to defaults.
+    pub fn load() -> io::Result<Self> {
+        let Some(path) = Self::default_path() else {
+            return Ok(Config::default());
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(e),
+        };
+        toml::from_str(&text).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })
+    }
+}
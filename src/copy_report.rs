@@ -0,0 +1,101 @@
+use chrono::Local;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::manifest::HashAlgorithm;
+
+/// Columns of every file line in the report
+const COLUMNS_HEADER: &str = "# status\thash\tsize\tsource\tdestination";
+
+/// Record of a verify-only copy: every file copied, its hash and both locations, for
+/// archival runs where the sources may be the only copy and must stay untouched.
+///
+/// The report ends with a digest of every line above it, so a later edit to the
+/// report shows when the digest is recomputed with any hashing tool.
+pub struct CopyReport {
+    path: PathBuf,
+    algorithm: HashAlgorithm,
+    entries: Mutex<Vec<CopyRecord>>,
+}
+
+struct CopyRecord {
+    /// The copy hashed the same as its source
+    verified: bool,
+    hash: String,
+    size: u64,
+    source: PathBuf,
+    dest: PathBuf,
+}
+
+impl CopyReport {
+    pub fn new(path: PathBuf, algorithm: HashAlgorithm) -> Self {
+        CopyReport {
+            path,
+            algorithm,
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Note a copy and whether it matched its source, `hash` being the source's
+    pub fn record(&self, source: &Path, dest: &Path, hash: String, size: u64, verified: bool) {
+        self.entries.lock().unwrap().push(CopyRecord {
+            verified,
+            hash,
+            size,
+            source: source.to_path_buf(),
+            dest: dest.to_path_buf(),
+        });
+    }
+
+    /// Write the report, sorted by source path so two runs over a tree compare cleanly
+    pub fn save(&self) -> io::Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.sort_by(|a, b| a.source.cmp(&b.source));
+
+        let mut out = format!(
+            "# timekeeper copy report ({})\n# written {}\n{}\n",
+            self.algorithm.name(),
+            Local::now().format("%Y-%m-%dT%H:%M:%S%:z"),
+            COLUMNS_HEADER
+        );
+        for entry in entries.iter() {
+            out.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                if entry.verified { "OK" } else { "MISMATCH" },
+                entry.hash,
+                entry.size,
+                entry.source.display(),
+                entry.dest.display()
+            ));
+        }
+        let verified = entries.iter().filter(|entry| entry.verified).count();
+        out.push_str(&format!(
+            "# {} files copied and verified, {} mismatched, no source was deleted\n",
+            verified,
+            entries.len() - verified
+        ));
+        let digest = self.algorithm.hash_bytes(out.as_bytes());
+        out.push_str(&format!(
+            "# signed-off {} {}\n",
+            self.algorithm.name(),
+            digest
+        ));
+
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, out)?;
+        fs::rename(&tmp_path, &self.path)
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::outcome::{Action, DateSource, FileOutcome};
+use crate::progress::ProgressObserver;
+use crate::space::format_bytes;
+use crate::stats::StatsSnapshot;
+
+/// Files and bytes a dry run would put into each destination folder, an overview of
+/// a first run on a big dump that tens of thousands of per-file lines aren't
+pub struct DryRunSummary {
+    /// Folders below it are shown relative to it
+    destination: Option<PathBuf>,
+    /// List every file under its folder rather than only the totals
+    list_files: bool,
+    folders: Mutex<BTreeMap<PathBuf, FolderTotals>>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct FolderTotals {
+    files: usize,
+    bytes: u64,
+    /// File name at the destination, source and whether the date is a fallback
+    incoming: Vec<(String, PathBuf, bool)>,
+}
+
+impl DryRunSummary {
+    pub fn new(destination: Option<PathBuf>) -> Self {
+        DryRunSummary {
+            destination,
+            list_files: false,
+            folders: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// List the files each folder would receive beneath it (see `--group-by-dest`)
+    pub fn with_files(mut self, list_files: bool) -> Self {
+        self.list_files = list_files;
+        self
+    }
+
+    /// Print the folders in order with their totals and start over for the next run
+    pub fn print(&self, stats: &StatsSnapshot) {
+        let folders = std::mem::take(&mut *self.folders.lock().unwrap());
+        if folders.is_empty() {
+            return;
+        }
+
+        println!("\n=== DESTINATION FOLDERS (dry run) ===");
+        if self.list_files {
+            self.print_files(&folders);
+        } else {
+            self.print_table(&folders);
+        }
+        let files: usize = folders.values().map(|totals| totals.files).sum();
+        let bytes: u64 = folders.values().map(|totals| totals.bytes).sum();
+        println!(
+            "{} files ({}) into {} folder{}, {} renamed to avoid a name clash",
+            files,
+            format_bytes(bytes),
+            folders.len(),
+            if folders.len() == 1 { "" } else { "s" },
+            stats.name_conflicts
+        );
+    }
+
+    fn print_table(&self, folders: &BTreeMap<PathBuf, FolderTotals>) {
+        let width = folders
+            .values()
+            .map(|totals| totals.files.to_string().len())
+            .max()
+            .unwrap_or(1)
+            .max("Files".len());
+        println!("{:>width$} {:>10}  Folder", "Files", "Size");
+        for (folder, totals) in folders {
+            println!(
+                "{:>width$} {:>10}  {}",
+                totals.files,
+                format_bytes(totals.bytes),
+                self.display_folder(folder).display(),
+            );
+        }
+    }
+
+    /// Each folder followed by its files sorted by name, marking the ones dated from
+    /// their file times
+    fn print_files(&self, folders: &BTreeMap<PathBuf, FolderTotals>) {
+        for (folder, totals) in folders {
+            println!(
+                "\n{}/  ({} file{}, {})",
+                self.display_folder(folder).display(),
+                totals.files,
+                if totals.files == 1 { "" } else { "s" },
+                format_bytes(totals.bytes)
+            );
+            let mut incoming: Vec<_> = totals.incoming.iter().collect();
+            incoming.sort();
+            for (name, source, fallback) in incoming {
+                println!(
+                    "    {}  <- {}{}",
+                    name,
+                    source.display(),
+                    if *fallback {
+                        "  (date from file time)"
+                    } else {
+                        ""
+                    }
+                );
+            }
+        }
+        println!();
+    }
+
+    fn display_folder<'a>(&self, folder: &'a Path) -> &'a Path {
+        self.destination
+            .as_deref()
+            .and_then(|destination| folder.strip_prefix(destination).ok())
+            .filter(|relative| !relative.as_os_str().is_empty())
+            .unwrap_or(folder)
+    }
+}
+
+impl ProgressObserver for DryRunSummary {
+    fn on_file_done(&self, outcome: &FileOutcome) {
+        if !matches!(outcome.action, Action::Copy | Action::Move) {
+            return;
+        }
+        let Some(destination) = outcome.destination.as_deref() else {
+            return;
+        };
+        let Some(folder) = destination.parent() else {
+            return;
+        };
+        let bytes = fs::metadata(&outcome.source).map_or(0, |metadata| metadata.len());
+        let mut folders = self.folders.lock().unwrap();
+        let totals = folders.entry(folder.to_path_buf()).or_default();
+        totals.files += 1;
+        totals.bytes += bytes;
+        if self.list_files {
+            totals.incoming.push((
+                destination
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                outcome.source.clone(),
+                outcome.date_source == Some(DateSource::Fallback),
+            ));
+        }
+    }
+}
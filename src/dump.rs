@@ -0,0 +1,69 @@
+use chrono::{DateTime, Local};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Result, TimekeeperError};
+use crate::exiftool::record_datetime;
+
+/// Dates imported from an `exiftool -j -r` dump, used instead of running exiftool per file
+pub struct MetadataDump {
+    dates: HashMap<PathBuf, DateTime<Local>>,
+}
+
+impl MetadataDump {
+    /// Load a JSON dump. Relative `SourceFile` paths are resolved against the current
+    /// directory, then `source_root`, then the dump's own directory, whichever exists.
+    /// Group-prefixed tags (`exiftool -G`, e.g. `EXIF:DateTimeOriginal`) are accepted.
+    pub fn load(path: &Path, source_root: &Path) -> Result<Self> {
+        let records: Vec<Map<String, Value>> =
+            serde_json::from_slice(&fs::read(path)?).map_err(|e| {
+                TimekeeperError::ParseError(format!(
+                    "Invalid exiftool JSON dump '{}': {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+
+        let dump_dir = path.parent().unwrap_or(Path::new("."));
+        let mut dates = HashMap::with_capacity(records.len());
+
+        for record in &records {
+            let Some(source_file) = record.get("SourceFile").and_then(Value::as_str) else {
+                continue;
+            };
+            let Some(datetime) = record_datetime(record) else {
+                continue;
+            };
+
+            let source_file = Path::new(source_file);
+            let resolved = if source_file.is_absolute() {
+                source_file.canonicalize().ok()
+            } else {
+                [Path::new("."), source_root, dump_dir]
+                    .iter()
+                    .find_map(|base| base.join(source_file).canonicalize().ok())
+            };
+            if let Some(resolved) = resolved {
+                dates.insert(resolved, datetime);
+            }
+        }
+
+        Ok(MetadataDump { dates })
+    }
+
+    /// Number of files with a usable date in the dump
+    pub fn len(&self) -> usize {
+        self.dates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dates.is_empty()
+    }
+
+    pub fn datetime(&self, file: &Path) -> Option<DateTime<Local>> {
+        let canonical = file.canonicalize().ok()?;
+        self.dates.get(&canonical).copied()
+    }
+}
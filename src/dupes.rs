@@ -0,0 +1,332 @@
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::manifest::HashAlgorithm;
+use crate::metadata;
+use crate::transfer::CopyWatch;
+use crate::visited::VisitedInodes;
+
+/// File in the review directory recording where every consolidated duplicate came from
+pub const MAPPING_FILE: &str = "duplicates.tsv";
+
+/// What to do with the duplicates that were found
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum DuplicateAction {
+    /// List them and leave everything in place
+    #[default]
+    Report,
+    /// Move every copy but the first of each group into a review directory
+    Consolidate(PathBuf),
+}
+
+impl std::str::FromStr for DuplicateAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s.eq_ignore_ascii_case("report") => Ok(DuplicateAction::Report),
+            Some((action, dir))
+                if action.eq_ignore_ascii_case("consolidate") && !dir.is_empty() =>
+            {
+                Ok(DuplicateAction::Consolidate(PathBuf::from(dir)))
+            }
+            _ => Err(format!(
+                "unknown duplicates action '{}' (expected report or consolidate:<dir>)",
+                s
+            )),
+        }
+    }
+}
+
+/// Files with identical contents
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateGroup {
+    pub hash: String,
+    /// Size of each copy in bytes
+    pub size: u64,
+    /// Sorted, the first one is the copy that is kept
+    pub paths: Vec<PathBuf>,
+}
+
+impl DuplicateGroup {
+    /// The copy kept when the group is consolidated
+    pub fn canonical(&self) -> &Path {
+        &self.paths[0]
+    }
+
+    /// Bytes freed by keeping a single copy
+    pub fn reclaimable(&self) -> u64 {
+        self.size * (self.paths.len() as u64 - 1)
+    }
+}
+
+/// Outcome of `find_duplicates`
+#[derive(Debug, Default)]
+pub struct DuplicateReport {
+    /// Files looked at across all roots
+    pub scanned: usize,
+    /// Largest reclaimable space first
+    pub groups: Vec<DuplicateGroup>,
+}
+
+/// What `DuplicateReport::export` writes as JSON
+#[derive(Serialize)]
+struct DuplicateExport<'a> {
+    scanned: usize,
+    duplicates: usize,
+    reclaimable: u64,
+    groups: &'a [DuplicateGroup],
+}
+
+impl DuplicateReport {
+    pub fn reclaimable(&self) -> u64 {
+        self.groups.iter().map(DuplicateGroup::reclaimable).sum()
+    }
+
+    /// Copies beyond the first of each group, the ones that could go
+    pub fn duplicates(&self) -> usize {
+        self.groups.iter().map(|group| group.paths.len() - 1).sum()
+    }
+
+    /// Write the report as JSON, or as CSV (one row per group) for a `.csv` path
+    pub fn export(&self, path: &Path) -> io::Result<()> {
+        let contents = if is_csv(path) {
+            let mut csv = String::from("hash,size,copies,duplicates,reclaimable,kept\n");
+            for group in &self.groups {
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{}\n",
+                    group.hash,
+                    group.size,
+                    group.paths.len(),
+                    group.paths.len() - 1,
+                    group.reclaimable(),
+                    csv_field(&group.canonical().to_string_lossy())
+                ));
+            }
+            csv
+        } else {
+            serde_json::to_string_pretty(&DuplicateExport {
+                scanned: self.scanned,
+                duplicates: self.duplicates(),
+                reclaimable: self.reclaimable(),
+                groups: &self.groups,
+            })?
+        };
+        fs::write(path, contents)
+    }
+}
+
+pub(crate) fn is_csv(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+}
+
+/// A CSV field, quoted when it holds a comma, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Find files with the same contents below `roots`, leaving out the folders in
+/// `exclude`. Files are grouped by size first, so only files sharing a size with
+/// another one are hashed. Paths to the same physical file (overlapping roots, bind
+/// mounts, hard links) are counted once. Nothing is modified.
+pub fn find_duplicates(
+    roots: &[PathBuf],
+    exclude: &[PathBuf],
+    algorithm: HashAlgorithm,
+    terminate_flag: &AtomicBool,
+) -> DuplicateReport {
+    let mut seen = HashSet::new();
+    let visited = VisitedInodes::default();
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    let excluded: Vec<PathBuf> = exclude
+        .iter()
+        .map(|dir| dir.canonicalize().unwrap_or_else(|_| dir.clone()))
+        .collect();
+
+    for root in roots {
+        for entry in ignore::WalkBuilder::new(root)
+            .standard_filters(false)
+            .filter_entry({
+                let excluded = excluded.clone();
+                move |entry| {
+                    !entry.file_type().is_some_and(|t| t.is_dir())
+                        || excluded.is_empty()
+                        || !entry
+                            .path()
+                            .canonicalize()
+                            .is_ok_and(|dir| excluded.contains(&dir))
+                }
+            })
+            .build()
+        {
+            if terminate_flag.load(Ordering::SeqCst) {
+                break;
+            }
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    eprintln!("[WARN] {}", e);
+                    continue;
+                }
+            };
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+            let path = entry.into_path();
+            let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+            // Bind mounts show one file under two canonical paths
+            if !seen.insert(canonical) || !visited.first_visit(&path) {
+                continue;
+            }
+            match std::fs::metadata(&path) {
+                // Empty files are all alike, that's not worth reporting
+                Ok(metadata) if metadata.len() > 0 => {
+                    by_size.entry(metadata.len()).or_default().push(path)
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("[WARN] Cannot read {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    let candidates: Vec<(u64, PathBuf)> = by_size
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .flat_map(|(size, paths)| paths.into_iter().map(move |path| (size, path)))
+        .collect();
+
+    let hashed: Vec<(u64, String, PathBuf)> = candidates
+        .into_par_iter()
+        .filter_map(|(size, path)| {
+            if terminate_flag.load(Ordering::SeqCst) {
+                return None;
+            }
+            match algorithm.hash_file(&path) {
+                Ok(hash) => Some((size, hash, path)),
+                Err(e) => {
+                    eprintln!("[WARN] Cannot hash {}: {}", path.display(), e);
+                    None
+                }
+            }
+        })
+        .collect();
+
+    let mut by_hash: HashMap<(u64, String), Vec<PathBuf>> = HashMap::new();
+    for (size, hash, path) in hashed {
+        by_hash.entry((size, hash)).or_default().push(path);
+    }
+
+    let mut groups: Vec<DuplicateGroup> = by_hash
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|((size, hash), mut paths)| {
+            paths.sort();
+            DuplicateGroup { hash, size, paths }
+        })
+        .collect();
+    groups.sort_by(|a, b| {
+        b.reclaimable()
+            .cmp(&a.reclaimable())
+            .then_with(|| a.paths.cmp(&b.paths))
+    });
+
+    DuplicateReport {
+        scanned: seen.len(),
+        groups,
+    }
+}
+
+/// Outcome of `consolidate`
+#[derive(Debug, Default)]
+pub struct Consolidation {
+    pub moved: usize,
+    /// Copies left where they were, because they couldn't be moved
+    pub failed: usize,
+}
+
+/// Move every copy but the canonical one of each group below `review_dir`, keeping
+/// its path relative to the root it was found in (prefixed with the root's name when
+/// there are several roots). Each move is appended to `review_dir/duplicates.tsv`,
+/// so the copies can be checked and put back before anything is deleted.
+pub fn consolidate(
+    report: &DuplicateReport,
+    roots: &[PathBuf],
+    review_dir: &Path,
+    terminate_flag: &AtomicBool,
+) -> io::Result<Consolidation> {
+    fs::create_dir_all(review_dir)?;
+    let mapping_path = review_dir.join(MAPPING_FILE);
+    let new_mapping = !mapping_path.exists();
+    let mut mapping = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&mapping_path)?;
+    if new_mapping {
+        writeln!(mapping, "# hash\tmoved to\toriginal path\tkept copy")?;
+    }
+
+    let mut outcome = Consolidation::default();
+    for group in &report.groups {
+        for duplicate in &group.paths[1..] {
+            if terminate_flag.load(Ordering::SeqCst) {
+                return Ok(outcome);
+            }
+            let target = review_dir.join(review_path(duplicate, roots));
+            match move_duplicate(duplicate, &target) {
+                Ok(()) => {
+                    writeln!(
+                        mapping,
+                        "{}\t{}\t{}\t{}",
+                        group.hash,
+                        target.display(),
+                        duplicate.display(),
+                        group.canonical().display()
+                    )?;
+                    outcome.moved += 1;
+                }
+                Err(e) => {
+                    eprintln!("[WARN] Cannot move {}: {}", duplicate.display(), e);
+                    outcome.failed += 1;
+                }
+            }
+        }
+    }
+    mapping.flush()?;
+    Ok(outcome)
+}
+
+/// Where a duplicate goes below the review directory
+fn review_path(path: &Path, roots: &[PathBuf]) -> PathBuf {
+    let Some(root) = roots.iter().find(|root| path.starts_with(root)) else {
+        return PathBuf::from(path.file_name().unwrap_or(path.as_os_str()));
+    };
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    match root.file_name() {
+        Some(name) if roots.len() > 1 => Path::new(name).join(relative),
+        _ => relative.to_path_buf(),
+    }
+}
+
+fn move_duplicate(source: &Path, target: &Path) -> io::Result<()> {
+    // An earlier consolidation may have left a file there, never replace it
+    if target.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", target.display()),
+        ));
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    metadata::move_file_cross_platform(source, target, false, &CopyWatch::default())
+}
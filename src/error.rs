@@ -0,0 +1,137 @@
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+use crate::exiftool::{DateError, ExifToolError, FailureKind};
+use crate::space::format_bytes;
+
+/// Everything that can go wrong while organizing. Callers match on the variant
+/// (for exit codes or reports) instead of parsing messages.
+#[derive(Debug, Error)]
+pub enum TimekeeperError {
+    /// No working ExifTool could be found or set up
+    #[error(transparent)]
+    ExiftoolMissing(#[from] ExifToolError),
+
+    /// ExifTool ran but couldn't read the file's metadata
+    #[error("Could not read the date of {path}: {error}")]
+    ExtractionFailed {
+        path: PathBuf,
+        #[source]
+        error: DateError,
+    },
+
+    /// Copying, moving or uploading a file to its destination failed
+    #[error("Could not place {source} at {dest}: {error}")]
+    MoveFailed {
+        source: PathBuf,
+        dest: PathBuf,
+        #[source]
+        error: io::Error,
+    },
+
+    /// A written file doesn't match its original
+    #[error("{copy} does not match the original {original}")]
+    VerifyFailed { original: PathBuf, copy: PathBuf },
+
+    /// The destination has no room for what is about to be written
+    #[error(
+        "Not enough free space at '{path}': {} needed, {} available",
+        format_bytes(*.required),
+        format_bytes(*.available)
+    )]
+    OutOfSpace {
+        path: PathBuf,
+        required: u64,
+        available: u64,
+    },
+
+    /// An `--exec-before` or `--exec-after` command failed
+    #[error("Command `{command}` failed: {detail}")]
+    HookFailed { command: String, detail: String },
+
+    /// A path that has no file name to place the file under
+    #[error("Invalid file name: {0}")]
+    InvalidPath(PathBuf),
+
+    /// Malformed input such as a metadata dump or a manifest
+    #[error("{0}")]
+    ParseError(String),
+
+    /// The source couldn't be walked
+    #[error(transparent)]
+    Walk(#[from] ignore::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+pub type Result<T, E = TimekeeperError> = std::result::Result<T, E>;
+
+/// What a failure is most likely down to, so a run's errors can be told apart as
+/// exiftool's, the disk's, a permission problem or a slow network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// exiftool missing, broken or unable to read a file's metadata
+    Metadata,
+    /// Reading, writing, moving or uploading a file
+    Io,
+    PermissionDenied,
+    /// A remote destination or another service that stopped answering
+    Timeout,
+    Other,
+}
+
+impl ErrorCategory {
+    /// The category of a failed file operation
+    pub fn of_io(error: &io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => ErrorCategory::PermissionDenied,
+            io::ErrorKind::TimedOut => ErrorCategory::Timeout,
+            // Network libraries mostly surface timeouts as their own errors
+            _ if is_timeout_message(&error.to_string()) => ErrorCategory::Timeout,
+            _ => ErrorCategory::Io,
+        }
+    }
+}
+
+fn is_timeout_message(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("timed out") || message.contains("timeout")
+}
+
+impl TimekeeperError {
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            TimekeeperError::ExtractionFailed {
+                error:
+                    DateError::Failed {
+                        kind: FailureKind::Unreadable,
+                        detail,
+                    },
+                ..
+            } if detail.to_lowercase().contains("permission denied") => {
+                ErrorCategory::PermissionDenied
+            }
+            TimekeeperError::ExiftoolMissing(_) | TimekeeperError::ExtractionFailed { .. } => {
+                ErrorCategory::Metadata
+            }
+            TimekeeperError::MoveFailed { error, .. } | TimekeeperError::Io(error) => {
+                ErrorCategory::of_io(error)
+            }
+            TimekeeperError::Walk(error) => match error.io_error() {
+                Some(error) => ErrorCategory::of_io(error),
+                None => ErrorCategory::Io,
+            },
+            TimekeeperError::VerifyFailed { .. } | TimekeeperError::OutOfSpace { .. } => {
+                ErrorCategory::Io
+            }
+            TimekeeperError::HookFailed { detail, .. } if is_timeout_message(detail) => {
+                ErrorCategory::Timeout
+            }
+            TimekeeperError::HookFailed { .. }
+            | TimekeeperError::InvalidPath(_)
+            | TimekeeperError::ParseError(_) => ErrorCategory::Other,
+        }
+    }
+}
@@ -1,5 +1,4 @@
 use std::path::{Path, PathBuf};
-use std::process::Command;
 
 // Conditional imports for bundled ExifTool on Windows
 #[cfg(all(windows, feature = "bundled"))]
@@ -30,43 +29,25 @@ fn exiftool_command(path: &Path) -> std::process::Command {
 
 // Error Type
 /// Typed error for ExifTool resolution failures.
-/// This replaces all `Box<dyn Error>` usage in the resolution layer.
 /// Callers (CLI, Tauri) decide how to surface these errors to users.
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum ExifToolError {
     /// No valid ExifTool binary found in any resolution source.
     /// Contains platform-specific installation instructions.
+    #[error("ExifTool not found. Install it with:\n{instructions}")]
     NotFound { instructions: &'static str },
     /// Bundled ExifTool extraction failed (I/O error or missing embedded asset).
+    #[error("Failed to extract bundled ExifTool: {0}")]
     ExtractionFailed(String),
     /// A candidate binary exists but failed the `exiftool -ver` validation check.
+    #[error("ExifTool validation failed: {0}")]
     ValidationFailed(String),
     /// The user-supplied path was explicitly provided but is invalid.
     /// This is a hard failure — no fallthrough to other sources.
+    #[error("User-specified ExifTool path is invalid: {0}")]
     UserPathInvalid(String),
 }
 
-impl std::fmt::Display for ExifToolError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ExifToolError::NotFound { instructions } => {
-                write!(f, "ExifTool not found. Install it with:\n{}", instructions)
-            }
-            ExifToolError::ExtractionFailed(msg) => {
-                write!(f, "Failed to extract bundled ExifTool: {}", msg)
-            }
-            ExifToolError::ValidationFailed(msg) => {
-                write!(f, "ExifTool validation failed: {}", msg)
-            }
-            ExifToolError::UserPathInvalid(msg) => {
-                write!(f, "User-specified ExifTool path is invalid: {}", msg)
-            }
-        }
-    }
-}
-
-impl std::error::Error for ExifToolError {}
-
 // Public API
 
 /// Resolve and validate a path to a working ExifTool binary.
@@ -74,6 +55,7 @@ impl std::error::Error for ExifToolError {}
 /// 1. **User path** (`Some(path)`) — validated, hard failure if invalid
 /// 2. **Bundled binary** (Windows + `bundled` feature) — extracted next to exe, hard failure if broken
 /// 3. **System PATH** — `which::which("exiftool")`, final fallback
+///
 /// Returns the first validated `PathBuf` or a typed error.
 /// This function is deterministic for identical inputs.
 pub fn get_exiftool_path(user_path: Option<PathBuf>) -> Result<PathBuf, ExifToolError> {
@@ -108,18 +90,12 @@ pub fn get_exiftool_path(user_path: Option<PathBuf>) -> Result<PathBuf, ExifTool
 /// Runs `exiftool -ver` and checks:
 /// - Process exits successfully (exit code 0)
 /// - stdout is non-empty (contains version string)
+///
 /// Does NOT mutate state. Does NOT log.
 fn validate_exiftool(path: &Path) -> Result<(), ExifToolError> {
-    let output = exiftool_command(path)
-        .arg("-ver")
-        .output()
-        .map_err(|e| {
-            ExifToolError::ValidationFailed(format!(
-                "Failed to execute '{}': {}",
-                path.display(),
-                e
-            ))
-        })?;
+    let output = exiftool_command(path).arg("-ver").output().map_err(|e| {
+        ExifToolError::ValidationFailed(format!("Failed to execute '{}': {}", path.display(), e))
+    })?;
 
     if !output.status.success() {
         return Err(ExifToolError::ValidationFailed(format!(
@@ -316,61 +292,356 @@ fn extract_dir_recursive(dir: &include_dir::Dir, dest_path: &PathBuf) -> Result<
 }
 
 // Metadata Extraction
-pub fn extract_datetime(
-    exiftool_path: &PathBuf,
-    file_path: &PathBuf,
-) -> Result<chrono::DateTime<chrono::Local>, Box<dyn std::error::Error>> {
-    let date_fields = [
-        "DateTimeOriginal",
-        "CreateDate",
-        "DateTime",
-        "FileModifyDate",
-    ];
+/// Date tags tried in order, most trustworthy first
+pub const DATE_FIELDS: [&str; 4] = [
+    "DateTimeOriginal",
+    "CreateDate",
+    "DateTime",
+    "FileModifyDate",
+];
+
+/// Fractions of a second kept apart from the date tags, which only go to the second.
+/// They tell apart and order burst shots taken within the same second.
+pub const SUBSEC_FIELDS: [(&str, &str); 3] = [
+    ("DateTimeOriginal", "SubSecTimeOriginal"),
+    ("CreateDate", "SubSecTimeDigitized"),
+    ("DateTime", "SubSecTime"),
+];
+
+/// Why no capture date could be read from a file
+#[derive(Debug, thiserror::Error)]
+pub enum DateError {
+    /// The file was read but carries none of the `DATE_FIELDS`
+    #[error("No valid date found in EXIF data")]
+    NoDate,
+    /// exiftool could not read the file, `detail` is what it reported
+    #[error("{}: {detail}", .kind.description())]
+    Failed { kind: FailureKind, detail: String },
+}
+
+/// Broad cause of a failed extraction, classified from exiftool's error output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    /// exiftool could not be started at all
+    Launch,
+    /// exiftool itself is broken, e.g. a missing Perl module
+    ExifTool,
+    /// The file could not be opened
+    Unreadable,
+    /// The file is damaged, truncated or empty
+    Corrupt,
+    /// exiftool doesn't know the file's format
+    Unsupported,
+    Other,
+}
+
+impl FailureKind {
+    fn classify(message: &str) -> Self {
+        let message = message.to_lowercase();
+        let mentions = |needles: &[&str]| needles.iter().any(|needle| message.contains(needle));
+
+        if mentions(&["can't locate", "perl", "compilation failed"]) {
+            FailureKind::ExifTool
+        } else if mentions(&["file not found", "permission denied", "error opening"]) {
+            FailureKind::Unreadable
+        } else if mentions(&["unknown file type", "unsupported", "not supported"]) {
+            FailureKind::Unsupported
+        } else if mentions(&[
+            "corrupt",
+            "format error",
+            "truncated",
+            "file is empty",
+            "error reading",
+        ]) {
+            FailureKind::Corrupt
+        } else {
+            FailureKind::Other
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            FailureKind::Launch => "could not run exiftool",
+            FailureKind::ExifTool => "exiftool installation is broken",
+            FailureKind::Unreadable => "file is not readable",
+            FailureKind::Corrupt => "file is corrupt",
+            FailureKind::Unsupported => "file format is not supported",
+            FailureKind::Other => "exiftool failed",
+        }
+    }
+}
+
+/// Edits the metadata of organized files in place with exiftool:
+///
+/// - dates found outside a file, in its name or a sidecar, are written into its EXIF
+///   so other tools and later runs see them. Videos are left alone, RAW files too
+///   unless asked for: their formats are proprietary and rewriting them is riskier.
+/// - GPS tags are stripped from every file, for trees meant for sharing
+#[derive(Debug, Clone)]
+pub struct ExifWriter {
+    exiftool_path: PathBuf,
+    write_dates: bool,
+    include_raw: bool,
+    strip_gps: bool,
+}
+
+impl ExifWriter {
+    /// A writer that changes nothing until told what to write
+    pub fn new(exiftool_path: PathBuf) -> Self {
+        Self {
+            exiftool_path,
+            write_dates: false,
+            include_raw: false,
+            strip_gps: false,
+        }
+    }
 
-    for field in &date_fields {
-        let output = exiftool_command(exiftool_path)
-            .args(["-s", "-s", "-s", &format!("-{}", field)])
-            .arg(file_path)
-            .output()?; // already handled errors
+    /// Write dates handed to `edit` as `DateTimeOriginal`, into RAW files too with `include_raw`
+    pub fn with_dates(mut self, include_raw: bool) -> Self {
+        self.write_dates = true;
+        self.include_raw = include_raw;
+        self
+    }
 
-        let date_str = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    pub fn with_strip_gps(mut self, strip_gps: bool) -> Self {
+        self.strip_gps = strip_gps;
+        self
+    }
 
-        if !date_str.is_empty() {
-            if let Ok(datetime) = parse_exif_date(&date_str) {
-                return Ok(datetime);
+    /// Edit a placed file in a single exiftool run, writing `date` into it when given
+    /// and its format allows. The file's modification time is kept.
+    pub fn edit(
+        &self,
+        path: &Path,
+        date: Option<&chrono::DateTime<chrono::Local>>,
+    ) -> Result<(), String> {
+        let mut args = Vec::new();
+        if let Some(datetime) = date.filter(|_| self.writes_dates_into(path)) {
+            args.push(format!(
+                "-DateTimeOriginal={}",
+                datetime.format("%Y:%m:%d %H:%M:%S")
+            ));
+            args.push(format!("-OffsetTimeOriginal={}", datetime.format("%:z")));
+        }
+        if self.strip_gps {
+            args.push("-gps:all=".to_string());
+            args.push("-xmp-exif:gps*=".to_string());
+            // Phones keep a video's location in QuickTime tags rather than a GPS group
+            if crate::metadata::is_video_file(path) {
+                args.push("-Keys:GPSCoordinates=".to_string());
+                args.push("-UserData:GPSCoordinates=".to_string());
             }
         }
+        if args.is_empty() {
+            return Ok(());
+        }
+
+        let output = exiftool_command(&self.exiftool_path)
+            .arg("-overwrite_original")
+            .arg("-P")
+            .args(&args)
+            .arg(path)
+            .output()
+            .map_err(|e| e.to_string())?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            Err(stderr
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .map(|line| line.trim_start_matches("Error: ").to_string())
+                .unwrap_or_else(|| format!("exiftool exited with {}", output.status)))
+        }
+    }
+
+    fn writes_dates_into(&self, path: &Path) -> bool {
+        self.write_dates
+            && !crate::metadata::is_video_file(path)
+            && (self.include_raw || !crate::metadata::is_raw_file(path))
+    }
+}
+
+/// Read every candidate date tag with a single exiftool run and pick the most
+/// trustworthy one that parses. When there is none, exiftool's own error (from the
+/// JSON record or stderr) tells a file without dates apart from an unreadable one.
+///
+/// MP4 and MOV files store their dates in UTC as the QuickTime spec asks, but
+/// exiftool prints them as they are. With `quicktime_utc` it converts those of
+/// video files to local time, so evening videos don't land on the next day.
+pub fn extract_datetime(
+    exiftool_path: &Path,
+    file_path: &Path,
+    quicktime_utc: bool,
+) -> Result<chrono::DateTime<chrono::Local>, DateError> {
+    let mut command = exiftool_command(exiftool_path);
+    if quicktime_utc && crate::metadata::is_video_file(file_path) {
+        command.args(["-api", "QuickTimeUTC=1"]);
+    }
+    let output = command
+        .arg("-j")
+        .args(DATE_FIELDS.iter().map(|field| format!("-{}", field)))
+        .args(SUBSEC_FIELDS.iter().map(|(_, field)| format!("-{}", field)))
+        .arg(file_path)
+        .output()
+        .map_err(|e| DateError::Failed {
+            kind: FailureKind::Launch,
+            detail: e.to_string(),
+        })?;
+
+    // No tags at all gives empty output rather than an empty array
+    let records: Vec<serde_json::Map<String, serde_json::Value>> =
+        serde_json::from_slice(&output.stdout).unwrap_or_default();
+    if let Some(datetime) = records.iter().find_map(record_datetime) {
+        return Ok(datetime);
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let reported = records
+        .iter()
+        .find_map(|record| record.get("Error")?.as_str().map(str::to_string))
+        .or_else(|| {
+            stderr
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty() && !line.starts_with("Warning"))
+                .map(|line| line.trim_start_matches("Error: ").to_string())
+        });
+
+    match reported {
+        Some(detail) => Err(DateError::Failed {
+            kind: FailureKind::classify(&detail),
+            detail,
+        }),
+        None if !output.status.success() => Err(DateError::Failed {
+            kind: FailureKind::Other,
+            detail: format!("exiftool exited with {}", output.status),
+        }),
+        None => Err(DateError::NoDate),
+    }
+}
+
+/// Dates of many files from a single exiftool process, keyed by the paths given.
+/// The file list goes through stdin (`-@ -`) so it is not bound by the command
+/// line's length. Files without a date are left out.
+pub fn extract_datetimes(
+    exiftool_path: &Path,
+    files: &[PathBuf],
+    quicktime_utc: bool,
+) -> std::io::Result<std::collections::HashMap<PathBuf, chrono::DateTime<chrono::Local>>> {
+    use std::io::Write;
+
+    let mut command = exiftool_command(exiftool_path);
+    if quicktime_utc {
+        command.args(["-api", "QuickTimeUTC=1"]);
     }
+    let mut child = command
+        .arg("-j")
+        .args(DATE_FIELDS.iter().map(|field| format!("-{}", field)))
+        .args(SUBSEC_FIELDS.iter().map(|(_, field)| format!("-{}", field)))
+        .args(["-@", "-"])
+        .stdin(std::process::Stdio::piped())
+        .spawn()?;
+
+    // Written from another thread so a full stdout pipe can't stall exiftool
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let list: String = files
+        .iter()
+        .map(|file| format!("{}\n", file.display()))
+        .collect();
+    let writer = std::thread::spawn(move || stdin.write_all(list.as_bytes()));
+    let output = child.wait_with_output()?;
+    writer.join().expect("writer thread panicked")?;
+
+    let records: Vec<serde_json::Map<String, serde_json::Value>> =
+        serde_json::from_slice(&output.stdout).unwrap_or_default();
+    Ok(records
+        .iter()
+        .filter_map(|record| {
+            let source_file = record.get("SourceFile")?.as_str()?;
+            Some((PathBuf::from(source_file), record_datetime(record)?))
+        })
+        .collect())
+}
 
-    Err("No valid date found in EXIF data".into())
+/// First parseable date in an `exiftool -j` record, following `DATE_FIELDS` priority,
+/// with its fraction of a second from `SUBSEC_FIELDS`. Group-prefixed tags
+/// (`exiftool -G`, e.g. `EXIF:DateTimeOriginal`) are accepted.
+pub(crate) fn record_datetime(
+    record: &serde_json::Map<String, serde_json::Value>,
+) -> Option<chrono::DateTime<chrono::Local>> {
+    let tag = |field: &'static str| {
+        record
+            .iter()
+            .filter(move |(key, _)| key.rsplit(':').next() == Some(field))
+            .map(|(_, value)| value)
+    };
+    DATE_FIELDS.iter().find_map(|field| {
+        let datetime = tag(field).find_map(|value| parse_exif_date(value.as_str()?))?;
+        let subsec = SUBSEC_FIELDS
+            .iter()
+            .find(|(date_field, _)| date_field == field)
+            .and_then(|(_, subsec_field)| {
+                // Usually a string, but exiftool prints all-digit values as numbers
+                tag(subsec_field).find_map(|value| match value {
+                    serde_json::Value::String(text) => Some(text.clone()),
+                    serde_json::Value::Number(number) => Some(number.to_string()),
+                    _ => None,
+                })
+            });
+        Some(match subsec {
+            Some(subsec) => with_subsec(datetime, &subsec),
+            None => datetime,
+        })
+    })
+}
+
+/// Add a `SubSecTime*` value, the digits after the decimal point (`"045"` is 45 ms),
+/// to a date that doesn't carry a fraction of a second already
+pub(crate) fn with_subsec(
+    datetime: chrono::DateTime<chrono::Local>,
+    subsec: &str,
+) -> chrono::DateTime<chrono::Local> {
+    use chrono::Timelike;
+
+    let digits = subsec.trim();
+    if datetime.nanosecond() != 0
+        || digits.is_empty()
+        || !digits.bytes().all(|b| b.is_ascii_digit())
+    {
+        return datetime;
+    }
+    let nanos = format!("{:0<9}", &digits[..digits.len().min(9)]);
+    nanos
+        .parse()
+        .ok()
+        .and_then(|nanos| datetime.with_nanosecond(nanos))
+        .unwrap_or(datetime)
 }
 
-fn parse_exif_date(
-    date_str: &str,
-) -> Result<chrono::DateTime<chrono::Local>, Box<dyn std::error::Error>> {
-    use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+pub(crate) fn parse_exif_date(date_str: &str) -> Option<chrono::DateTime<chrono::Local>> {
+    use chrono::{DateTime, Local, NaiveDateTime};
 
+    // `%.f` also takes the fraction of a second some cameras write into the date
     let formats = [
-        "%Y:%m:%d %H:%M:%S",
-        "%Y:%m:%d %H:%M:%S%z",
-        "%Y:%m:%d %H:%M:%SZ",
-        "%Y-%m-%d %H:%M:%S",
-        "%Y-%m-%dT%H:%M:%S",
-        "%Y-%m-%dT%H:%M:%SZ",
-        "%Y-%m-%dT%H:%M:%S%z",
+        "%Y:%m:%d %H:%M:%S%.f",
+        "%Y:%m:%d %H:%M:%S%.f%z",
+        "%Y:%m:%d %H:%M:%S%.fZ",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S%.fZ",
+        "%Y-%m-%dT%H:%M:%S%.f%z",
     ];
 
     for format in &formats {
         if let Ok(dt) = DateTime::parse_from_str(date_str, format) {
-            return Ok(dt.with_timezone(&Local));
+            return Some(dt.with_timezone(&Local));
         }
         if let Ok(naive_dt) = NaiveDateTime::parse_from_str(date_str, format) {
-            return Ok(Local
-                .from_local_datetime(&naive_dt)
-                .single()
-                .unwrap_or_else(|| Local.from_utc_datetime(&naive_dt)));
+            return Some(crate::localtime::resolve(&naive_dt));
         }
     }
 
-    Err("Invalid date format".into())
+    None
 }
@@ -0,0 +1,575 @@
+use chrono::{DateTime, Local};
+use regex::Regex;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::cache::MetadataCache;
+use crate::clock::ClockOffsets;
+use crate::cloud;
+use crate::dump::MetadataDump;
+use crate::exiftool::{self, DateError, FailureKind, parse_exif_date};
+use crate::localtime::{self, DstAdjustment};
+use crate::messaging;
+use crate::motion;
+use crate::outcome::DateSource;
+use crate::photos;
+use crate::xmp;
+
+lazy_static::lazy_static! {
+    /// XMP date properties in priority order, written as attributes or as elements
+    static ref XMP_DATES: [Regex; 3] = ["exif:DateTimeOriginal", "xmp:CreateDate", "photoshop:DateCreated"]
+        .map(|property| {
+            Regex::new(&format!(
+                r#"{}(?:="|>)(\d{{4}}-\d{{2}}-\d{{2}}T\d{{2}}:\d{{2}}(?::\d{{2}})?)(?:\.\d+)?(Z|[+-]\d{{2}}:?\d{{2}})?"#,
+                property
+            ))
+            .unwrap()
+        });
+}
+
+/// One way of finding a file's capture date. Extractors are tried in order by an
+/// `ExtractorChain` until one finds a date.
+pub trait DateExtractor: Send + Sync {
+    /// Short name used in messages
+    fn name(&self) -> &str;
+
+    /// Whether a date from this extractor is real metadata or a guess
+    fn date_source(&self) -> DateSource {
+        DateSource::Metadata
+    }
+
+    /// Whether the date is a trustworthy one found outside the file's own metadata,
+    /// which `--write-exif` writes into the organized file
+    fn write_back(&self) -> bool {
+        false
+    }
+
+    /// Whether a metadata date comes from the camera's own clock, which
+    /// `[clock_offsets]` corrects
+    fn camera_clock(&self) -> bool {
+        true
+    }
+
+    /// `Err(DateError::NoDate)` passes the file on to the next extractor. So does
+    /// `DateError::Failed`, but the failure is kept and reported if only a guess is found.
+    fn extract(&self, path: &Path) -> Result<DateTime<Local>, DateError>;
+}
+
+/// A date found by an `ExtractorChain`
+pub struct ExtractedDate {
+    pub datetime: DateTime<Local>,
+    pub date_source: DateSource,
+    /// Why an earlier extractor couldn't read the file, if one failed
+    pub failure: Option<DateError>,
+    /// See `DateExtractor::write_back`
+    pub write_back: bool,
+    /// How the date was settled when it fell on a daylight saving change
+    pub dst_adjustment: Option<DstAdjustment>,
+}
+
+/// Date extractors in priority order
+#[derive(Clone, Default)]
+pub struct ExtractorChain {
+    extractors: Vec<Arc<dyn DateExtractor>>,
+    /// Corrections for cameras whose clock was off, applied to metadata dates
+    clock_offsets: Option<Arc<ClockOffsets>>,
+}
+
+impl ExtractorChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The built-in chain: embedded EXIF, XMP sidecars, messaging app file names,
+    /// exiftool (through the cache when given), then the modification time
+    pub fn standard(
+        exiftool_path: PathBuf,
+        cache: Option<Arc<MetadataCache>>,
+        quicktime_utc: bool,
+    ) -> Self {
+        Self::new()
+            .with(Arc::new(EmbeddedExif))
+            .with(Arc::new(XmpSidecar))
+            .with(Arc::new(FileName))
+            .with(Arc::new(ExifTool {
+                path: exiftool_path,
+                cache,
+                quicktime_utc,
+            }))
+            .with(Arc::new(ModifiedTime))
+    }
+
+    /// The chain for an imported exiftool dump, which replaces reading the files
+    pub fn from_dump(dump: Arc<MetadataDump>) -> Self {
+        Self::new()
+            .with(dump)
+            .with(Arc::new(XmpSidecar))
+            .with(Arc::new(FileName))
+            .with(Arc::new(ModifiedTime))
+    }
+
+    /// Add an extractor after the ones already in the chain
+    pub fn with(mut self, extractor: Arc<dyn DateExtractor>) -> Self {
+        self.extractors.push(extractor);
+        self
+    }
+
+    /// Add extractors ahead of the ones already in the chain, keeping their order
+    pub fn with_first(mut self, extractors: &[Arc<dyn DateExtractor>]) -> Self {
+        self.extractors.splice(0..0, extractors.iter().cloned());
+        self
+    }
+
+    /// Correct the metadata dates of the cameras in `offsets`
+    pub fn with_clock_offsets(mut self, offsets: Arc<ClockOffsets>) -> Self {
+        self.clock_offsets = Some(offsets);
+        self
+    }
+
+    /// Ask each extractor in turn. Fails with the first extractor failure, or
+    /// `NoDate`, when none of them finds a date. Apple's edited copies (`IMG_E1234`)
+    /// take the date of their original, and clips exported from motion photos that
+    /// of their still, so the two stay together.
+    pub fn extract(&self, path: &Path) -> Result<ExtractedDate, DateError> {
+        let paired = photos::edited_original(path).or_else(|| motion::clip_still(path));
+        let path = paired.as_deref().unwrap_or(path);
+        let mut failure = None;
+        for extractor in &self.extractors {
+            localtime::take_adjustment();
+            match extractor.extract(path) {
+                Ok(datetime) => {
+                    let date_source = extractor.date_source();
+                    let datetime = if extractor.camera_clock() {
+                        self.correct_clock(path, datetime, date_source)
+                    } else {
+                        datetime
+                    };
+                    return Ok(ExtractedDate {
+                        datetime,
+                        date_source,
+                        failure,
+                        write_back: extractor.write_back(),
+                        dst_adjustment: localtime::take_adjustment(),
+                    });
+                }
+                Err(DateError::NoDate) => {}
+                Err(e) => {
+                    failure.get_or_insert(e);
+                }
+            }
+        }
+        Err(failure.unwrap_or(DateError::NoDate))
+    }
+
+    /// Shift a date the camera's clock got wrong. Guesses from file names and
+    /// modification times never came from the camera's clock.
+    fn correct_clock(
+        &self,
+        path: &Path,
+        datetime: DateTime<Local>,
+        date_source: DateSource,
+    ) -> DateTime<Local> {
+        let Some(offsets) = &self.clock_offsets else {
+            return datetime;
+        };
+        if date_source != DateSource::Metadata || offsets.is_empty() {
+            return datetime;
+        }
+        offsets
+            .offset_for(&shot_info(path))
+            .and_then(|offset| datetime.checked_add_signed(offset))
+            .unwrap_or(datetime)
+    }
+}
+
+/// EXIF read straight from JPEG, TIFF-based RAW, HEIF, PNG and WebP files without
+/// starting exiftool. Anything it can't read is left to the extractors after it.
+pub struct EmbeddedExif;
+
+impl DateExtractor for EmbeddedExif {
+    fn name(&self) -> &str {
+        "embedded EXIF"
+    }
+
+    fn extract(&self, path: &Path) -> Result<DateTime<Local>, DateError> {
+        let file = File::open(path).map_err(|_| DateError::NoDate)?;
+        let exif = exif::Reader::new()
+            .read_from_container(&mut BufReader::new(file))
+            .map_err(|_| DateError::NoDate)?;
+
+        let text = |tag| match &exif.get_field(tag, exif::In::PRIMARY)?.value {
+            exif::Value::Ascii(values) => Some(std::str::from_utf8(values.first()?).ok()?.trim()),
+            _ => None,
+        };
+        [
+            (exif::Tag::DateTimeOriginal, exif::Tag::SubSecTimeOriginal),
+            (exif::Tag::DateTimeDigitized, exif::Tag::SubSecTimeDigitized),
+            (exif::Tag::DateTime, exif::Tag::SubSecTime),
+        ]
+        .into_iter()
+        .find_map(|(tag, subsec_tag)| {
+            let datetime = parse_exif_date(text(tag)?)?;
+            Some(match text(subsec_tag) {
+                Some(subsec) => exiftool::with_subsec(datetime, subsec),
+                None => datetime,
+            })
+        })
+        .ok_or(DateError::NoDate)
+    }
+}
+
+/// The GPS timestamp in the embedded EXIF (`GPSDateStamp` and `GPSTimeStamp`). It is
+/// UTC from the satellites, right even when the camera's clock was not, and is
+/// turned into local time like every other date.
+pub struct GpsTime;
+
+impl DateExtractor for GpsTime {
+    fn name(&self) -> &str {
+        "GPS time"
+    }
+
+    fn camera_clock(&self) -> bool {
+        false
+    }
+
+    fn extract(&self, path: &Path) -> Result<DateTime<Local>, DateError> {
+        let file = File::open(path).map_err(|_| DateError::NoDate)?;
+        let exif = exif::Reader::new()
+            .read_from_container(&mut BufReader::new(file))
+            .map_err(|_| DateError::NoDate)?;
+        gps_datetime(&exif).ok_or(DateError::NoDate)
+    }
+}
+
+fn gps_datetime(exif: &exif::Exif) -> Option<DateTime<Local>> {
+    let exif::Value::Ascii(values) = &exif
+        .get_field(exif::Tag::GPSDateStamp, exif::In::PRIMARY)?
+        .value
+    else {
+        return None;
+    };
+    let date = std::str::from_utf8(values.first()?).ok()?.trim();
+    // The spec says `YYYY:MM:DD`, some phones write dashes
+    let date = chrono::NaiveDate::parse_from_str(date, "%Y:%m:%d")
+        .or_else(|_| chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d"))
+        .ok()?;
+
+    let exif::Value::Rational(hms) = &exif
+        .get_field(exif::Tag::GPSTimeStamp, exif::In::PRIMARY)?
+        .value
+    else {
+        return None;
+    };
+    if hms.len() < 3 || hms.iter().any(|part| part.denom == 0) {
+        return None;
+    }
+    let seconds = hms[0].to_f64() * 3600.0 + hms[1].to_f64() * 60.0 + hms[2].to_f64();
+    if !(0.0..86_400.0).contains(&seconds) {
+        return None;
+    }
+    let time =
+        chrono::NaiveTime::MIN + chrono::TimeDelta::milliseconds((seconds * 1000.0).round() as i64);
+    Some(date.and_time(time).and_utc().with_timezone(&Local))
+}
+
+/// What the file's embedded EXIF says about the shot and the camera that took it
+#[derive(Debug, Clone, Default)]
+pub struct ShotInfo {
+    /// Make and model, e.g. `Canon EOS R5`
+    pub model: Option<String>,
+    /// Body serial number, telling apart two bodies of the same model
+    pub serial: Option<String>,
+    /// Lens model, e.g. `RF24-70mm F2.8 L IS USM`
+    pub lens: Option<String>,
+    /// Focal length in millimeters, rounded
+    pub focal_length: Option<u32>,
+    /// How the picture is displayed, after applying the EXIF rotation
+    pub orientation: Option<Orientation>,
+    /// Keywords Windows Explorer writes into the EXIF (`XPKeywords`)
+    pub keywords: Vec<String>,
+    /// Where the picture was taken, as decimal latitude and longitude
+    pub location: Option<(f64, f64)>,
+}
+
+/// The shape of a picture as it is displayed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+    Square,
+}
+
+impl std::fmt::Display for Orientation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Orientation::Portrait => write!(f, "portrait"),
+            Orientation::Landscape => write!(f, "landscape"),
+            Orientation::Square => write!(f, "square"),
+        }
+    }
+}
+
+/// Windows' keyword tag, which kamadak-exif has no name for
+const XP_KEYWORDS: exif::Tag = exif::Tag(exif::Context::Tiff, 0x9c9e);
+
+/// Read the shot details from the file's embedded EXIF. Everything is `None` for
+/// files without readable EXIF, such as most videos.
+pub fn shot_info(path: &Path) -> ShotInfo {
+    let Some(exif) = File::open(path).ok().and_then(|file| {
+        exif::Reader::new()
+            .read_from_container(&mut BufReader::new(file))
+            .ok()
+    }) else {
+        return ShotInfo::default();
+    };
+    let text = |tag| match &exif.get_field(tag, exif::In::PRIMARY)?.value {
+        exif::Value::Ascii(values) => {
+            let text = std::str::from_utf8(values.first()?).ok()?.trim();
+            (!text.is_empty()).then(|| text.to_string())
+        }
+        _ => None,
+    };
+
+    let model = text(exif::Tag::Model).map(|model| match text(exif::Tag::Make) {
+        // Most models already start with the make ("Canon EOS R5"), some don't ("ILCE-7M3")
+        Some(make) if !model.to_lowercase().starts_with(&make.to_lowercase()) => {
+            format!("{} {}", make, model)
+        }
+        _ => model,
+    });
+
+    let number = |tag| {
+        exif.get_field(tag, exif::In::PRIMARY)
+            .and_then(|field| field.value.get_uint(0))
+    };
+    let orientation = match (
+        number(exif::Tag::PixelXDimension).or(number(exif::Tag::ImageWidth)),
+        number(exif::Tag::PixelYDimension).or(number(exif::Tag::ImageLength)),
+    ) {
+        (Some(width), Some(height)) => {
+            // Orientations 5 to 8 turn the picture by 90 degrees
+            let (width, height) = match number(exif::Tag::Orientation) {
+                Some(5..=8) => (height, width),
+                _ => (width, height),
+            };
+            Some(match width.cmp(&height) {
+                std::cmp::Ordering::Less => Orientation::Portrait,
+                std::cmp::Ordering::Greater => Orientation::Landscape,
+                std::cmp::Ordering::Equal => Orientation::Square,
+            })
+        }
+        _ => None,
+    };
+
+    ShotInfo {
+        model,
+        serial: text(exif::Tag::BodySerialNumber),
+        lens: text(exif::Tag::LensModel),
+        focal_length: match &exif
+            .get_field(exif::Tag::FocalLength, exif::In::PRIMARY)
+            .map(|field| &field.value)
+        {
+            Some(exif::Value::Rational(values)) if values.first().is_some_and(|v| v.denom != 0) => {
+                Some(values[0].to_f64().round() as u32)
+            }
+            _ => None,
+        },
+        orientation,
+        keywords: match &exif
+            .get_field(XP_KEYWORDS, exif::In::PRIMARY)
+            .map(|field| &field.value)
+        {
+            // UCS-2 text, keywords separated by semicolons
+            Some(exif::Value::Byte(bytes)) => {
+                let units: Vec<u16> = bytes
+                    .chunks_exact(2)
+                    .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                    .take_while(|&unit| unit != 0)
+                    .collect();
+                String::from_utf16_lossy(&units)
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|keyword| !keyword.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            }
+            _ => Vec::new(),
+        },
+        location: coordinate(
+            &exif,
+            exif::Tag::GPSLatitude,
+            exif::Tag::GPSLatitudeRef,
+            b'S',
+        )
+        .zip(coordinate(
+            &exif,
+            exif::Tag::GPSLongitude,
+            exif::Tag::GPSLongitudeRef,
+            b'W',
+        )),
+    }
+}
+
+/// A GPS latitude or longitude in degrees, negative when its reference is
+/// `negative_ref` (south or west)
+fn coordinate(
+    exif: &exif::Exif,
+    tag: exif::Tag,
+    ref_tag: exif::Tag,
+    negative_ref: u8,
+) -> Option<f64> {
+    let exif::Value::Rational(dms) = &exif.get_field(tag, exif::In::PRIMARY)?.value else {
+        return None;
+    };
+    if dms.len() < 3 || dms.iter().any(|part| part.denom == 0) {
+        return None;
+    }
+    let degrees = dms[0].to_f64() + dms[1].to_f64() / 60.0 + dms[2].to_f64() / 3600.0;
+    let negative = match &exif.get_field(ref_tag, exif::In::PRIMARY)?.value {
+        exif::Value::Ascii(values) => values
+            .first()
+            .and_then(|value| value.first())
+            .is_some_and(|&r| r.eq_ignore_ascii_case(&negative_ref)),
+        _ => false,
+    };
+    Some(if negative { -degrees } else { degrees })
+}
+
+/// Keywords of a file, from its XMP (sidecar or embedded) or else its EXIF
+pub fn keywords(path: &Path) -> Vec<String> {
+    let keywords = xmp::read(path).keywords;
+    if keywords.is_empty() {
+        shot_info(path).keywords
+    } else {
+        keywords
+    }
+}
+
+/// Dates written by photo managers into an XMP sidecar next to the file,
+/// named either `IMG_1234.jpg.xmp` or `IMG_1234.xmp`
+pub struct XmpSidecar;
+
+impl DateExtractor for XmpSidecar {
+    fn name(&self) -> &str {
+        "XMP sidecar"
+    }
+
+    fn write_back(&self) -> bool {
+        true
+    }
+
+    fn extract(&self, path: &Path) -> Result<DateTime<Local>, DateError> {
+        xmp::sidecars(path)
+            .iter()
+            .find_map(|xmp| {
+                XMP_DATES.iter().find_map(|pattern| {
+                    let captures = pattern.captures(xmp)?;
+                    let mut date = captures[1].to_string();
+                    // Seconds are optional in XMP
+                    if date.len() == "2021-03-04T05:06".len() {
+                        date.push_str(":00");
+                    }
+                    if let Some(offset) = captures.get(2) {
+                        date.push_str(offset.as_str());
+                    }
+                    parse_exif_date(&date)
+                })
+            })
+            .ok_or(DateError::NoDate)
+    }
+}
+
+/// Capture dates messaging apps and cloud camera uploads (Dropbox, OneDrive) put in
+/// their file names. Their modification time is when the file was downloaded, so
+/// the name is the better guess.
+pub struct FileName;
+
+impl DateExtractor for FileName {
+    fn name(&self) -> &str {
+        "file name"
+    }
+
+    fn date_source(&self) -> DateSource {
+        DateSource::Fallback
+    }
+
+    fn write_back(&self) -> bool {
+        true
+    }
+
+    fn extract(&self, path: &Path) -> Result<DateTime<Local>, DateError> {
+        messaging::messaging_filename_datetime(path)
+            .or_else(|| cloud::camera_upload_datetime(path))
+            .ok_or(DateError::NoDate)
+    }
+}
+
+/// Run exiftool on the file, going through the metadata cache when one is used.
+/// Files without a date are cached too, but failed extractions are not so they get retried.
+pub struct ExifTool {
+    pub path: PathBuf,
+    pub cache: Option<Arc<MetadataCache>>,
+    /// Read the UTC dates of videos as UTC, see `exiftool::extract_datetime`
+    pub quicktime_utc: bool,
+}
+
+impl DateExtractor for ExifTool {
+    fn name(&self) -> &str {
+        "exiftool"
+    }
+
+    fn extract(&self, path: &Path) -> Result<DateTime<Local>, DateError> {
+        let Some(cache) = &self.cache else {
+            return exiftool::extract_datetime(&self.path, path, self.quicktime_utc);
+        };
+
+        // Video dates cached with the other setting are hours off
+        let utc = self.quicktime_utc && crate::metadata::is_video_file(path);
+        if let Some(cached) = cache.get(path, utc) {
+            return cached.ok_or(DateError::NoDate);
+        }
+
+        let result = exiftool::extract_datetime(&self.path, path, self.quicktime_utc);
+        match &result {
+            Ok(datetime) => cache.insert(path, utc, Some(*datetime)),
+            Err(DateError::NoDate) => cache.insert(path, utc, None),
+            Err(DateError::Failed { .. }) => {}
+        }
+        result
+    }
+}
+
+impl DateExtractor for MetadataDump {
+    fn name(&self) -> &str {
+        "metadata dump"
+    }
+
+    fn extract(&self, path: &Path) -> Result<DateTime<Local>, DateError> {
+        self.datetime(path).ok_or(DateError::NoDate)
+    }
+}
+
+/// The file's modification time, the last resort
+pub struct ModifiedTime;
+
+impl DateExtractor for ModifiedTime {
+    fn name(&self) -> &str {
+        "modification time"
+    }
+
+    fn date_source(&self) -> DateSource {
+        DateSource::Fallback
+    }
+
+    fn extract(&self, path: &Path) -> Result<DateTime<Local>, DateError> {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .map(Into::into)
+            .map_err(|e| DateError::Failed {
+                kind: FailureKind::Unreadable,
+                detail: e.to_string(),
+            })
+    }
+}
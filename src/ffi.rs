@@ -0,0 +1,172 @@
+use serde::Deserialize;
+use serde_json::json;
+use std::ffi::{CStr, CString, c_char, c_int, c_void};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::Organizer;
+use crate::cache::MetadataCache;
+use crate::layout::{self, Preset};
+use crate::manifest::HashAlgorithm;
+use crate::outcome::{Action, DateSource, FileOutcome};
+use crate::stats::Stats;
+
+/// The run finished or was stopped by the callback
+pub const TIMEKEEPER_OK: c_int = 0;
+/// `options_json` is missing, not UTF-8 or not valid options
+pub const TIMEKEEPER_INVALID_OPTIONS: c_int = 1;
+/// The run itself failed, e.g. exiftool is missing or the destination is full
+pub const TIMEKEEPER_RUN_FAILED: c_int = 2;
+/// The library panicked
+pub const TIMEKEEPER_PANIC: c_int = 3;
+
+/// Receives every event as a NUL-terminated JSON object, valid only during the call.
+/// Returning non-zero stops the run after the files in flight.
+pub type TimekeeperCallback =
+    Option<unsafe extern "C" fn(event_json: *const c_char, user_data: *mut c_void) -> c_int>;
+
+/// Options of `timekeeper_organize`, a JSON object with the fields below
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OrganizeOptions {
+    source: PathBuf,
+    destination: PathBuf,
+    #[serde(default)]
+    dry_run: bool,
+    /// Move files instead of copying them
+    #[serde(default, rename = "move")]
+    move_files: bool,
+    /// plain, immich, photoprism, digikam, plex or jellyfin
+    preset: Option<String>,
+    /// A built-in layout name or a template, overriding the preset's
+    layout: Option<String>,
+    exiftool: Option<PathBuf>,
+    #[serde(default)]
+    verify: bool,
+    /// Write MANIFEST.<algorithm> (sha256 or blake3) into the destination
+    manifest: Option<String>,
+}
+
+impl OrganizeOptions {
+    fn organizer(self) -> Result<Organizer, String> {
+        let mut organizer = Organizer::new(self.source, self.destination.clone(), self.dry_run)
+            .with_copy(!self.move_files)
+            .with_verify(self.verify);
+        if let Some(preset) = &self.preset {
+            organizer = organizer.with_preset(preset.parse::<Preset>()?);
+        }
+        if let Some(layout) = &self.layout {
+            organizer = organizer.with_layout(layout::parse_layout(layout)?);
+        }
+        if let Some(exiftool) = self.exiftool {
+            organizer = organizer.with_exiftool(exiftool);
+        }
+        if let Some(path) = MetadataCache::default_path() {
+            organizer = organizer.with_cache(MetadataCache::load(path));
+        }
+        if let Some(algorithm) = &self.manifest
+            && !self.dry_run
+        {
+            let algorithm = algorithm.parse::<HashAlgorithm>()?;
+            let manifests = organizer
+                .open_manifests(algorithm)
+                .map_err(|e| e.to_string())?;
+            organizer = organizer.with_manifest(manifests);
+        }
+        Ok(organizer)
+    }
+}
+
+/// C entry point for embedding the organizer, declared in `include/timekeeper.h`.
+/// Organizes `source` into `destination` as described by `options_json`, e.g.
+/// `{"source": "/media/card", "destination": "/home/me/Photos", "preset": "immich"}`.
+///
+/// Blocks until the run ends. `callback` (may be NULL) gets a `{"event": "file", ...}`
+/// object per file and a last `{"event": "finish", ...}` object with the stats.
+///
+/// # Safety
+///
+/// `options_json` must be NULL or a NUL-terminated string, and `callback` must be safe
+/// to call with `user_data` from the calling thread.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn timekeeper_organize(
+    options_json: *const c_char,
+    callback: TimekeeperCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    if options_json.is_null() {
+        return TIMEKEEPER_INVALID_OPTIONS;
+    }
+    // SAFETY: non-null and NUL-terminated as the caller promises
+    let options = unsafe { CStr::from_ptr(options_json) };
+    let notify = |event: serde_json::Value| -> bool {
+        let Some(callback) = callback else {
+            return true;
+        };
+        let Ok(event) = CString::new(event.to_string()) else {
+            return true;
+        };
+        // SAFETY: the caller vouches for the callback and its user data
+        unsafe { callback(event.as_ptr(), user_data) == 0 }
+    };
+
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| organize(options, &notify)))
+        .unwrap_or(TIMEKEEPER_PANIC)
+}
+
+fn organize(options: &CStr, notify: &dyn Fn(serde_json::Value) -> bool) -> c_int {
+    let organizer = match options
+        .to_str()
+        .map_err(|e| e.to_string())
+        .and_then(|text| serde_json::from_str::<OrganizeOptions>(text).map_err(|e| e.to_string()))
+        .and_then(OrganizeOptions::organizer)
+    {
+        Ok(organizer) => organizer,
+        Err(e) => {
+            notify(json!({ "event": "finish", "error": format!("invalid options: {}", e) }));
+            return TIMEKEEPER_INVALID_OPTIONS;
+        }
+    };
+
+    let stats = Arc::new(Stats::new());
+    let terminate_flag = Arc::new(AtomicBool::new(false));
+    let mut outcomes =
+        Arc::new(organizer).outcomes(Arc::clone(&stats), Arc::clone(&terminate_flag));
+    for outcome in &mut outcomes {
+        if !notify(file_event(&outcome)) {
+            terminate_flag.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let result = outcomes.finish();
+    let error = result.as_ref().err().map(ToString::to_string);
+    notify(json!({ "event": "finish", "error": error, "stats": stats.snapshot() }));
+    match result {
+        Ok(()) => TIMEKEEPER_OK,
+        Err(_) => TIMEKEEPER_RUN_FAILED,
+    }
+}
+
+fn file_event(outcome: &FileOutcome) -> serde_json::Value {
+    let action = match outcome.action {
+        Action::Copy => "copy",
+        Action::Move => "move",
+        Action::AlreadyInPlace => "already_in_place",
+        Action::NotSettled => "not_settled",
+        Action::OnlineOnly => "online_only",
+        Action::Failed => "failed",
+    };
+    let date_source = outcome.date_source.map(|source| match source {
+        DateSource::Metadata => "metadata",
+        DateSource::Fallback => "fallback",
+    });
+    json!({
+        "event": "file",
+        "source": outcome.source,
+        "destination": outcome.destination,
+        "action": action,
+        "date_source": date_source,
+        "error": outcome.error.as_ref().map(ToString::to_string),
+    })
+}
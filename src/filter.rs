@@ -0,0 +1,154 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::extract;
+
+/// Mean radius of the Earth in kilometers
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// Which media files a run organizes, checked while walking the source. Files left
+/// out are not counted and stay where they are.
+#[derive(Debug, Clone, Default)]
+pub struct FileFilter {
+    /// Keep only files tagged with at least one of these keywords
+    pub required_keywords: Vec<String>,
+    /// Leave out files tagged with any of these keywords
+    pub excluded_keywords: Vec<String>,
+    /// Keep only files taken inside this area, leaving out files without GPS tags
+    pub area: Option<Area>,
+    /// Keep only these files, e.g. the ones picked with `--pick`
+    pub only: Option<Arc<HashSet<PathBuf>>>,
+}
+
+impl FileFilter {
+    /// Whether every file passes, so nothing needs to be read for it
+    pub fn is_empty(&self) -> bool {
+        self.required_keywords.is_empty()
+            && self.excluded_keywords.is_empty()
+            && self.area.is_none()
+            && self.only.is_none()
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        self.only.as_ref().is_none_or(|only| only.contains(path))
+            && self.matches_keywords(path)
+            && self.matches_area(path)
+    }
+
+    fn matches_keywords(&self, path: &Path) -> bool {
+        if self.required_keywords.is_empty() && self.excluded_keywords.is_empty() {
+            return true;
+        }
+
+        // Keywords are compared case-insensitively, "Wedding" and "wedding" are the same tag
+        let keywords: Vec<String> = extract::keywords(path)
+            .iter()
+            .map(|keyword| keyword.to_lowercase())
+            .collect();
+        let tagged = |wanted: &String| keywords.contains(&wanted.to_lowercase());
+
+        (self.required_keywords.is_empty() || self.required_keywords.iter().any(tagged))
+            && !self.excluded_keywords.iter().any(tagged)
+    }
+
+    fn matches_area(&self, path: &Path) -> bool {
+        let Some(area) = &self.area else {
+            return true;
+        };
+        extract::shot_info(path)
+            .location
+            .is_some_and(|(lat, lon)| area.contains(lat, lon))
+    }
+}
+
+/// A geographic area files must have been taken in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Area {
+    /// Between two corners, `--within "38.6,-9.3:38.8,-9.0"`
+    Within {
+        south: f64,
+        west: f64,
+        north: f64,
+        east: f64,
+    },
+    /// Around a point, `--near "38.7,-9.1,10km"`
+    Near { lat: f64, lon: f64, radius_km: f64 },
+}
+
+impl Area {
+    /// Parse the corners of a box as `lat,lon:lat,lon`, in any order
+    pub fn within(s: &str) -> Result<Self, String> {
+        let (first, second) = s
+            .split_once(':')
+            .ok_or_else(|| format!("invalid area '{}' (expected lat,lon:lat,lon)", s))?;
+        let (lat1, lon1) = parse_point(first)?;
+        let (lat2, lon2) = parse_point(second)?;
+        // Boxes across the antimeridian aren't supported, the corners are simply sorted
+        Ok(Area::Within {
+            south: lat1.min(lat2),
+            west: lon1.min(lon2),
+            north: lat1.max(lat2),
+            east: lon1.max(lon2),
+        })
+    }
+
+    /// Parse a circle as `lat,lon,radius`, the radius in `km` or `m`
+    pub fn near(s: &str) -> Result<Self, String> {
+        let (point, radius) = s
+            .rsplit_once(',')
+            .ok_or_else(|| format!("invalid area '{}' (expected lat,lon,radius)", s))?;
+        let (lat, lon) = parse_point(point)?;
+        let radius = radius.trim().to_lowercase();
+        let radius_km = if let Some(km) = radius.strip_suffix("km") {
+            km.trim().parse::<f64>().ok()
+        } else if let Some(m) = radius.strip_suffix('m') {
+            m.trim().parse::<f64>().ok().map(|m| m / 1000.0)
+        } else {
+            None
+        }
+        .filter(|km| km.is_finite() && *km > 0.0)
+        .ok_or_else(|| format!("invalid radius '{}' (expected e.g. 10km or 500m)", radius))?;
+        Ok(Area::Near {
+            lat,
+            lon,
+            radius_km,
+        })
+    }
+
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        match *self {
+            Area::Within {
+                south,
+                west,
+                north,
+                east,
+            } => (south..=north).contains(&lat) && (west..=east).contains(&lon),
+            Area::Near {
+                lat: center_lat,
+                lon: center_lon,
+                radius_km,
+            } => distance_km((center_lat, center_lon), (lat, lon)) <= radius_km,
+        }
+    }
+}
+
+fn parse_point(s: &str) -> Result<(f64, f64), String> {
+    let invalid = || format!("invalid coordinates '{}' (expected lat,lon)", s.trim());
+    let (lat, lon) = s.split_once(',').ok_or_else(invalid)?;
+    let lat: f64 = lat.trim().parse().map_err(|_| invalid())?;
+    let lon: f64 = lon.trim().parse().map_err(|_| invalid())?;
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+        return Err(invalid());
+    }
+    Ok((lat, lon))
+}
+
+/// Great-circle distance between two points, by the haversine formula
+fn distance_km((lat1, lon1): (f64, f64), (lat2, lon2): (f64, f64)) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (lon2 - lon1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
@@ -0,0 +1,98 @@
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Condvar, Mutex};
+
+use crate::error::{Result, TimekeeperError};
+
+/// User commands run for every placed file, e.g. to make thumbnails or fix ownership.
+/// `{src}` and `{dst}` in a command are replaced by the file's source and destination,
+/// quoted for the shell.
+pub struct ExecHooks {
+    before: Option<String>,
+    after: Option<String>,
+    /// Commands that may still start before the limit is reached
+    free: Mutex<usize>,
+    released: Condvar,
+}
+
+impl ExecHooks {
+    /// Hooks running at most `concurrency` commands at once across all movers
+    pub fn new(before: Option<String>, after: Option<String>, concurrency: usize) -> Self {
+        ExecHooks {
+            before,
+            after,
+            free: Mutex::new(concurrency.max(1)),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Run the `--exec-before` command. A failure keeps the file from being placed.
+    pub fn before(&self, source: &Path, dest: &Path) -> Result<()> {
+        match &self.before {
+            Some(template) => self.run(template, source, dest),
+            None => Ok(()),
+        }
+    }
+
+    /// Run the `--exec-after` command once the file is in place
+    pub fn after(&self, source: &Path, dest: &Path) -> Result<()> {
+        match &self.after {
+            Some(template) => self.run(template, source, dest),
+            None => Ok(()),
+        }
+    }
+
+    fn run(&self, template: &str, source: &Path, dest: &Path) -> Result<()> {
+        let command = template
+            .replace("{src}", &shell_quote(source))
+            .replace("{dst}", &shell_quote(dest));
+
+        let mut free = self
+            .released
+            .wait_while(self.free.lock().unwrap(), |free| *free == 0)
+            .unwrap();
+        *free -= 1;
+        drop(free);
+
+        let status = shell(&command).status();
+
+        *self.free.lock().unwrap() += 1;
+        self.released.notify_one();
+
+        let detail = match status {
+            Ok(status) if status.success() => return Ok(()),
+            Ok(status) => match status.code() {
+                Some(code) => format!("exited with status {}", code),
+                None => "killed by a signal".to_string(),
+            },
+            Err(e) => e.to_string(),
+        };
+        Err(TimekeeperError::HookFailed { command, detail })
+    }
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+/// Quote a path so the shell passes it on as a single argument
+#[cfg(unix)]
+fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+#[cfg(windows)]
+fn shell_quote(path: &Path) -> String {
+    // Windows paths can't contain double quotes
+    format!("\"{}\"", path.to_string_lossy())
+}
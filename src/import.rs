@@ -0,0 +1,148 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::removal;
+
+/// Folder every camera following the DCF standard stores its pictures in
+pub const DCIM: &str = "DCIM";
+
+/// What to remove from a card once its files are imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Erase {
+    /// Leave the card untouched
+    None,
+    /// Delete only the files that were imported
+    Imported,
+    /// Delete everything below the card's DCIM folder
+    All,
+}
+
+impl std::str::FromStr for Erase {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Erase::None),
+            "imported" => Ok(Erase::Imported),
+            "all" => Ok(Erase::All),
+            _ => Err(format!(
+                "unknown erase mode '{}' (expected none, imported or all)",
+                s
+            )),
+        }
+    }
+}
+
+/// Mounted volumes that look like camera cards, i.e. have a `DCIM` folder at their root
+pub fn find_camera_cards() -> Vec<PathBuf> {
+    let mut cards: Vec<PathBuf> = mounted_volumes()
+        .into_iter()
+        .filter(|volume| volume.join(DCIM).is_dir())
+        .collect();
+    cards.sort();
+    cards.dedup();
+    cards
+}
+
+/// Mount points of removable media under the usual automount locations
+#[cfg(target_os = "linux")]
+fn mounted_volumes() -> Vec<PathBuf> {
+    const AUTOMOUNT_ROOTS: [&str; 3] = ["/media", "/run/media", "/mnt"];
+
+    let Ok(mounts) = fs::read_to_string("/proc/self/mounts") else {
+        return Vec::new();
+    };
+    mounts
+        .lines()
+        .filter_map(|line| line.split(' ').nth(1))
+        // Spaces in mount points are escaped as \040
+        .map(|mount_point| PathBuf::from(mount_point.replace("\\040", " ")))
+        .filter(|mount_point| {
+            AUTOMOUNT_ROOTS
+                .iter()
+                .any(|root| mount_point.starts_with(root))
+        })
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+fn mounted_volumes() -> Vec<PathBuf> {
+    fs::read_dir("/Volumes")
+        .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(windows)]
+fn mounted_volumes() -> Vec<PathBuf> {
+    // A: and B: are floppy drives and C: is the system drive
+    (b'D'..=b'Z')
+        .map(|letter| PathBuf::from(format!("{}:\\", letter as char)))
+        .filter(|drive| drive.exists())
+        .collect()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn mounted_volumes() -> Vec<PathBuf> {
+    Vec::new()
+}
+
+/// Delete `files` from the card, plus any folders below `DCIM` they leave empty.
+/// Returns how many files were removed; files that are already gone are not an error.
+/// With `use_trash` the files go to the system trash instead.
+pub fn erase_files(card: &Path, files: &[PathBuf], use_trash: bool) -> io::Result<usize> {
+    let mut removed = 0;
+    for file in files {
+        match removal::remove_file(file, use_trash) {
+            Ok(()) => removed += 1,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    remove_empty_dirs(&card.join(DCIM))?;
+    Ok(removed)
+}
+
+/// Delete every file below the card's `DCIM` folder, keeping the folder itself
+/// so the camera doesn't have to recreate it
+pub fn erase_dcim(card: &Path, use_trash: bool) -> io::Result<usize> {
+    let mut removed = 0;
+    for entry in fs::read_dir(card.join(DCIM))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            removed += count_files(&path)?;
+            removal::remove_dir_all(&path, use_trash)?;
+        } else {
+            removal::remove_file(&path, use_trash)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+fn count_files(dir: &Path) -> io::Result<usize> {
+    let mut count = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        count += if path.is_dir() {
+            count_files(&path)?
+        } else {
+            1
+        };
+    }
+    Ok(count)
+}
+
+/// Remove the empty folders below `dir`, leaving `dir` itself
+fn remove_empty_dirs(dir: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            remove_empty_dirs(&path)?;
+            if fs::read_dir(&path)?.next().is_none() {
+                fs::remove_dir(&path)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,375 @@
+use chrono::{DateTime, Datelike, Local};
+use regex::Regex;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use crate::android;
+use crate::extract::{self, Orientation, ShotInfo};
+use crate::photos;
+use crate::template::{self, Template};
+use crate::xmp::{self, XmpInfo};
+
+/// What a layout knows about a file when picking its folder
+pub struct FileMeta<'a> {
+    pub source: &'a Path,
+    /// Root of the run's source, whose own name is never a title
+    pub source_root: &'a Path,
+    pub datetime: DateTime<Local>,
+    /// The file's `{seq}` number, set while its name is picked
+    pub seq: Option<usize>,
+    shot: OnceLock<ShotInfo>,
+    xmp: OnceLock<XmpInfo>,
+}
+
+impl<'a> FileMeta<'a> {
+    pub fn new(source: &'a Path, source_root: &'a Path, datetime: DateTime<Local>) -> Self {
+        FileMeta {
+            source,
+            source_root,
+            datetime,
+            seq: None,
+            shot: OnceLock::new(),
+            xmp: OnceLock::new(),
+        }
+    }
+
+    /// Camera make and model from the file's EXIF, read the first time it is asked for
+    pub fn camera(&self) -> Option<&str> {
+        self.shot_info().model.as_deref()
+    }
+
+    /// The camera body's serial number from the file's EXIF
+    pub fn serial(&self) -> Option<&str> {
+        self.shot_info().serial.as_deref()
+    }
+
+    /// The lens model from the file's EXIF
+    pub fn lens(&self) -> Option<&str> {
+        self.shot_info().lens.as_deref()
+    }
+
+    /// The focal length in millimeters from the file's EXIF
+    pub fn focal_length(&self) -> Option<u32> {
+        self.shot_info().focal_length
+    }
+
+    /// Portrait, landscape or square, from the file's EXIF dimensions and rotation
+    pub fn orientation(&self) -> Option<Orientation> {
+        self.shot_info().orientation
+    }
+
+    fn shot_info(&self) -> &ShotInfo {
+        self.shot.get_or_init(|| extract::shot_info(self.source))
+    }
+
+    /// Star rating from the file's XMP sidecar or embedded XMP
+    pub fn rating(&self) -> Option<i32> {
+        self.xmp_info().rating
+    }
+
+    /// Keywords from the file's XMP sidecar or embedded XMP, or else its EXIF
+    pub fn keywords(&self) -> &[String] {
+        match &self.xmp_info().keywords {
+            keywords if keywords.is_empty() => &self.shot_info().keywords,
+            keywords => keywords,
+        }
+    }
+
+    fn xmp_info(&self) -> &XmpInfo {
+        self.xmp.get_or_init(|| xmp::read(self.source))
+    }
+
+    /// Name of the folder the file sits in, `None` directly in the source root
+    pub fn parent_name(&self) -> Option<String> {
+        let parent = self.source.parent()?;
+        if parent == self.source_root {
+            return None;
+        }
+        Some(parent.file_name()?.to_string_lossy().into_owned())
+    }
+
+    /// The file's folder relative to the source root, `None` directly in the root
+    pub fn relative_dir(&self) -> Option<&Path> {
+        self.source
+            .parent()?
+            .strip_prefix(self.source_root)
+            .ok()
+            .filter(|relative| !relative.as_os_str().is_empty())
+    }
+
+    /// The file's name, without the prefix Android gives files in its trash
+    pub fn original_name(&self) -> Option<OsString> {
+        Some(android::untrashed_name(self.source.file_name()?))
+    }
+
+    /// Name of the folder the file sits in, when it was named by a person. Folders a
+    /// Photos export named after a moment keep only its place, the date is the layout's.
+    pub fn folder_title(&self) -> Option<String> {
+        self.parent_name()
+            .map(|name| photos::moment_place(&name).to_string())
+            .filter(|name| !name.is_empty() && !is_generic_folder_name(name))
+    }
+}
+
+/// Folder structure created under the destination. Implement it to place files
+/// in a structure of your own with `Organizer::with_layout`.
+pub trait Layout: Send + Sync {
+    /// Destination directory for the file, relative to the destination
+    fn dest_for(&self, meta: &FileMeta) -> PathBuf;
+
+    /// Number of folder levels the layout creates below the destination, used to
+    /// recognize folders that are already organized
+    fn depth(&self) -> usize;
+}
+
+/// The layouts timekeeper ships with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BuiltinLayout {
+    /// `2023/May`, the original timekeeper layout
+    #[default]
+    YearMonthName,
+    /// `2023/05`
+    YearMonthNumber,
+    /// `2023/2023-05-14`
+    YearDate,
+    /// `2023/2023-05`, sorts correctly in any file browser (Lightroom, most NAS apps)
+    YearYearMonth,
+    /// `2023/05/14`
+    YearMonthDay,
+    /// `2023/Q2`, for archives kept by (fiscal) quarter
+    YearQuarter,
+    /// `1990s/1994`, coarse folders for old scanned archives
+    DecadeYear,
+    /// `Canon EOS R5/2023/May`, `Unknown Camera` for files without a camera model
+    Camera,
+    /// `2023/2023-05-14 Birthday`, titled after the source folder when it was named by a person
+    Events,
+}
+
+impl Layout for BuiltinLayout {
+    fn dest_for(&self, meta: &FileMeta) -> PathBuf {
+        let datetime = &meta.datetime;
+        let year = PathBuf::from(datetime.year().to_string());
+        match self {
+            BuiltinLayout::YearMonthName => year.join(datetime.format("%B").to_string()),
+            BuiltinLayout::YearMonthNumber => year.join(datetime.format("%m").to_string()),
+            BuiltinLayout::YearDate => year.join(datetime.format("%Y-%m-%d").to_string()),
+            BuiltinLayout::YearYearMonth => year.join(datetime.format("%Y-%m").to_string()),
+            BuiltinLayout::YearQuarter => year.join(format!("Q{}", template::quarter(datetime))),
+            BuiltinLayout::DecadeYear => Path::new(&template::decade(datetime)).join(year),
+            BuiltinLayout::YearMonthDay => year
+                .join(datetime.format("%m").to_string())
+                .join(datetime.format("%d").to_string()),
+            BuiltinLayout::Camera => {
+                // A model with a slash would otherwise become two folders
+                let camera = meta.camera().unwrap_or("Unknown Camera").replace('/', "-");
+                Path::new(&camera)
+                    .join(year)
+                    .join(datetime.format("%B").to_string())
+            }
+            BuiltinLayout::Events => {
+                let date = datetime.format("%Y-%m-%d").to_string();
+                match meta.folder_title() {
+                    // A folder this layout made keeps its name when organized again
+                    Some(title) if title.starts_with(&date) => year.join(title),
+                    Some(title) => year.join(format!("{} {}", date, title.trim())),
+                    None => year.join(date),
+                }
+            }
+        }
+    }
+
+    fn depth(&self) -> usize {
+        match self {
+            BuiltinLayout::YearMonthName
+            | BuiltinLayout::YearMonthNumber
+            | BuiltinLayout::YearDate
+            | BuiltinLayout::YearYearMonth
+            | BuiltinLayout::YearQuarter
+            | BuiltinLayout::DecadeYear
+            | BuiltinLayout::Events => 2,
+            BuiltinLayout::YearMonthDay | BuiltinLayout::Camera => 3,
+        }
+    }
+}
+
+impl std::str::FromStr for BuiltinLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "year-month" => Ok(BuiltinLayout::YearMonthName),
+            "year-month-number" => Ok(BuiltinLayout::YearMonthNumber),
+            "year-date" => Ok(BuiltinLayout::YearDate),
+            "ym" | "year-year-month" => Ok(BuiltinLayout::YearYearMonth),
+            "year-month-day" => Ok(BuiltinLayout::YearMonthDay),
+            "quarter" => Ok(BuiltinLayout::YearQuarter),
+            "decade" => Ok(BuiltinLayout::DecadeYear),
+            "camera" => Ok(BuiltinLayout::Camera),
+            "events" => Ok(BuiltinLayout::Events),
+            _ => Err(format!(
+                "unknown layout '{}' (expected year-month, year-month-number, year-date, ym, year-month-day, quarter, decade, camera, events or a template)",
+                s
+            )),
+        }
+    }
+}
+
+/// How files are named at the destination
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum FileNaming {
+    /// Keep the original file name
+    #[default]
+    Keep,
+    /// Prefix the original name with the capture time: `20230514_102345_IMG_1234.jpg`
+    DatePrefix,
+    /// Name files from a template, e.g. `{date}_{orig_stem|trunc(40)}`. The original
+    /// extension is added.
+    Template(Template),
+}
+
+impl FileNaming {
+    /// Whether names are numbered with `{seq}`, which needs files planned in date order
+    pub fn uses_sequence(&self) -> bool {
+        matches!(self, FileNaming::Template(template) if template.uses_sequence())
+    }
+
+    /// Destination file name for the file
+    pub fn file_name(&self, meta: &FileMeta) -> Option<OsString> {
+        let original = meta.original_name()?;
+        match self {
+            FileNaming::Keep => Some(original),
+            FileNaming::DatePrefix => {
+                let prefix = meta.datetime.format("%Y%m%d_%H%M%S_").to_string();
+                // Prefixed by an earlier run
+                if original.to_string_lossy().starts_with(&prefix) {
+                    return Some(original);
+                }
+                let mut name = OsString::from(prefix);
+                name.push(original);
+                Some(name)
+            }
+            FileNaming::Template(template) => {
+                let mut name = OsString::from(template.render(meta));
+                if name.is_empty() {
+                    return None;
+                }
+                if let Some(ext) = meta.source.extension() {
+                    name.push(".");
+                    name.push(ext);
+                }
+                Some(name)
+            }
+        }
+    }
+}
+
+/// Parse `--layout`: a built-in layout name or a template such as `{year}/{month:02}`
+pub fn parse_layout(s: &str) -> Result<Arc<dyn Layout>, String> {
+    if s.contains('{') {
+        Ok(Arc::new(s.parse::<Template>()?))
+    } else {
+        Ok(Arc::new(s.parse::<BuiltinLayout>()?))
+    }
+}
+
+lazy_static::lazy_static! {
+    // Camera (DCF) folders such as 100CANON or 101APPLE
+    static ref DCF_FOLDER: Regex = Regex::new(r"^\d{3}[A-Za-z0-9_]{5}$").unwrap();
+}
+
+/// Folder names created by devices or apps rather than chosen by a person
+const GENERIC_FOLDER_NAMES: [&str; 8] = [
+    "dcim", "camera", "videos", "movies", "video", "media", "import", "download",
+];
+
+/// Whether a folder name carries no meaning as a title (camera folders, dates, app defaults)
+pub fn is_generic_folder_name(name: &str) -> bool {
+    GENERIC_FOLDER_NAMES.contains(&name.to_lowercase().as_str())
+        || DCF_FOLDER.is_match(name)
+        || name
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, '-' | '_' | '.' | ' '))
+}
+
+/// Plex/Jellyfin home video location: `Home Videos/2023/2023-05-14 Title.ext`
+pub fn home_video_destination(
+    base: &Path,
+    source_path: &Path,
+    datetime: &DateTime<Local>,
+    title: &str,
+) -> (PathBuf, OsString) {
+    let dir = base.join("Home Videos").join(datetime.year().to_string());
+
+    let mut name = OsString::from(format!("{} {}", datetime.format("%Y-%m-%d"), title.trim()));
+    if let Some(ext) = source_path.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+
+    (dir, name)
+}
+
+/// Ready-made settings matching what popular photo servers expect from an external library
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// timekeeper's own defaults: `2023/May/IMG_1234.jpg`
+    Plain,
+    /// Immich's default storage template: `2023/2023-05-14/IMG_1234.jpg` with XMP sidecars
+    Immich,
+    /// PhotoPrism originals: `2023/05/20230514_102345_IMG_1234.jpg` with XMP sidecars
+    PhotoPrism,
+    /// digiKam date-based albums: `2023/05/14/IMG_1234.jpg` with XMP sidecars
+    DigiKam,
+    /// Plex/Jellyfin: videos as `Home Videos/2023/2023-05-14 Title.mp4`, photos in the date tree
+    HomeVideos,
+}
+
+impl Preset {
+    pub fn layout(&self) -> BuiltinLayout {
+        match self {
+            Preset::Plain | Preset::HomeVideos => BuiltinLayout::YearMonthName,
+            Preset::Immich => BuiltinLayout::YearDate,
+            Preset::PhotoPrism => BuiltinLayout::YearMonthNumber,
+            Preset::DigiKam => BuiltinLayout::YearMonthDay,
+        }
+    }
+
+    pub fn naming(&self) -> FileNaming {
+        match self {
+            Preset::PhotoPrism => FileNaming::DatePrefix,
+            Preset::Plain | Preset::Immich | Preset::DigiKam | Preset::HomeVideos => {
+                FileNaming::Keep
+            }
+        }
+    }
+
+    /// Whether XMP sidecars travel with their media file
+    pub fn sidecars(&self) -> bool {
+        !matches!(self, Preset::Plain | Preset::HomeVideos)
+    }
+
+    /// Whether videos go to a `Home Videos` library instead of the date tree
+    pub fn home_videos(&self) -> bool {
+        matches!(self, Preset::HomeVideos)
+    }
+}
+
+impl std::str::FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "plain" => Ok(Preset::Plain),
+            "immich" => Ok(Preset::Immich),
+            "photoprism" => Ok(Preset::PhotoPrism),
+            "digikam" => Ok(Preset::DigiKam),
+            "plex" | "jellyfin" => Ok(Preset::HomeVideos),
+            _ => Err(format!(
+                "unknown preset '{}' (expected plain, immich, photoprism, digikam, plex or jellyfin)",
+                s
+            )),
+        }
+    }
+}
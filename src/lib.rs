@@ -1,22 +1,228 @@
+pub mod analyze;
+pub mod android;
+pub mod bench;
+pub mod cache;
+pub mod clock;
+pub mod cloud;
+pub mod color;
+pub mod config;
+pub mod copy_report;
+pub mod dryrun;
+pub mod dump;
+pub mod dupes;
+pub mod error;
 pub mod exiftool;
+pub mod extract;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod filter;
+pub mod hooks;
+pub mod import;
+pub mod layout;
+pub mod localtime;
+pub mod logfile;
+pub mod manifest;
+pub mod merge;
+pub mod messaging;
 pub mod metadata;
+pub mod mirror;
+pub mod motion;
+#[cfg(feature = "notify")]
+pub mod notify;
+pub mod outcome;
+pub mod photos;
+#[cfg(feature = "pick")]
+pub mod pick;
+mod pipeline;
+pub mod progress;
+pub mod remote;
+pub mod removal;
+pub mod report;
+mod route;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod schedule;
+#[cfg(unix)]
+pub mod serve;
+pub mod settings;
+#[cfg(feature = "sftp")]
+pub mod sftp;
+pub mod smb;
+pub mod source;
+pub mod space;
+pub mod stability;
 pub mod stats;
+pub mod status;
+pub mod template;
+mod transfer;
+pub mod trial;
+mod visited;
+#[cfg(feature = "webdav")]
+pub mod webdav;
+#[cfg(feature = "webhook")]
+pub mod webhook;
+pub mod xmp;
 
+pub use error::TimekeeperError;
 pub use exiftool::ExifToolError;
 
-use crate::metadata::{is_media_file, process_file_with_fallback, process_with_exiftool};
+use crate::android::TrashedFiles;
+use crate::cache::MetadataCache;
+use crate::clock::ClockOffsets;
+use crate::copy_report::CopyReport;
+use crate::dump::MetadataDump;
+use crate::error::Result;
+use crate::exiftool::ExifWriter;
+use crate::extract::{DateExtractor, ExtractorChain};
+use crate::filter::{Area, FileFilter};
+use crate::hooks::ExecHooks;
+use crate::layout::{BuiltinLayout, FileNaming, Layout, Preset};
+use crate::manifest::{HashAlgorithm, Manifests};
+use crate::metadata::{PlaceOptions, PlacedSources, is_media_file, is_organized_folder};
+use crate::mirror::MirrorIndex;
+use crate::outcome::{Action, FileOutcome};
+use crate::progress::ProgressObserver;
+use crate::remote::RemoteStore;
+use crate::route::{BytesPerRoot, Destinations};
 use crate::stats::Stats;
-use rayon::prelude::*;
+use crate::template::{SequenceScope, Sequences};
+use crate::visited::{OrganizedFolders, VisitedInodes, is_walk_loop};
+use crossbeam_channel::Sender;
+use std::collections::HashSet;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Name of the gitignore-style file that excludes paths from a run.
+/// Honored at the source root and in any nested directory.
+pub const IGNORE_FILE_NAME: &str = ".timekeeperignore";
+
+/// How a run ended, reported by completion notifications
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunOutcome {
+    Finished,
+    Stopped,
+    Failed,
+}
+
+/// How planned files are written to the destination
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// A small pool of threads, each copying one file at a time
+    #[default]
+    Threads,
+    /// A tokio runtime keeping many transfers in flight, for remote and network destinations
+    #[cfg(feature = "async")]
+    Async,
+}
+
+impl std::str::FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "threads" => Ok(Backend::Threads),
+            #[cfg(feature = "async")]
+            "async" => Ok(Backend::Async),
+            #[cfg(not(feature = "async"))]
+            "async" => {
+                Err("the async backend needs timekeeper built with the async feature".into())
+            }
+            _ => Err(format!(
+                "unknown backend '{}' (expected threads or async)",
+                s
+            )),
+        }
+    }
+}
+
+/// The order the planner takes files in: which get their destination (and name
+/// suffix or `{seq}` number on a clash) first, and the order they are written in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProcessingOrder {
+    /// By source path, also walking folders sorted by name, for runs that come out
+    /// the same way every time. Files are planned and written as they are dated.
+    Path,
+    /// By capture date, oldest first
+    #[default]
+    Date,
+    /// By size, smallest first
+    Size,
+    /// Shuffled anew on every run
+    Random,
+}
+
+impl std::str::FromStr for ProcessingOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "path" => Ok(ProcessingOrder::Path),
+            "date" => Ok(ProcessingOrder::Date),
+            "size" => Ok(ProcessingOrder::Size),
+            "random" => Ok(ProcessingOrder::Random),
+            _ => Err(format!(
+                "unknown order '{}' (expected path, date, size or random)",
+                s
+            )),
+        }
+    }
+}
 
 pub struct Organizer {
     pub source: PathBuf,
     pub destination: PathBuf,
+    pub video_destination: Option<PathBuf>,
+    pub large_files: Option<(u64, PathBuf)>,
     pub dry_run: bool,
     pub use_copy: bool,
     pub exiftool_path: Option<PathBuf>,
+    /// Read video dates as the UTC times they are, see `with_quicktime_utc`
+    pub quicktime_utc: bool,
+    pub separate_received: bool,
+    pub android_trashed: TrashedFiles,
+    pub check_free_space: bool,
+    pub warn_out_of_space: bool,
+    pub min_age: Option<Duration>,
+    pub stable_for: Option<Duration>,
+    pub hydrate_placeholders: bool,
+    pub skip_organized: bool,
+    /// Walk into symlinked folders and organize symlinked files
+    pub follow_symlinks: bool,
+    pub filter: FileFilter,
+    pub layout: Arc<dyn Layout>,
+    pub naming: FileNaming,
+    pub sequence_scope: SequenceScope,
+    pub sidecars: bool,
+    pub motion_clips: bool,
+    pub home_videos: bool,
+    pub keep_album: bool,
+    pub preserve_structure: bool,
+    pub rename_only: bool,
+    pub selects_rating: Option<i32>,
+    pub cache: Option<Arc<MetadataCache>>,
+    pub metadata_dump: Option<Arc<MetadataDump>>,
+    pub manifest: Option<Arc<Manifests>>,
+    pub remote: Option<Arc<dyn RemoteStore>>,
+    pub verify: bool,
+    pub copy_report: Option<Arc<CopyReport>>,
+    pub placed: Option<Arc<PlacedSources>>,
+    pub mirror: Option<Arc<MirrorIndex>>,
+    pub exif_writer: Option<ExifWriter>,
+    pub sync_mtime: bool,
+    pub use_trash: bool,
+    pub backend: Backend,
+    pub order: ProcessingOrder,
+    pub queue_size: usize,
+    pub itemize: bool,
+    pub group_by_dest: bool,
+    pub max_errors: Option<usize>,
+    pub observer: Option<Arc<dyn ProgressObserver>>,
+    pub extractors: Vec<Arc<dyn DateExtractor>>,
+    pub clock_offsets: Option<Arc<ClockOffsets>>,
+    pub exec_hooks: Option<Arc<ExecHooks>>,
 }
 
 impl Organizer {
@@ -24,9 +230,53 @@ impl Organizer {
         Self {
             source,
             destination,
+            video_destination: None,
+            large_files: None,
             dry_run,
             use_copy: true, // Default to copy
             exiftool_path: None,
+            quicktime_utc: true,
+            separate_received: false,
+            android_trashed: TrashedFiles::default(),
+            check_free_space: true,
+            warn_out_of_space: false,
+            min_age: None,
+            stable_for: None,
+            hydrate_placeholders: false,
+            skip_organized: false,
+            follow_symlinks: false,
+            filter: FileFilter::default(),
+            layout: Arc::new(BuiltinLayout::default()),
+            naming: FileNaming::default(),
+            sequence_scope: SequenceScope::default(),
+            sidecars: false,
+            motion_clips: false,
+            home_videos: false,
+            keep_album: false,
+            preserve_structure: false,
+            rename_only: false,
+            selects_rating: None,
+            cache: None,
+            metadata_dump: None,
+            manifest: None,
+            remote: None,
+            verify: false,
+            copy_report: None,
+            placed: None,
+            mirror: None,
+            exif_writer: None,
+            sync_mtime: false,
+            use_trash: false,
+            backend: Backend::default(),
+            order: ProcessingOrder::default(),
+            queue_size: pipeline::DEFAULT_QUEUE_SIZE,
+            itemize: false,
+            group_by_dest: false,
+            max_errors: None,
+            observer: None,
+            extractors: Vec::new(),
+            clock_offsets: None,
+            exec_hooks: None,
         }
     }
 
@@ -40,111 +290,702 @@ impl Organizer {
         self
     }
 
-    pub fn run(
-        &self,
-        stats: Arc<Stats>,
-        terminate_flag: Arc<AtomicBool>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let exiftool_path = exiftool::get_exiftool_path(self.exiftool_path.clone())?;
+    /// Whether MP4/MOV dates are converted from UTC to local time (the default).
+    /// Turn it off for cameras that store local time in them against the spec.
+    pub fn with_quicktime_utc(mut self, quicktime_utc: bool) -> Self {
+        self.quicktime_utc = quicktime_utc;
+        self
+    }
 
-        if self.source.is_dir() {
-            self.count_media_files(&stats)?;
-            self.process_directory(&exiftool_path, &stats, &terminate_flag)
+    /// Send videos to their own root, e.g. on a bigger disk, laid out like the photos
+    pub fn with_video_destination(mut self, video_destination: PathBuf) -> Self {
+        self.video_destination = Some(video_destination);
+        self
+    }
+
+    /// Send files of at least `threshold` bytes to their own root, e.g. an archive
+    /// volume for 4K video and scans. Takes precedence over `with_video_destination`.
+    pub fn with_large_files(mut self, threshold: u64, destination: PathBuf) -> Self {
+        self.large_files = Some((threshold, destination));
+        self
+    }
+
+    pub fn with_separate_received(mut self, separate_received: bool) -> Self {
+        self.separate_received = separate_received;
+        self
+    }
+
+    /// Leave out the files in an Android phone's trash, or organize them into a
+    /// `Trash Review` folder
+    pub fn with_android_trashed(mut self, android_trashed: TrashedFiles) -> Self {
+        self.android_trashed = android_trashed;
+        self
+    }
+
+    pub fn with_free_space_check(mut self, check_free_space: bool) -> Self {
+        self.check_free_space = check_free_space;
+        self
+    }
+
+    /// Only warn when the run won't fit a destination root and copy until it is full
+    pub fn with_out_of_space_warning(mut self, warn_out_of_space: bool) -> Self {
+        self.warn_out_of_space = warn_out_of_space;
+        self
+    }
+
+    pub fn with_min_age(mut self, min_age: Duration) -> Self {
+        self.min_age = Some(min_age);
+        self
+    }
+
+    pub fn with_stable_for(mut self, stable_for: Duration) -> Self {
+        self.stable_for = Some(stable_for);
+        self
+    }
+
+    /// Download online-only cloud files before organizing them rather than skipping
+    /// them (see `cloud::is_placeholder`)
+    pub fn with_hydrate_placeholders(mut self, hydrate_placeholders: bool) -> Self {
+        self.hydrate_placeholders = hydrate_placeholders;
+        self
+    }
+
+    pub fn with_skip_organized(mut self, skip_organized: bool) -> Self {
+        self.skip_organized = skip_organized;
+        self
+    }
+
+    pub fn with_follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    pub fn with_layout(mut self, layout: Arc<dyn Layout>) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn with_naming(mut self, naming: FileNaming) -> Self {
+        self.naming = naming;
+        self
+    }
+
+    /// What restarts `{seq}` numbering in rename templates
+    pub fn with_sequence_scope(mut self, scope: SequenceScope) -> Self {
+        self.sequence_scope = scope;
+        self
+    }
+
+    pub fn with_sidecars(mut self, sidecars: bool) -> Self {
+        self.sidecars = sidecars;
+        self
+    }
+
+    /// Also save the video inside motion photos next to them (see `motion`)
+    pub fn with_motion_clips(mut self, motion_clips: bool) -> Self {
+        self.motion_clips = motion_clips;
+        self
+    }
+
+    pub fn with_home_videos(mut self, home_videos: bool) -> Self {
+        self.home_videos = home_videos;
+        self
+    }
+
+    /// Keep human-named source folders as albums below the date folders:
+    /// `2019 Wedding/IMG_1.jpg` goes to `2019/June/2019 Wedding/IMG_1.jpg`
+    pub fn with_keep_album(mut self, keep_album: bool) -> Self {
+        self.keep_album = keep_album;
+        self
+    }
+
+    /// Recreate the source's folders below the date folders:
+    /// `Trips/Japan/IMG_1.jpg` goes to `2023/March/Trips/Japan/IMG_1.jpg`
+    pub fn with_preserve_structure(mut self, preserve_structure: bool) -> Self {
+        self.preserve_structure = preserve_structure;
+        self
+    }
+
+    /// Only organize files tagged with at least one of `keywords` (XMP or EXIF)
+    pub fn with_required_keywords(mut self, keywords: Vec<String>) -> Self {
+        self.filter.required_keywords = keywords;
+        self
+    }
+
+    /// Leave files tagged with any of `keywords` (XMP or EXIF) where they are
+    pub fn with_excluded_keywords(mut self, keywords: Vec<String>) -> Self {
+        self.filter.excluded_keywords = keywords;
+        self
+    }
+
+    /// Only organize files whose GPS tags place them inside `area`
+    pub fn with_area(mut self, area: Area) -> Self {
+        self.filter.area = Some(area);
+        self
+    }
+
+    /// Only organize these files of the source, leaving the rest where they are
+    pub fn with_only_files(mut self, files: Arc<HashSet<PathBuf>>) -> Self {
+        self.filter.only = Some(files);
+        self
+    }
+
+    /// Edit organized files' metadata: write dates found in file names or XMP
+    /// sidecars into them, strip their GPS tags
+    pub fn with_exif_writer(mut self, writer: ExifWriter) -> Self {
+        self.exif_writer = Some(writer);
+        self
+    }
+
+    /// Set organized files' modification time to their capture date
+    pub fn with_sync_mtime(mut self, sync_mtime: bool) -> Self {
+        self.sync_mtime = sync_mtime;
+        self
+    }
+
+    /// Move the originals a move deletes to the system trash, so they can be restored
+    pub fn with_trash(mut self, use_trash: bool) -> Self {
+        self.use_trash = use_trash;
+        self
+    }
+
+    /// Leave files in their folders and only rename them with the naming, e.g.
+    /// `FileNaming::DatePrefix`. Clashing names get the usual `_1` suffix.
+    pub fn with_rename_only(mut self, rename_only: bool) -> Self {
+        self.rename_only = rename_only;
+        self
+    }
+
+    /// Place files with an XMP rating of at least `min_rating` stars in a `Selects`
+    /// tree of their own: `Selects/2023/May/IMG_1.jpg`
+    pub fn with_selects_rating(mut self, min_rating: i32) -> Self {
+        self.selects_rating = Some(min_rating);
+        self
+    }
+
+    pub fn with_cache(mut self, cache: MetadataCache) -> Self {
+        self.cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Take dates from an imported exiftool JSON dump instead of running exiftool
+    pub fn with_metadata_dump(mut self, dump: MetadataDump) -> Self {
+        self.metadata_dump = Some(Arc::new(dump));
+        self
+    }
+
+    /// Hash every placed file into an archival manifest at the root it was placed under
+    pub fn with_manifest(mut self, manifests: Manifests) -> Self {
+        self.manifest = Some(Arc::new(manifests));
+        self
+    }
+
+    /// Open the manifests `with_manifest` takes, one for each destination root set so far
+    pub fn open_manifests(&self, algorithm: HashAlgorithm) -> Result<Manifests> {
+        Manifests::open(self.destinations().all(), algorithm)
+    }
+
+    /// Upload into a remote store instead of a local directory.
+    /// Local free space no longer matters, so the space check is turned off.
+    pub fn with_remote(mut self, remote: Arc<dyn RemoteStore>) -> Self {
+        self.remote = Some(remote);
+        self.check_free_space = false;
+        self
+    }
+
+    /// Hash every copy after writing it and fail the file when it doesn't match its source
+    pub fn with_verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Copy and verify every file, never deleting a source, and list each copy with
+    /// its hash and both locations in `report`
+    pub fn with_copy_report(mut self, report: CopyReport) -> Self {
+        self.copy_report = Some(Arc::new(report));
+        self.use_copy = true;
+        self.verify = true;
+        self
+    }
+
+    /// Record the source of every placed file into `placed`
+    pub fn with_placed_log(mut self, placed: Arc<PlacedSources>) -> Self {
+        self.placed = Some(placed);
+        self
+    }
+
+    /// Keep `mirror` up to date: files it already holds are left out of the run and
+    /// every copied file is added to it
+    pub fn with_mirror(mut self, mirror: Arc<MirrorIndex>) -> Self {
+        self.mirror = Some(mirror);
+        self
+    }
+
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Order files are planned in. `{seq}` templates always number in date order.
+    pub fn with_order(mut self, order: ProcessingOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// The order the planner takes files in: the chosen one, or date order when
+    /// names are numbered with `{seq}`
+    pub(crate) fn planning_order(&self) -> ProcessingOrder {
+        if self.naming.uses_sequence() {
+            ProcessingOrder::Date
         } else {
-            stats.total.store(1, Ordering::SeqCst);
-            self.process_single_file(&exiftool_path, &self.source, &stats, &terminate_flag)
+            self.order
         }
     }
 
-    fn count_media_files(&self, stats: &Arc<Stats>) -> Result<(), Box<dyn std::error::Error>> {
-        for entry in walkdir::WalkDir::new(&self.source) {
-            let entry = entry?;
-            if entry.file_type().is_file() {
-                if let Some(path_str) = entry.path().to_str() {
-                    if is_media_file(path_str) {
-                        stats.total.fetch_add(1, Ordering::SeqCst);
+    /// Files held between two processing stages, bounding memory on huge runs
+    pub fn with_queue_size(mut self, queue_size: usize) -> Self {
+        self.queue_size = queue_size.max(1);
+        self
+    }
+
+    /// Print each planned file as one tab-separated line (see `--itemize`)
+    pub fn with_itemize(mut self, itemize: bool) -> Self {
+        self.itemize = itemize;
+        self
+    }
+
+    /// Leave the planned files of a dry run out of the per-file lines, for a
+    /// `DryRunSummary` listing them under their destination folders instead
+    pub fn with_group_by_dest(mut self, group_by_dest: bool) -> Self {
+        self.group_by_dest = group_by_dest;
+        self
+    }
+
+    /// Stop the run once this many files have failed
+    pub fn with_max_errors(mut self, max_errors: usize) -> Self {
+        self.max_errors = Some(max_errors.max(1));
+        self
+    }
+
+    /// Report the run's progress to `observer`
+    pub fn with_observer(mut self, observer: Arc<dyn ProgressObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Register a date extractor. Registered extractors are asked before the built-in
+    /// ones, in the order they were added.
+    pub fn with_extractor(mut self, extractor: Arc<dyn DateExtractor>) -> Self {
+        self.extractors.push(extractor);
+        self
+    }
+
+    /// Shift the metadata dates of cameras whose clock was off
+    pub fn with_clock_offsets(mut self, offsets: ClockOffsets) -> Self {
+        self.clock_offsets = (!offsets.is_empty()).then(|| Arc::new(offsets));
+        self
+    }
+
+    /// Run user commands before and after each file is placed. Dry runs skip them.
+    pub fn with_exec_hooks(mut self, hooks: ExecHooks) -> Self {
+        self.exec_hooks = Some(Arc::new(hooks));
+        self
+    }
+
+    /// Apply a media server preset's layout, naming, sidecar and video handling
+    pub fn with_preset(self, preset: Preset) -> Self {
+        self.with_layout(Arc::new(preset.layout()))
+            .with_naming(preset.naming())
+            .with_sidecars(preset.sidecars())
+            .with_home_videos(preset.home_videos())
+    }
+
+    fn place_options(&self, terminate_flag: &Arc<AtomicBool>) -> PlaceOptions {
+        PlaceOptions {
+            layout: Arc::clone(&self.layout),
+            naming: self.naming.clone(),
+            sidecars: self.sidecars,
+            motion_clips: self.motion_clips,
+            home_videos: self.home_videos,
+            keep_album: self.keep_album,
+            preserve_structure: self.preserve_structure,
+            rename_only: self.rename_only,
+            selects_rating: self.selects_rating,
+            source_root: self.source.clone(),
+            dry_run: self.dry_run,
+            use_copy: self.use_copy,
+            manifest: self.manifest.clone(),
+            remote: self.remote.clone(),
+            verify: self.verify,
+            copy_report: self.copy_report.clone(),
+            placed: self.placed.clone(),
+            mirror: self.mirror.clone(),
+            exif_writer: self.exif_writer.clone(),
+            sync_mtime: self.sync_mtime,
+            use_trash: self.use_trash,
+            sequences: Arc::new(Sequences::new(self.sequence_scope)),
+            terminate_flag: Some(Arc::clone(terminate_flag)),
+            observer: self.observer.clone(),
+        }
+    }
+
+    /// Organize the source, printing a line for every file as it is done
+    pub fn run(&self, stats: Arc<Stats>, terminate_flag: Arc<AtomicBool>) -> Result<()> {
+        let (outcomes, finished) = crossbeam_channel::bounded::<FileOutcome>(self.queue_size);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for outcome in finished {
+                    if self.dry_run
+                        && self.group_by_dest
+                        && matches!(outcome.action, Action::Copy | Action::Move)
+                    {
+                        continue;
                     }
+                    outcome.print(self.dry_run, self.itemize);
                 }
+            });
+            self.run_with_outcomes(stats, terminate_flag, outcomes)
+        })
+    }
+
+    /// Organize the source, sending the outcome of every file to `outcomes` instead
+    /// of printing it. Run-level problems (no exiftool, no space) are returned.
+    pub fn run_with_outcomes(
+        &self,
+        stats: Arc<Stats>,
+        terminate_flag: Arc<AtomicBool>,
+        outcomes: Sender<FileOutcome>,
+    ) -> Result<()> {
+        let result = self
+            .extractor_chain()
+            .map_err(Into::into)
+            .and_then(|extractors| self.organize(&extractors, &stats, &terminate_flag, &outcomes));
+
+        // Whatever was extracted before a failure is still worth keeping
+        if let Some(cache) = &self.cache
+            && let Err(e) = cache.save()
+        {
+            eprintln!("[WARN] Could not save metadata cache: {}", e);
+        }
+        if let Some(report) = &self.copy_report
+            && let Err(e) = report.save()
+        {
+            eprintln!(
+                "[WARN] Could not write copy report {}: {}",
+                report.path().display(),
+                e
+            );
+        }
+        if let Some(mirror) = &self.mirror
+            && let Err(e) = mirror.save()
+        {
+            eprintln!("[WARN] Could not write the mirror index: {}", e);
+        }
+        for manifest in self.manifest.iter().flat_map(|manifests| manifests.iter()) {
+            if let Err(e) = manifest.save() {
+                eprintln!(
+                    "[WARN] Could not write manifest {}: {}",
+                    manifest.path().display(),
+                    e
+                );
             }
         }
-        Ok(())
+        if let Some(observer) = &self.observer {
+            observer.on_finish(&stats.snapshot());
+        }
+
+        result
+    }
+
+    /// Where dates come from: the registered extractors first, then the imported
+    /// dump or the built-in chain ending in exiftool through the cache, with the
+    /// cameras' clock corrections
+    fn extractor_chain(&self) -> Result<ExtractorChain, ExifToolError> {
+        let chain = match &self.metadata_dump {
+            Some(dump) => ExtractorChain::from_dump(Arc::clone(dump)),
+            None => ExtractorChain::standard(
+                exiftool::get_exiftool_path(self.exiftool_path.clone())?,
+                self.cache.clone(),
+                self.quicktime_utc,
+            ),
+        };
+        let chain = chain.with_first(&self.extractors);
+        Ok(match &self.clock_offsets {
+            Some(offsets) => chain.with_clock_offsets(Arc::clone(offsets)),
+            None => chain,
+        })
     }
 
-    fn process_directory(
+    /// Organize everything below the source, or the source itself when it is a file
+    fn organize(
         &self,
-        exiftool_path: &PathBuf,
+        extractors: &ExtractorChain,
         stats: &Arc<Stats>,
         terminate_flag: &Arc<AtomicBool>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let entries: Vec<_> = walkdir::WalkDir::new(&self.source)
-            .into_iter()
-            .filter_map(Result::ok)
-            .filter(|e| e.file_type().is_file())
-            .collect();
-
-        entries.par_iter().for_each(|entry| {
-            if terminate_flag.load(Ordering::SeqCst) {
-                return;
+        outcomes: &Sender<FileOutcome>,
+    ) -> Result<()> {
+        if self.source.is_dir() {
+            for nested in self.nested_destinations() {
+                println!(
+                    "[WARN] Destination '{}' is inside the source, excluding it from the scan",
+                    nested.display()
+                );
             }
+            self.count_media_files(extractors, stats)
+                .and_then(|(bytes, organized)| {
+                    stats
+                        .bytes_total
+                        .store(bytes.values().sum(), Ordering::SeqCst);
+                    // The other orders check what they planned, leaving out files
+                    // already in place
+                    if self.planning_order() == ProcessingOrder::Path {
+                        self.check_free_space(&bytes)?;
+                    }
+                    // Walk again rather than holding millions of paths in memory
+                    let files =
+                        self.media_files(extractors, &organized, true)
+                            .filter_map(|entry| {
+                                entry
+                                    .map_err(|e| {
+                                        eprintln!("[WARN] Could not read source entry: {}", e)
+                                    })
+                                    .ok()
+                                    .map(ignore::DirEntry::into_path)
+                            });
+                    self.run_pipeline(extractors, files, stats, terminate_flag, outcomes)
+                })
+        } else {
+            stats.total.store(1, Ordering::SeqCst);
+            std::fs::metadata(&self.source)
+                .map_err(Into::into)
+                .and_then(|file| {
+                    stats.bytes_total.store(file.len(), Ordering::SeqCst);
+                    if self.planning_order() == ProcessingOrder::Path {
+                        let root = self
+                            .destinations()
+                            .for_file(&self.source, file.len())
+                            .to_path_buf();
+                        self.check_free_space(&BytesPerRoot::from([(root, file.len())]))?;
+                    }
+                    let files = std::iter::once(self.source.clone());
+                    self.run_pipeline(extractors, files, stats, terminate_flag, outcomes)
+                })
+        }
+    }
+
+    /// Walk the source tree, leaving out anything matched by a `.timekeeperignore`,
+    /// the destination itself when it lives inside the source, anything already
+    /// reached through another symlink or bind mount and, with `skip_organized`,
+    /// folders that already follow the destination layout (reported when
+    /// `announce` is set, and only sampled unless `organized` knows them already)
+    fn walk_source(
+        &self,
+        extractors: &ExtractorChain,
+        organized: &OrganizedFolders,
+        announce: bool,
+    ) -> ignore::Walk {
+        let nested = self.nested_destinations();
+        let skip_organized = self.skip_organized;
+        let layout = Arc::clone(&self.layout);
+        let keep_album = self.keep_album;
+        let extractors = extractors.clone();
+        let follow_symlinks = self.follow_symlinks;
+        let organized = organized.clone();
+        let visited = VisitedInodes::default();
+        // The root itself never goes through the filter
+        visited.first_visit(&self.source);
 
-            if let Some(path_str) = entry.path().to_str() {
-                if is_media_file(path_str) {
-                    if let Err(e) = self.process_single_file(
-                        exiftool_path,
-                        &entry.path().to_path_buf(),
-                        stats,
-                        terminate_flag,
-                    ) {
-                        eprintln!("Error processing {}: {}", entry.path().display(), e);
-                        stats.errors.fetch_add(1, Ordering::SeqCst);
+        let mut walker = ignore::WalkBuilder::new(&self.source);
+        walker
+            .standard_filters(false)
+            .follow_links(follow_symlinks)
+            .add_custom_ignore_filename(IGNORE_FILE_NAME)
+            .filter_entry(move |entry| {
+                if nested.iter().any(|n| n == entry.path()) {
+                    return false;
+                }
+                // Counted and organized once, and no looping through a cycle. Links
+                // that aren't followed are left out later, their target must not be.
+                if (follow_symlinks || !entry.path_is_symlink())
+                    && !visited.first_visit(entry.path())
+                {
+                    if announce {
+                        println!(
+                            "{}",
+                            color::cyan(&format!(
+                                "[SKIP] Already reached through another path: {}",
+                                entry.path().display()
+                            ))
+                        );
                     }
+                    return false;
                 }
-            }
-        });
+                if skip_organized
+                    && entry.file_type().is_some_and(|t| t.is_dir())
+                    && organized.contains(entry.path(), || {
+                        is_organized_folder(&extractors, entry.path(), layout.as_ref(), keep_album)
+                    })
+                {
+                    if announce {
+                        println!(
+                            "{}",
+                            color::cyan(&format!(
+                                "[SKIP] Already organized: {}",
+                                entry.path().display()
+                            ))
+                        );
+                    }
+                    return false;
+                }
+                true
+            });
+        // Otherwise folders list in whatever order the file system keeps them
+        if self.planning_order() == ProcessingOrder::Path {
+            walker.sort_by_file_name(|a, b| a.cmp(b));
+        }
+        walker.build()
+    }
 
-        Ok(())
+    /// The destinations expressed under the source path when they are a subdirectory
+    /// of it. Organizing in place (destination == source) is not considered nested.
+    fn nested_destinations(&self) -> Vec<PathBuf> {
+        if self.remote.is_some() {
+            return Vec::new();
+        }
+        let Ok(source) = self.source.canonicalize() else {
+            return Vec::new();
+        };
+        self.destinations()
+            .all()
+            .filter_map(|destination| {
+                let absolute = match destination.canonicalize() {
+                    Ok(path) => path,
+                    Err(_) => std::path::absolute(destination).ok()?,
+                };
+                let relative = absolute.strip_prefix(&source).ok()?;
+                (!relative.as_os_str().is_empty()).then(|| self.source.join(relative))
+            })
+            .collect()
+    }
+
+    /// Media files below the source that pass the filter, in walk order
+    fn media_files(
+        &self,
+        extractors: &ExtractorChain,
+        organized: &OrganizedFolders,
+        announce: bool,
+    ) -> impl Iterator<Item = Result<ignore::DirEntry, ignore::Error>> + Send + use<> {
+        let filter = self.filter.clone();
+        let mirror = self.mirror.clone();
+        let android_trashed = self.android_trashed;
+        self.walk_source(extractors, organized, announce)
+            .filter(move |entry| match entry {
+                Ok(entry) => {
+                    entry.file_type().is_some_and(|t| t.is_file())
+                        && entry.path().to_str().is_some_and(is_media_file)
+                        && !android_leftover(entry.path(), android_trashed, announce)
+                        && filter.matches(entry.path())
+                        && !mirror
+                            .as_ref()
+                            .is_some_and(|mirror| mirror.contains(entry.path()))
+                }
+                Err(e) if is_walk_loop(e) => {
+                    if announce {
+                        println!("{}", color::cyan(&format!("[SKIP] {}", e)));
+                    }
+                    false
+                }
+                Err(_) => true,
+            })
+    }
+
+    /// The run's destination roots
+    pub(crate) fn destinations(&self) -> Destinations {
+        Destinations {
+            main: self.destination.clone(),
+            videos: self.video_destination.clone(),
+            large: self.large_files.clone(),
+        }
+    }
+
+    /// How many media files a run would take from the source and their combined size,
+    /// without touching any of them
+    pub fn count_media(&self) -> Result<(usize, u64)> {
+        if !self.source.is_dir() {
+            return Ok((1, std::fs::metadata(&self.source)?.len()));
+        }
+        let stats = Arc::new(Stats::new());
+        let (bytes, _) = self.count_media_files(&self.extractor_chain()?, &stats)?;
+        Ok((stats.total.load(Ordering::SeqCst), bytes.values().sum()))
     }
 
-    fn process_single_file(
+    /// Count media files, returning their combined size in bytes per destination root
+    /// and the organized folders the walk found, for the next walk to reuse
+    fn count_media_files(
         &self,
-        exiftool_path: &PathBuf,
-        source_path: &PathBuf,
+        extractors: &ExtractorChain,
         stats: &Arc<Stats>,
-        terminate_flag: &Arc<AtomicBool>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        if terminate_flag.load(Ordering::SeqCst) {
-            return Ok(());
+    ) -> Result<(BytesPerRoot, OrganizedFolders)> {
+        let destinations = self.destinations();
+        let organized = OrganizedFolders::default();
+        let mut bytes = BytesPerRoot::new();
+        for entry in self.media_files(extractors, &organized, false) {
+            let entry = entry?;
+            stats.total.fetch_add(1, Ordering::SeqCst);
+            let len = entry.metadata()?.len();
+            let root = destinations.for_file(entry.path(), len);
+            *bytes.entry(root.to_path_buf()).or_default() += len;
         }
+        Ok((bytes, organized.known()))
+    }
 
-        stats.processed.fetch_add(1, Ordering::SeqCst);
+    /// Compare the bytes a copy-mode run will write against each destination root's
+    /// free space. Dry runs and `with_out_of_space_warning` only warn, real runs
+    /// abort before touching any file.
+    fn check_free_space(&self, bytes: &BytesPerRoot) -> Result<()> {
+        if !self.use_copy || !self.check_free_space {
+            return Ok(());
+        }
 
-        let filename = source_path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("unknown");
+        bytes
+            .iter()
+            .try_for_each(|(root, required)| self.check_free_space_at(root, *required))
+    }
 
-        match process_with_exiftool(
-            exiftool_path,
-            source_path,
-            &self.destination,
-            self.dry_run,
-            self.use_copy,
-            stats,
-        ) {
-            Ok(()) => {
-                stats.exif_count.fetch_add(1, Ordering::SeqCst);
-            }
-            Err(_) => {
-                let _ = process_file_with_fallback(
-                    source_path,
-                    &self.destination,
-                    self.dry_run,
-                    self.use_copy,
-                );
-                stats.fallback_count.fetch_add(1, Ordering::SeqCst);
+    fn check_free_space_at(&self, destination: &std::path::Path, required: u64) -> Result<()> {
+        let available = space::available_space(destination)?;
+        if required > available {
+            let error = TimekeeperError::OutOfSpace {
+                path: destination.to_path_buf(),
+                required,
+                available,
+            };
+            if self.dry_run || self.warn_out_of_space {
+                println!("[WARN] {}", error);
+            } else {
+                return Err(error);
             }
         }
 
         Ok(())
     }
 }
+
+/// Whether a file is one Android is still writing, or one in its trash that
+/// `TrashedFiles::Ignore` leaves out (reported when `announce` is set)
+fn android_leftover(path: &std::path::Path, trashed: TrashedFiles, announce: bool) -> bool {
+    let reason = if android::is_pending(path) {
+        "Android file still being written"
+    } else if android::is_trashed(path) && trashed == TrashedFiles::Ignore {
+        "In the Android trash"
+    } else {
+        return false;
+    };
+    if announce {
+        println!(
+            "{}",
+            color::cyan(&format!("[SKIP] {}: {}", reason, path.display()))
+        );
+    }
+    true
+}
@@ -0,0 +1,140 @@
+use chrono::{DateTime, Local, LocalResult, NaiveDateTime, TimeDelta, TimeZone};
+use std::cell::Cell;
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+static POLICY: AtomicU8 = AtomicU8::new(DstPolicy::Earliest as u8);
+
+thread_local! {
+    /// The last date resolved on this thread that fell on a DST change
+    static LAST_ADJUSTMENT: Cell<Option<DstAdjustment>> = const { Cell::new(None) };
+}
+
+/// How to read a wall-clock time without a UTC offset that falls on a daylight saving
+/// change: happening twice when the clocks go back, or not at all when they go forward
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DstPolicy {
+    /// The earlier of the two possible moments
+    #[default]
+    Earliest,
+    /// The later of the two possible moments
+    Latest,
+    /// Read the time as UTC
+    Utc,
+}
+
+impl std::str::FromStr for DstPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "earliest" => Ok(DstPolicy::Earliest),
+            "latest" => Ok(DstPolicy::Latest),
+            "utc" => Ok(DstPolicy::Utc),
+            _ => Err(format!(
+                "unknown DST policy '{}' (expected earliest, latest or utc)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DstPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DstPolicy::Earliest => write!(f, "earliest"),
+            DstPolicy::Latest => write!(f, "latest"),
+            DstPolicy::Utc => write!(f, "utc"),
+        }
+    }
+}
+
+/// Set the DST policy for the rest of the process
+pub fn set_dst_policy(policy: DstPolicy) {
+    POLICY.store(policy as u8, Ordering::SeqCst);
+}
+
+fn dst_policy() -> DstPolicy {
+    match POLICY.load(Ordering::SeqCst) {
+        x if x == DstPolicy::Latest as u8 => DstPolicy::Latest,
+        x if x == DstPolicy::Utc as u8 => DstPolicy::Utc,
+        _ => DstPolicy::Earliest,
+    }
+}
+
+/// A date that fell on a daylight saving change and how it was read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DstAdjustment {
+    pub local: NaiveDateTime,
+    /// Whether the time happened twice rather than not at all
+    pub ambiguous: bool,
+    pub policy: DstPolicy,
+    pub resolved: DateTime<Local>,
+}
+
+impl fmt::Display for DstAdjustment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}, read as {} (--dst-policy {})",
+            self.local,
+            if self.ambiguous {
+                "happened twice when the clocks went back"
+            } else {
+                "never happened, the clocks went forward"
+            },
+            self.resolved.format("%Y-%m-%d %H:%M:%S%:z"),
+            self.policy
+        )
+    }
+}
+
+/// Turn a wall-clock time into a local date, settling times on a daylight saving
+/// change by the DST policy. Such a date is noted for `take_adjustment`.
+pub(crate) fn resolve(local: &NaiveDateTime) -> DateTime<Local> {
+    let (earliest, latest, ambiguous) = match Local.from_local_datetime(local) {
+        LocalResult::Single(datetime) => return datetime,
+        LocalResult::Ambiguous(a, b) => (a.min(b), a.max(b), true),
+        // In the gap, the offsets from before and after the change give two moments
+        LocalResult::None => {
+            let offset = |at: NaiveDateTime| {
+                Local
+                    .from_local_datetime(&at)
+                    .earliest()
+                    .map(|datetime| *datetime.offset())
+            };
+            let before = offset(*local - TimeDelta::days(1));
+            let after = offset(*local + TimeDelta::days(1));
+            let moment = |offset: Option<chrono::FixedOffset>| {
+                offset
+                    .and_then(|offset| local.checked_sub_offset(offset))
+                    .map(|utc| Local.from_utc_datetime(&utc))
+            };
+            match (moment(before), moment(after)) {
+                (Some(a), Some(b)) => (a.min(b), a.max(b), false),
+                _ => return Local.from_utc_datetime(local),
+            }
+        }
+    };
+
+    let policy = dst_policy();
+    let resolved = match policy {
+        DstPolicy::Earliest => earliest,
+        DstPolicy::Latest => latest,
+        DstPolicy::Utc => Local.from_utc_datetime(local),
+    };
+    LAST_ADJUSTMENT.set(Some(DstAdjustment {
+        local: *local,
+        ambiguous,
+        policy,
+        resolved,
+    }));
+    resolved
+}
+
+/// The last date `resolve` settled by the DST policy on this thread, if any, clearing
+/// it. Extraction runs on a single thread per file, so this belongs to the file just
+/// extracted when cleared before.
+pub(crate) fn take_adjustment() -> Option<DstAdjustment> {
+    LAST_ADJUSTMENT.take()
+}
@@ -0,0 +1,146 @@
+use chrono::Local;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use crate::outcome::FileOutcome;
+use crate::progress::ProgressObserver;
+use crate::space::format_bytes;
+use crate::stats::StatsSnapshot;
+
+/// When a log file is set aside for a fresh one
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogRotation {
+    /// Rotate once the log reaches this many bytes
+    pub max_size: Option<u64>,
+    /// Rotate once the log was started this long ago
+    pub max_age: Option<Duration>,
+    /// How many rotated logs to keep as `<log>.1` (newest) to `<log>.<keep>`
+    pub keep: usize,
+}
+
+impl LogRotation {
+    fn is_due(&self, size: u64, started: SystemTime) -> bool {
+        self.max_size.is_some_and(|max| size >= max)
+            || self
+                .max_age
+                .is_some_and(|max| started.elapsed().is_ok_and(|age| age >= max))
+    }
+}
+
+/// A log of every file organized, kept across runs and rotated so a long-lived
+/// `--schedule` process doesn't fill the disk over months
+pub struct LogFile {
+    path: PathBuf,
+    dry_run: bool,
+    rotation: LogRotation,
+    current: Mutex<OpenLog>,
+}
+
+struct OpenLog {
+    file: File,
+    size: u64,
+    started: SystemTime,
+}
+
+impl OpenLog {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let metadata = file.metadata()?;
+        // Where creation times aren't kept, an existing log counts from its last write
+        let started = metadata
+            .created()
+            .or_else(|_| metadata.modified())
+            .unwrap_or_else(|_| SystemTime::now());
+        Ok(OpenLog {
+            file,
+            size: metadata.len(),
+            started,
+        })
+    }
+}
+
+impl LogFile {
+    /// Append to the log at `path`, creating it if needed
+    pub fn open(path: PathBuf, dry_run: bool, rotation: LogRotation) -> io::Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let current = OpenLog::open(&path)?;
+        Ok(LogFile {
+            path,
+            dry_run,
+            rotation,
+            current: Mutex::new(current),
+        })
+    }
+
+    /// Append a timestamped line, rotating the log first when it is due
+    pub fn write_line(&self, line: &str) {
+        let mut current = self.current.lock().unwrap();
+        if current.size > 0
+            && self.rotation.is_due(current.size, current.started)
+            && let Err(e) = self.rotate(&mut current)
+        {
+            eprintln!("[WARN] Could not rotate log {}: {}", self.path.display(), e);
+        }
+
+        let line = format!("{} {}\n", Local::now().format("%Y-%m-%dT%H:%M:%S%:z"), line);
+        match current.file.write_all(line.as_bytes()) {
+            Ok(()) => current.size += line.len() as u64,
+            Err(e) => eprintln!(
+                "[WARN] Could not write to log {}: {}",
+                self.path.display(),
+                e
+            ),
+        }
+    }
+
+    /// Shift `<log>.1`.. up by one, dropping the oldest past `keep`, and start over
+    fn rotate(&self, current: &mut OpenLog) -> io::Result<()> {
+        let rotated = |n: usize| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{}", n));
+            PathBuf::from(name)
+        };
+        match fs::remove_file(rotated(self.rotation.keep.max(1))) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        if self.rotation.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.rotation.keep).rev() {
+                match fs::rename(rotated(n), rotated(n + 1)) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+
+        *current = OpenLog::open(&self.path)?;
+        // The creation time of a reused inode can't be trusted, the new log starts now
+        current.started = SystemTime::now();
+        Ok(())
+    }
+}
+
+impl ProgressObserver for LogFile {
+    fn on_file_done(&self, outcome: &FileOutcome) {
+        self.write_line(&outcome.message(self.dry_run));
+    }
+
+    fn on_finish(&self, stats: &StatsSnapshot) {
+        self.write_line(&format!(
+            "[SUMMARY] {} of {} files processed, {} skipped, {} errors, {} transferred",
+            stats.processed,
+            stats.total,
+            stats.skipped,
+            stats.errors,
+            format_bytes(stats.bytes_transferred)
+        ));
+    }
+}
@@ -1,37 +1,704 @@
-use clap::Parser;
+use chrono::Local;
+use clap::{ArgAction, CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::process::ExitCode;
 use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
-use timekeeper::Organizer;
+use std::time::Duration;
+use timekeeper::android::TrashedFiles;
+use timekeeper::cache::MetadataCache;
+use timekeeper::clock::ClockOffsets;
+use timekeeper::color::{self, ColorChoice};
+use timekeeper::config::Config;
+use timekeeper::copy_report::CopyReport;
+use timekeeper::dryrun::DryRunSummary;
+use timekeeper::dump::MetadataDump;
+use timekeeper::dupes::{self, DuplicateAction};
+use timekeeper::exiftool::{self, ExifWriter};
+use timekeeper::extract::GpsTime;
+use timekeeper::filter::Area;
+use timekeeper::hooks::ExecHooks;
+use timekeeper::import::{self, Erase};
+use timekeeper::layout::{self, FileMeta, FileNaming, Layout, Preset};
+use timekeeper::localtime::{self, DstPolicy};
+use timekeeper::logfile::{LogFile, LogRotation};
+use timekeeper::manifest::{HashAlgorithm, Manifest};
+use timekeeper::merge;
+use timekeeper::mirror::MirrorIndex;
+use timekeeper::progress::{ConsoleProgress, PlainProgress, ProgressObserver, ProgressStyle};
+use timekeeper::remote;
+use timekeeper::report::RunReport;
+use timekeeper::schedule;
+use timekeeper::settings::Settings;
+use timekeeper::source;
+use timekeeper::space;
 use timekeeper::stats::Stats;
+use timekeeper::status;
+use timekeeper::template::{SequenceScope, Template};
+use timekeeper::trial::Trial;
+use timekeeper::{Backend, Organizer, ProcessingOrder, RunOutcome};
 
-#[derive(Parser)]
+#[derive(Parser, Clone)]
 #[command(
     version,
     about = "A media file organizer that sorts files by date using EXIF metadata",
-    name = "timekeeper"
+    name = "timekeeper",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    // Flags given after a loaded preset's replace the preset's
+    args_override_self = true
 )]
 struct Args {
-    /// Source file or directory
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Sources followed by the destination, like cp: `timekeeper ~/Dump ~/Photos`.
+    /// With -d they are all sources.
+    #[arg(value_name = "PATH")]
+    paths: Vec<std::path::PathBuf>,
+
+    /// Source file or directory, a macOS Photos library (its originals are organized),
+    /// or an MTP device such as mtp://Pixel7/DCIM
     #[arg(short = 's', long = "source")]
-    source: std::path::PathBuf,
+    source: Option<std::path::PathBuf>,
 
-    /// Destination directory
+    /// Destination directory, or a remote URL (s3://bucket/photos, sftp://user@nas/photos,
+    /// davs://cloud.example.com/remote.php/dav/files/user/Photos)
     #[arg(short = 'd', long = "destination")]
-    destination: std::path::PathBuf,
+    destination: Option<std::path::PathBuf>,
+
+    /// With a remote destination, delete each source file once its upload is verified
+    #[arg(long = "delete-after-verify")]
+    delete_after_verify: bool,
 
     /// Show what would be done without actually moving files
     #[arg(long = "dry-run")]
     dry_run: bool,
 
+    /// Plan the run, then pick the files to organize from a list that narrows down as
+    /// you type, checking or unchecking single files or whole folders
+    #[arg(long = "pick", conflicts_with = "schedule")]
+    pick: bool,
+
+    /// Only organize the first N files found, to try the settings on before a long run
+    #[arg(long = "limit", value_name = "N", conflicts_with_all = ["sample", "schedule"])]
+    limit: Option<usize>,
+
+    /// Only organize N files drawn at random from all of them, to try the settings on
+    /// a spread of folders and dates before a long run
+    #[arg(long = "sample", value_name = "N", conflicts_with = "schedule")]
+    sample: Option<usize>,
+
+    /// Don't ask before large runs (see --confirm-over-files), and start copying even
+    /// when the planned files won't all fit the destination
+    #[arg(short = 'y', long = "yes")]
+    yes: bool,
+
+    /// Ask before a run from a terminal that takes more than this many files
+    #[arg(long = "confirm-over-files", value_name = "N", default_value_t = 1000)]
+    confirm_over_files: usize,
+
+    /// Ask before a run from a terminal that takes more than this much data
+    #[arg(long = "confirm-over-size", value_name = "SIZE", value_parser = parse_size, default_value = "10GB")]
+    confirm_over_size: u64,
+
+    /// Stop at the first file that fails
+    #[arg(long = "fail-fast", conflicts_with = "max_errors")]
+    fail_fast: bool,
+
+    /// Stop once this many files have failed
+    #[arg(long = "max-errors", value_name = "N")]
+    max_errors: Option<usize>,
+
+    /// Color output: green for metadata dates, yellow for guessed (mtime) dates,
+    /// cyan for skips and red for errors: auto, always or never
+    #[arg(long = "color", default_value = "auto", global = true)]
+    color: ColorChoice,
+
+    /// How to read dates without a UTC offset that fall on a daylight saving change,
+    /// happening twice or not at all: earliest, latest or utc. Such files are listed.
+    #[arg(long = "dst-policy", default_value = "earliest", global = true)]
+    dst_policy: DstPolicy,
+
+    /// With --dry-run, print one tab-separated line per file instead: action (c copy,
+    /// m move, = already in place), date source (exif or mtime), source and destination.
+    /// Files are planned in parallel, so sort the output before diffing two runs.
+    #[arg(long = "itemize", requires = "dry_run")]
+    itemize: bool,
+
+    /// With --dry-run, list the planned files under their destination folders at the
+    /// end instead of one line per file as they are planned
+    #[arg(
+        long = "group-by-dest",
+        requires = "dry_run",
+        conflicts_with = "itemize"
+    )]
+    group_by_dest: bool,
+
+    /// How to report progress: human ([PROGRESS] lines every ten seconds) or plain
+    /// (`PROGRESS <done> <total> <bytes_done> <bytes_total>` lines on stderr, for GUI wrappers)
+    #[arg(long = "progress", value_name = "STYLE", default_value = "human")]
+    progress: ProgressStyle,
+
+    /// Write plain progress lines to this already open file descriptor instead (Unix)
+    #[arg(long = "progress-fd", value_name = "FD")]
+    progress_fd: Option<i32>,
+
     /// Path to ExifTool executable (optional, auto-detected if not specified)
     #[arg(long = "exiftool")]
     exiftool: Option<std::path::PathBuf>,
+
+    /// Date photos by their GPS timestamp when they have one, ahead of the camera's
+    /// clock. It is UTC from the satellites, so it is right even when the clock was not.
+    #[arg(long = "gps-time")]
+    gps_time: bool,
+
+    /// Take MP4/MOV dates as local time instead of converting them from UTC, for
+    /// cameras that store local time in them
+    #[arg(long = "quicktime-local")]
+    quicktime_local: bool,
+
+    /// Shell command run before each file is placed, e.g. 'chmod 644 {src}'.
+    /// {src} and {dst} are replaced by the file's paths. A failing command skips the file.
+    #[arg(long = "exec-before", value_name = "CMD")]
+    exec_before: Option<String>,
+
+    /// Shell command run after each file is placed, e.g. 'chown media {dst}'
+    #[arg(long = "exec-after", value_name = "CMD")]
+    exec_after: Option<String>,
+
+    /// Most --exec-before/--exec-after commands running at once (default: one per core)
+    #[arg(long = "exec-jobs", value_name = "N")]
+    exec_jobs: Option<usize>,
+
+    /// Place media received through WhatsApp/Telegram/Signal under a separate Received/
+    /// folder. They are recognized by the names those apps give them.
+    #[arg(long = "separate-received")]
+    separate_received: bool,
+
+    /// Files in an Android phone's trash (.trashed-*): ignore them, or review to put
+    /// them in a Trash Review folder. Files Android is still writing (.pending-*) are
+    /// always left out.
+    #[arg(long = "android-trashed", default_value = "ignore")]
+    android_trashed: TrashedFiles,
+
+    /// Send files at least this big (e.g. 2G) to --large-files-dest instead
+    #[arg(long = "large-files-over", value_name = "SIZE", value_parser = parse_size, requires = "large_files_dest")]
+    large_files_over: Option<u64>,
+
+    /// Where files over --large-files-over go, e.g. an archive volume
+    #[arg(
+        long = "large-files-dest",
+        value_name = "DIR",
+        requires = "large_files_over"
+    )]
+    large_files_dest: Option<std::path::PathBuf>,
+
+    /// Put videos under this directory instead, e.g. on a bigger disk. They keep the
+    /// destination's layout.
+    #[arg(long = "video-destination", value_name = "DIR")]
+    video_destination: Option<std::path::PathBuf>,
+
+    /// Skip the destination free space check before and during copying
+    #[arg(long = "no-space-check")]
+    no_space_check: bool,
+
+    /// Only process files last modified at least this long ago (e.g. 30s, 2m, 1h)
+    #[arg(long = "min-age", value_parser = parse_duration)]
+    min_age: Option<Duration>,
+
+    /// Only process files whose size stays unchanged for this long (e.g. 5s)
+    #[arg(long = "stable-for", value_parser = parse_duration)]
+    stable_for: Option<Duration>,
+
+    /// Download online-only OneDrive, Dropbox and iCloud Drive files before organizing
+    /// them. They are skipped otherwise, so their sync state is left as it is.
+    #[arg(long = "hydrate-placeholders")]
+    hydrate_placeholders: bool,
+
+    /// Skip source folders that already follow the destination layout
+    #[arg(long = "skip-organized")]
+    skip_organized: bool,
+
+    /// Follow symlinks in the source. Files reachable through several paths are
+    /// organized once.
+    #[arg(long = "follow-symlinks")]
+    follow_symlinks: bool,
+
+    /// Only organize files tagged with this XMP/EXIF keyword (repeat to accept any of several)
+    #[arg(long = "require-keyword", value_name = "KEYWORD")]
+    require_keyword: Vec<String>,
+
+    /// Leave files tagged with this XMP/EXIF keyword where they are (repeatable)
+    #[arg(long = "exclude-keyword", value_name = "KEYWORD")]
+    exclude_keyword: Vec<String>,
+
+    /// Only organize files whose GPS position lies between two corners, "lat,lon:lat,lon"
+    #[arg(long = "within", value_name = "BOX", value_parser = Area::within)]
+    within: Option<Area>,
+
+    /// Only organize files whose GPS position lies around a point, "lat,lon,10km"
+    #[arg(long = "near", value_name = "AREA", value_parser = Area::near, conflicts_with = "within")]
+    near: Option<Area>,
+
+    /// Match the layout a media server expects: plain, immich, photoprism, digikam, plex or jellyfin
+    #[arg(long = "preset", default_value = "plain")]
+    preset: Preset,
+
+    /// Folder layout, overriding the preset's: year-month, year-month-number, year-date,
+    /// ym (2023/2023-05), year-month-day, quarter (2023/Q2), decade (1990s/1994), camera,
+    /// events, or a template such as '{year}/{month:02}'
+    #[arg(long = "layout", value_parser = layout::parse_layout)]
+    layout: Option<Arc<dyn Layout>>,
+
+    /// Rename files from a template, e.g. '{date}_{camera|lower|replace(' ','_')}'.
+    /// The original extension is kept. {subsec} (milliseconds) keeps burst shots apart.
+    #[arg(long = "rename", value_name = "TEMPLATE")]
+    rename: Option<Template>,
+
+    /// Rename files where they are instead of organizing them into a destination,
+    /// with --rename or else a date prefix (20230514_102345_IMG_1234.jpg)
+    #[arg(long = "rename-only", conflicts_with_all = ["destination", "layout"])]
+    rename_only: bool,
+
+    /// Keep the source folder name as an album below the date folders, e.g.
+    /// 2019/June/2019 Wedding. Camera and date folder names are not kept.
+    #[arg(long = "keep-album")]
+    keep_album: bool,
+
+    /// Recreate the source's folders below the date folders, e.g.
+    /// Trips/Japan/IMG.jpg goes to 2023/March/Trips/Japan/IMG.jpg
+    #[arg(long = "preserve-structure", conflicts_with = "keep_album")]
+    preserve_structure: bool,
+
+    /// Also save the video inside Samsung and Pixel motion photos next to each
+    /// photo, as <name>_motion.mp4
+    #[arg(long = "extract-motion-clips")]
+    extract_motion_clips: bool,
+
+    /// Write dates taken from file names or XMP sidecars into the organized files'
+    /// EXIF (DateTimeOriginal) with exiftool. Videos and RAW files are left alone.
+    #[arg(long = "write-exif")]
+    write_exif: bool,
+
+    /// Let --write-exif write into RAW files too
+    #[arg(long = "write-exif-raw", requires = "write_exif")]
+    write_exif_raw: bool,
+
+    /// Remove GPS tags from the organized files (and their sidecars) with exiftool,
+    /// for a tree meant for sharing. Sources are never touched in copy mode.
+    #[arg(long = "strip-gps")]
+    strip_gps: bool,
+
+    /// Set each organized file's modification time to its capture date, so apps
+    /// sorting by date modified show the tree in order
+    #[arg(long = "sync-mtime")]
+    sync_mtime: bool,
+
+    /// Send the originals a move deletes to the system trash instead of removing
+    /// them for good (needs the trash feature)
+    #[arg(long = "use-trash")]
+    use_trash: bool,
+
+    /// Copy and hash-verify every file, never deleting a source, and write FILE listing
+    /// each file with its hash and both locations, ending in a digest of the report
+    #[arg(
+        long = "verify-only-report",
+        value_name = "FILE",
+        conflicts_with_all = ["rename_only", "delete_after_verify", "write_exif", "strip_gps"]
+    )]
+    verify_only_report: Option<std::path::PathBuf>,
+
+    /// Put files with an XMP rating of at least this many stars (default 5) in a
+    /// Selects tree of their own, e.g. Selects/2023/May
+    #[arg(long = "by-rating", value_name = "STARS", num_args = 0..=1, default_missing_value = "5")]
+    by_rating: Option<i32>,
+
+    /// What restarts {seq} numbering in --rename: folder, day or run
+    #[arg(long = "seq-scope", default_value = "folder")]
+    seq_scope: SequenceScope,
+
+    /// Serve a JSON status (/status) and Prometheus metrics (/metrics) on this port (e.g. 8090)
+    #[arg(long = "status-port")]
+    status_port: Option<u16>,
+
+    /// Address the status endpoint listens on, use 0.0.0.0 to allow remote access
+    #[arg(
+        long = "status-bind",
+        default_value = "127.0.0.1",
+        requires = "status_port"
+    )]
+    status_bind: IpAddr,
+
+    /// Show a desktop notification when the run finishes or aborts
+    #[cfg(feature = "notify")]
+    #[arg(long = "notify")]
+    notify: bool,
+
+    /// POST a JSON summary to this URL when the run finishes or aborts
+    #[cfg(feature = "webhook")]
+    #[arg(long = "on-complete-webhook")]
+    on_complete_webhook: Option<String>,
+
+    /// Write the log, journal, JSON summary and errors of every run into a
+    /// timestamped folder inside this directory
+    #[arg(long = "report-dir", value_name = "DIR")]
+    report_dir: Option<std::path::PathBuf>,
+
+    /// Append a line for every file to this log, kept across runs
+    #[arg(long = "log-file", value_name = "FILE")]
+    log_file: Option<std::path::PathBuf>,
+
+    /// Rotate the log once it reaches this size (e.g. 10M)
+    #[arg(long = "log-max-size", value_name = "SIZE", value_parser = parse_size, requires = "log_file")]
+    log_max_size: Option<u64>,
+
+    /// Rotate the log once it is this old (e.g. 7d)
+    #[arg(long = "log-max-age", value_name = "AGE", value_parser = parse_duration, requires = "log_file")]
+    log_max_age: Option<Duration>,
+
+    /// Number of rotated logs to keep
+    #[arg(
+        long = "log-keep",
+        value_name = "N",
+        default_value_t = 5,
+        requires = "log_file"
+    )]
+    log_keep: usize,
+
+    /// Metadata cache file (defaults to the user cache directory)
+    #[arg(long = "cache-file")]
+    cache_file: Option<std::path::PathBuf>,
+
+    /// Don't read or write the metadata cache
+    #[arg(long = "no-cache", conflicts_with = "cache_file")]
+    no_cache: bool,
+
+    /// Read dates from an `exiftool -j -r` JSON dump instead of running exiftool
+    #[arg(long = "metadata-json", conflicts_with = "exiftool")]
+    metadata_json: Option<std::path::PathBuf>,
+
+    /// Write MANIFEST.<algorithm> with the hash, size and capture date of every placed
+    /// file, one at the root of the destination, --video-destination and --large-files-dest
+    #[arg(long = "manifest", value_name = "ALGORITHM", num_args = 0..=1, default_missing_value = "sha256")]
+    manifest: Option<HashAlgorithm>,
+
+    /// How files are written: threads, or async to keep many uploads in flight
+    /// (needs the async feature)
+    #[arg(long = "backend", default_value = "threads")]
+    backend: Backend,
+
+    /// Order files are planned and written in: path (reproducible, including the
+    /// suffixes of clashing names), date, size or random. All but path plan every file
+    /// before writing the first. `{seq}` numbers always follow the capture date.
+    #[arg(long = "order", default_value = "date")]
+    order: ProcessingOrder,
+
+    /// Files queued between processing stages (default 256), lower it to cap memory
+    #[arg(long = "queue-size")]
+    queue_size: Option<usize>,
+
+    /// Keep running and organize on a cron schedule (e.g. "0 3 * * *")
+    #[arg(long = "schedule", value_parser = schedule::parse_schedule)]
+    schedule: Option<cron::Schedule>,
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+#[derive(Subcommand, Clone)]
+enum Command {
+    /// Re-hash an organized tree and compare it against its manifest
+    VerifyManifest {
+        /// Manifest written by --manifest (e.g. dest/MANIFEST.sha256)
+        manifest: std::path::PathBuf,
+    },
+    /// Re-hash part of an organized tree against its manifest to catch bit rot: files
+    /// whose contents changed while their modification time didn't. Each scrub carries
+    /// on where the last one stopped, so regular scrubs cover the whole tree.
+    Scrub {
+        /// Destination organized with --manifest
+        destination: std::path::PathBuf,
+
+        /// Share of the files to re-hash, e.g. 0.1 or 10%
+        #[arg(long = "fraction", default_value = "10%", value_parser = parse_fraction)]
+        fraction: f64,
+    },
+    /// Import every mounted camera card (any volume with a DCIM folder) into the library
+    Import {
+        /// Card to import instead of the detected ones (e.g. /media/me/EOS_DIGITAL)
+        card: Option<std::path::PathBuf>,
+
+        /// Library to import into (defaults to default_destination in config.toml)
+        #[arg(short = 'd', long = "destination")]
+        destination: Option<std::path::PathBuf>,
+
+        /// Hash every copy and compare it against the card before trusting it
+        #[arg(long = "verify")]
+        verify: bool,
+
+        /// Afterwards delete the imported files, everything in DCIM, or nothing
+        /// (asks when not given): imported, all or none
+        #[arg(long = "erase")]
+        erase: Option<Erase>,
+
+        /// Send erased files to the system trash instead of deleting them for good
+        /// (needs the trash feature)
+        #[arg(long = "use-trash")]
+        use_trash: bool,
+
+        /// Show what would be imported without copying anything
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+
+        /// Path to ExifTool executable (optional, auto-detected if not specified)
+        #[arg(long = "exiftool")]
+        exiftool: Option<std::path::PathBuf>,
+
+        /// Match the layout a media server expects: plain, immich, photoprism, digikam, plex or jellyfin
+        #[arg(long = "preset", default_value = "plain")]
+        preset: Preset,
+    },
+    /// Show how a source's files spread over years and months without moving anything
+    Analyze {
+        /// Source directory or file to analyze
+        #[arg(short = 's', long = "source")]
+        source: std::path::PathBuf,
+
+        /// Also write the results to this file, as CSV for a .csv path and JSON otherwise
+        #[arg(long = "export")]
+        export: Option<std::path::PathBuf>,
+
+        /// Path to ExifTool executable (optional, auto-detected if not specified)
+        #[arg(long = "exiftool")]
+        exiftool: Option<std::path::PathBuf>,
+
+        /// Read dates from an `exiftool -j -r` JSON dump instead of running exiftool
+        #[arg(long = "metadata-json", conflicts_with = "exiftool")]
+        metadata_json: Option<std::path::PathBuf>,
+
+        /// Don't read or write the metadata cache
+        #[arg(long = "no-cache")]
+        no_cache: bool,
+    },
+    /// Time the ways of reading dates (native parser, exiftool per file, exiftool
+    /// batch) over a sample of a source's files, to see which suits the library
+    Bench {
+        /// Folder to take the sample from
+        #[arg(short = 's', long = "source")]
+        source: std::path::PathBuf,
+
+        /// How many of its media files to time each engine on
+        #[arg(long = "files", default_value_t = 200)]
+        files: usize,
+
+        /// Path to ExifTool executable (optional, auto-detected if not specified)
+        #[arg(long = "exiftool")]
+        exiftool: Option<std::path::PathBuf>,
+    },
+    /// Report files with identical contents across one or more folders without moving anything
+    Dupes {
+        /// Folder to search, repeat to compare several
+        #[arg(short = 's', long = "source", required = true)]
+        sources: Vec<std::path::PathBuf>,
+
+        /// Hash used to compare files: sha256 or blake3
+        #[arg(long = "hash", default_value = "blake3")]
+        hash: HashAlgorithm,
+
+        /// What to do with the duplicates: report lists them, consolidate:<dir> moves
+        /// every copy but the first of each group into <dir> for review
+        #[arg(long = "duplicates", value_name = "ACTION", default_value = "report")]
+        action: DuplicateAction,
+
+        /// Also write the groups found to this file, as CSV for a .csv path and JSON otherwise
+        #[arg(long = "export")]
+        export: Option<std::path::PathBuf>,
+    },
+    /// Combine several organized trees into one, leaving out files whose contents
+    /// are already there. The trees are only read.
+    Merge {
+        /// Organized trees to combine, e.g. an old backup and a laptop's library
+        #[arg(required = true)]
+        trees: Vec<std::path::PathBuf>,
+
+        /// Tree to merge into, may already hold files
+        #[arg(short = 'd', long = "destination")]
+        destination: std::path::PathBuf,
+
+        /// Hash used to compare files: sha256 or blake3
+        #[arg(long = "hash", default_value = "blake3")]
+        hash: HashAlgorithm,
+
+        /// Show what would be copied without copying anything
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+
+        /// Also write the totals to this file, as CSV for a .csv path and JSON otherwise
+        #[arg(long = "export")]
+        export: Option<std::path::PathBuf>,
+    },
+    /// Keep an organized copy of a source up to date, copying only the files added or
+    /// changed since the last run
+    Mirror {
+        /// Folder to mirror (e.g. ~/Phone)
+        #[arg(short = 's', long = "source")]
+        source: std::path::PathBuf,
+
+        /// Organized mirror, keeps an index of what it holds in .timekeeper-mirror
+        #[arg(short = 'd', long = "destination")]
+        destination: std::path::PathBuf,
+
+        /// List the files the mirror holds that are gone from the source. Their copies
+        /// are kept.
+        #[arg(long = "flag-removed")]
+        flag_removed: bool,
+
+        /// Show what would be copied without copying anything
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+
+        /// Path to ExifTool executable (optional, auto-detected if not specified)
+        #[arg(long = "exiftool")]
+        exiftool: Option<std::path::PathBuf>,
+
+        /// Match the layout a media server expects: plain, immich, photoprism, digikam, plex or jellyfin
+        #[arg(long = "preset", default_value = "plain")]
+        preset: Preset,
+    },
+    /// Accept organize jobs over JSON-RPC on a local socket until stopped (Unix)
+    Serve {
+        /// Socket to listen on, e.g. /run/user/1000/timekeeper.sock
+        #[arg(long = "socket")]
+        socket: std::path::PathBuf,
+    },
+    /// Save organize settings under a name, or organize with saved ones
+    #[command(name = "preset")]
+    Settings {
+        #[command(subcommand)]
+        action: SettingsAction,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+enum SettingsAction {
+    /// Save the given organize flags (all but --source) as a preset, e.g.
+    /// `preset save wedding-ingest -d /mnt/weddings --keep-album`
+    Save {
+        /// Preset name, kept in the config directory, or a .toml file to share
+        name: String,
+
+        /// Organize flags to save
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        flags: Vec<String>,
+    },
+    /// Organize with a preset's settings, e.g. `preset load wedding-ingest -s /media/card`.
+    /// Flags given here replace the preset's.
+    Load {
+        /// Preset name or .toml file
+        name: String,
+
+        /// Further organize flags, at least --source
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        flags: Vec<String>,
+    },
+}
+
+/// Parse sizes like `500M`, `2G` or `1.5GB` (binary units, bare numbers are bytes)
+fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let digits = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(digits);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", value))?;
+    let unit = match unit.trim().to_uppercase().trim_end_matches('B') {
+        "" => 1u64,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("invalid size unit '{}' in '{}'", unit, value)),
+    };
+    Ok((number * unit as f64) as u64)
+}
+
+/// Parse a share of files as `0.25` or `25%`
+fn parse_fraction(value: &str) -> Result<f64, String> {
+    let value = value.trim();
+    let fraction = match value.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f64>().map(|p| p / 100.0),
+        None => value.parse::<f64>(),
+    }
+    .map_err(|_| format!("invalid fraction '{}'", value))?;
+    if !(fraction > 0.0 && fraction <= 1.0) {
+        return Err(format!(
+            "fraction '{}' must be above 0 and at most 100%",
+            value
+        ));
+    }
+    Ok(fraction)
+}
+
+/// Parse durations like `90`, `30s`, `2m` or `1h30m` (bare numbers are seconds)
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let amount: u64 = number
+            .parse()
+            .map_err(|_| format!("invalid duration '{}'", value))?;
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(format!("invalid duration unit '{}' in '{}'", c, value)),
+        };
+        total += amount * unit;
+        number.clear();
+    }
+
+    if !number.is_empty() {
+        return Err(format!("missing unit after '{}' in '{}'", number, value));
+    }
+    Ok(Duration::from_secs(total))
+}
+
+fn main() -> ExitCode {
     let args = Args::parse();
+    color::init(args.color);
+    localtime::set_dst_policy(args.dst_policy);
 
-    let stats = Arc::new(Stats::new());
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", color::red(&format!("Error: {}", e)));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(Command::VerifyManifest { manifest }) = &args.command {
+        return verify_manifest(manifest);
+    }
+    if let Some(Command::Settings { action }) = &args.command {
+        return match action {
+            SettingsAction::Save { name, flags } => save_settings(name, flags),
+            SettingsAction::Load { name, flags } => run(load_settings(name, flags)?),
+        };
+    }
     let terminate_flag = Arc::new(AtomicBool::new(false));
 
     // Register Ctrl+C handler
@@ -43,15 +710,1200 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         })?;
     }
 
-    let mut organizer = Organizer::new(args.source, args.destination, args.dry_run);
-    if let Some(p) = args.exiftool {
+    if let Some(Command::Import { .. }) = &args.command {
+        return import_cards(&args, &terminate_flag);
+    }
+    if let Some(Command::Scrub {
+        destination,
+        fraction,
+    }) = &args.command
+    {
+        return scrub(destination, *fraction, &terminate_flag);
+    }
+    if let Some(Command::Dupes {
+        sources,
+        hash,
+        action,
+        export,
+    }) = &args.command
+    {
+        return report_duplicates(sources, *hash, action, export.as_deref(), &terminate_flag);
+    }
+    if let Some(Command::Merge {
+        trees,
+        destination,
+        hash,
+        dry_run,
+        export,
+    }) = &args.command
+    {
+        return merge_trees(
+            trees,
+            destination,
+            *hash,
+            *dry_run,
+            export.as_deref(),
+            &terminate_flag,
+        );
+    }
+    if let Some(Command::Analyze { .. }) = &args.command {
+        return analyze_source(&args, &terminate_flag);
+    }
+    if let Some(Command::Bench {
+        source,
+        files,
+        exiftool,
+    }) = &args.command
+    {
+        return bench_engines(source, *files, exiftool.clone(), &terminate_flag);
+    }
+    if let Some(Command::Mirror { .. }) = &args.command {
+        return mirror_source(&args, &terminate_flag);
+    }
+    if let Some(Command::Serve { socket }) = &args.command {
+        return serve_jobs(socket, &terminate_flag);
+    }
+    let stats = Arc::new(Stats::new());
+
+    if let Some(port) = args.status_port {
+        let addr = SocketAddr::new(args.status_bind, port);
+        status::serve_status(addr, Arc::clone(&stats))?;
+        println!("[INFO] Serving status on http://{}/status", addr);
+    }
+
+    let (sources, destination) = source_paths(&args)?;
+    let report = args
+        .report_dir
+        .clone()
+        .map(|dir| Arc::new(RunReport::new(dir)));
+    let mut watchers: Vec<Arc<dyn ProgressObserver>> = Vec::new();
+    if let Some(report) = &report {
+        watchers.push(Arc::clone(report) as Arc<dyn ProgressObserver>);
+    }
+    if let Some(path) = &args.log_file {
+        let rotation = LogRotation {
+            max_size: args.log_max_size,
+            max_age: args.log_max_age,
+            keep: args.log_keep,
+        };
+        let log = LogFile::open(path.clone(), args.dry_run, rotation)
+            .map_err(|e| format!("Cannot open log '{}': {}", path.display(), e))?;
+        watchers.push(Arc::new(log));
+    }
+    let dry_run_summary = args
+        .dry_run
+        .then(|| Arc::new(DryRunSummary::new(destination.clone()).with_files(args.group_by_dest)));
+    if let Some(summary) = &dry_run_summary {
+        watchers.push(Arc::clone(summary) as Arc<dyn ProgressObserver>);
+    }
+    let organizers = sources
+        .iter()
+        .map(|source| build_organizer(&args, source, destination.as_deref(), &stats, &watchers))
+        .collect::<Result<Vec<_>, _>>()?;
+    let trial = match (args.limit, args.sample) {
+        (Some(count), _) => Some(Trial::First(count)),
+        (_, Some(count)) => Some(Trial::Sample(count)),
+        (None, None) => None,
+    };
+    let (organizers, trial_files) = match trial {
+        Some(trial) => {
+            let files = Arc::new(trial.select(&organizers)?);
+            println!("[INFO] Trying the settings on {} files", files.len());
+            let organizers = organizers
+                .into_iter()
+                .map(|organizer| organizer.with_only_files(Arc::clone(&files)))
+                .collect();
+            (organizers, Some(files))
+        }
+        None => (organizers, None),
+    };
+    let organizers = if args.pick {
+        match pick_files(&args, &sources, destination.as_deref(), trial_files)? {
+            Some(files) if !files.is_empty() => organizers
+                .into_iter()
+                .map(|organizer| organizer.with_only_files(Arc::clone(&files)))
+                .collect(),
+            Some(_) => {
+                println!("[INFO] No files picked, nothing was changed.");
+                return Ok(());
+            }
+            None => {
+                println!("[INFO] Cancelled, nothing was changed.");
+                return Ok(());
+            }
+        }
+    } else {
+        organizers
+    };
+    if args.schedule.is_none() && !confirm_run(&args, &organizers)? {
+        println!("[INFO] Cancelled, nothing was changed.");
+        return Ok(());
+    }
+    match &args.schedule {
+        Some(schedule) => {
+            schedule::run_on_schedule(schedule, &terminate_flag, || {
+                stats.reset();
+                if let Err(e) = run_once(
+                    &args,
+                    &organizers,
+                    &stats,
+                    report.as_deref(),
+                    dry_run_summary.as_deref(),
+                    &terminate_flag,
+                ) {
+                    eprintln!(
+                        "{}",
+                        color::red(&format!("[ERROR] Scheduled run failed: {}", e))
+                    );
+                }
+            });
+            Ok(())
+        }
+        None => run_once(
+            &args,
+            &organizers,
+            &stats,
+            report.as_deref(),
+            dry_run_summary.as_deref(),
+            &terminate_flag,
+        ),
+    }
+}
+
+/// Sources and destination from -s/-d and the positional paths. Like with cp, the
+/// last positional path is the destination unless -d names one. A lone source goes
+/// to default_destination from config.toml, so `timekeeper /media/sdcard` imports a card.
+fn source_paths(
+    args: &Args,
+) -> Result<(Vec<std::path::PathBuf>, Option<std::path::PathBuf>), Box<dyn std::error::Error>> {
+    let mut paths = args.paths.clone();
+    let destination = match &args.destination {
+        Some(destination) => Some(destination.clone()),
+        // Renaming in place writes next to each source's files
+        None if args.rename_only => None,
+        None if args.source.is_some() || paths.len() > 1 => paths.pop(),
+        None => None,
+    };
+    let sources: Vec<_> = args.source.iter().cloned().chain(paths).collect();
+    if sources.is_empty() {
+        return Err("No source given: name it before the destination or with -s".into());
+    }
+    if destination.is_some() || args.rename_only {
+        return Ok((sources, destination));
+    }
+    let config = Config::load().map_err(|e| format!("Cannot read config: {}", e))?;
+    match config.default_destination {
+        Some(destination) => Ok((sources, Some(destination))),
+        None => Err(
+            "No destination given and no default_destination set in config.toml: \
+                     name it after the sources or with -d"
+                .into(),
+        ),
+    }
+}
+
+/// The organizer for the top-level organize flags and one source, reporting into
+/// `stats` and to `watchers` such as the run report and the log file
+fn build_organizer(
+    args: &Args,
+    source: &Path,
+    destination: Option<&Path>,
+    stats: &Arc<Stats>,
+    watchers: &[Arc<dyn ProgressObserver>],
+) -> Result<Organizer, Box<dyn std::error::Error>> {
+    let source = source.to_path_buf();
+    let resolved = source::resolve_source(&source)
+        .map_err(|e| format!("Cannot open source '{}': {}", source.display(), e))?;
+    if resolved != source {
+        println!(
+            "[INFO] Reading {} from {}",
+            source.display(),
+            resolved.display()
+        );
+    }
+    let source = resolved;
+    // Renaming in place writes next to the files themselves
+    let destination = match destination {
+        Some(destination) => destination.to_path_buf(),
+        None if source.is_dir() => source.clone(),
+        None => source.parent().map(Path::to_path_buf).unwrap_or_default(),
+    };
+
+    let mut organizer = Organizer::new(source.clone(), destination.clone(), args.dry_run)
+        .with_clock_offsets(clock_offsets()?)
+        .with_separate_received(args.separate_received)
+        .with_android_trashed(args.android_trashed)
+        .with_free_space_check(!args.no_space_check)
+        .with_out_of_space_warning(args.yes)
+        .with_skip_organized(args.skip_organized)
+        .with_follow_symlinks(args.follow_symlinks)
+        .with_quicktime_utc(!args.quicktime_local)
+        .with_required_keywords(args.require_keyword.clone())
+        .with_excluded_keywords(args.exclude_keyword.clone())
+        .with_preset(args.preset)
+        .with_backend(args.backend)
+        .with_order(args.order)
+        .with_observer(progress_observer(args, stats, watchers)?)
+        .with_keep_album(args.keep_album)
+        .with_preserve_structure(args.preserve_structure)
+        .with_sync_mtime(args.sync_mtime)
+        .with_trash(trash_enabled(args.use_trash)?)
+        .with_motion_clips(args.extract_motion_clips)
+        .with_hydrate_placeholders(args.hydrate_placeholders)
+        .with_itemize(args.itemize)
+        .with_group_by_dest(args.group_by_dest);
+    if args.gps_time {
+        organizer = organizer.with_extractor(Arc::new(GpsTime));
+    }
+    if let Some(layout) = &args.layout {
+        organizer = organizer.with_layout(Arc::clone(layout));
+    }
+    if args.write_exif || args.strip_gps {
+        let mut writer = ExifWriter::new(exiftool::get_exiftool_path(args.exiftool.clone())?)
+            .with_strip_gps(args.strip_gps);
+        if args.write_exif {
+            writer = writer.with_dates(args.write_exif_raw);
+        }
+        organizer = organizer.with_exif_writer(writer);
+    }
+    if let Some(area) = args.within.or(args.near) {
+        organizer = organizer.with_area(area);
+    }
+    if let Some(min_rating) = args.by_rating {
+        organizer = organizer.with_selects_rating(min_rating);
+    }
+    if let Some(videos) = &args.video_destination {
+        organizer = organizer.with_video_destination(videos.clone());
+    }
+    if let (Some(threshold), Some(large)) = (args.large_files_over, &args.large_files_dest) {
+        organizer = organizer.with_large_files(threshold, large.clone());
+    }
+    if let Some(rename) = &args.rename {
+        organizer = organizer
+            .with_naming(FileNaming::Template(rename.clone()))
+            .with_sequence_scope(args.seq_scope);
+    }
+    if args.rename_only {
+        organizer = organizer.with_rename_only(true).with_copy(false);
+        if args.rename.is_none() {
+            organizer = organizer.with_naming(FileNaming::DatePrefix);
+        }
+    }
+    match remote::open_destination(&destination) {
+        Some(remote) => {
+            organizer = organizer
+                .with_remote(remote.map_err(|e| {
+                    format!("Cannot open destination '{}': {}", destination.display(), e)
+                })?)
+                .with_copy(!args.delete_after_verify);
+            if args.manifest.is_some() {
+                return Err("--manifest needs a local destination".into());
+            }
+            if args.write_exif {
+                return Err("--write-exif needs a local destination".into());
+            }
+            if args.strip_gps {
+                return Err("--strip-gps needs a local destination".into());
+            }
+            if args.sync_mtime {
+                return Err("--sync-mtime needs a local destination".into());
+            }
+            if args.verify_only_report.is_some() {
+                return Err("--verify-only-report needs a local destination".into());
+            }
+            if args.video_destination.is_some() {
+                return Err("--video-destination needs a local destination".into());
+            }
+            if args.large_files_dest.is_some() {
+                return Err("--large-files-dest needs a local destination".into());
+            }
+        }
+        None if args.delete_after_verify => {
+            return Err("--delete-after-verify needs a remote destination".into());
+        }
+        None => {}
+    }
+    if let Some(p) = args.exiftool.clone() {
         organizer = organizer.with_exiftool(p);
     }
+    if !args.no_cache
+        && let Some(path) = args.cache_file.clone().or_else(MetadataCache::default_path)
+    {
+        organizer = organizer.with_cache(MetadataCache::load(path));
+    }
+    if let Some(path) = &args.metadata_json {
+        let dump = MetadataDump::load(path, &source)?;
+        println!(
+            "[INFO] Loaded dates for {} files from {}",
+            dump.len(),
+            path.display()
+        );
+        organizer = organizer.with_metadata_dump(dump);
+    }
+    if let Some(algorithm) = args.manifest
+        && !args.dry_run
+    {
+        let manifests = organizer.open_manifests(algorithm)?;
+        organizer = organizer.with_manifest(manifests);
+    }
+    if let Some(report) = &args.verify_only_report
+        && !args.dry_run
+    {
+        // Hash with the manifest's algorithm so the two can be compared line by line
+        let algorithm = args.manifest.unwrap_or(HashAlgorithm::Blake3);
+        organizer = organizer.with_copy_report(CopyReport::new(report.clone(), algorithm));
+    }
+    if let Some(min_age) = args.min_age {
+        organizer = organizer.with_min_age(min_age);
+    }
+    if let Some(stable_for) = args.stable_for {
+        organizer = organizer.with_stable_for(stable_for);
+    }
+    if let Some(queue_size) = args.queue_size {
+        organizer = organizer.with_queue_size(queue_size);
+    }
+    if args.exec_before.is_some() || args.exec_after.is_some() {
+        let jobs = args.exec_jobs.unwrap_or_else(|| {
+            std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+        });
+        organizer = organizer.with_exec_hooks(ExecHooks::new(
+            args.exec_before.clone(),
+            args.exec_after.clone(),
+            jobs,
+        ));
+    }
+    if args.fail_fast {
+        organizer = organizer.with_max_errors(1);
+    } else if let Some(max_errors) = args.max_errors {
+        organizer = organizer.with_max_errors(max_errors);
+    }
 
-    organizer.run(Arc::clone(&stats), Arc::clone(&terminate_flag))?;
+    Ok(organizer)
+}
+
+/// Run the organizers once, report the outcome and print the summary
+fn run_once(
+    args: &Args,
+    organizers: &[Organizer],
+    stats: &Arc<Stats>,
+    report: Option<&RunReport>,
+    dry_run_summary: Option<&DryRunSummary>,
+    terminate_flag: &Arc<AtomicBool>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(report) = report {
+        let sources: Vec<_> = organizers.iter().map(|o| o.source.clone()).collect();
+        // Renaming in place has no destination of its own
+        let destination = (!args.rename_only).then(|| organizers[0].destination.as_path());
+        let folder = report
+            .begin(&sources, destination, args.dry_run)
+            .map_err(|e| format!("Cannot start the run report: {}", e))?;
+        println!("[INFO] Writing the run report to {}", folder.display());
+    }
+
+    // Sources are organized one after the other into the same summary
+    let result = organizers.iter().try_for_each(|organizer| {
+        if terminate_flag.load(std::sync::atomic::Ordering::SeqCst) {
+            return Ok(());
+        }
+        organizer.run(Arc::clone(stats), Arc::clone(terminate_flag))
+    });
+
+    let outcome = if result.is_err() {
+        RunOutcome::Failed
+    } else if terminate_flag.load(std::sync::atomic::Ordering::SeqCst) {
+        RunOutcome::Stopped
+    } else {
+        RunOutcome::Finished
+    };
+
+    if let Some(report) = report {
+        let error = result.as_ref().err().map(ToString::to_string);
+        if let Err(e) = report.finish(outcome, error.as_deref(), &stats.snapshot()) {
+            eprintln!("[WARN] Could not write the run report: {}", e);
+        }
+    }
+
+    #[cfg(feature = "notify")]
+    if args.notify
+        && let Err(e) = timekeeper::notify::notify_completion(outcome, stats)
+    {
+        eprintln!("[WARN] Could not show desktop notification: {}", e);
+    }
+
+    #[cfg(feature = "webhook")]
+    if let Some(url) = &args.on_complete_webhook
+        && let Err(e) = timekeeper::webhook::post_summary(
+            url,
+            outcome,
+            &organizers[0].source,
+            &organizers[0].destination,
+            stats,
+        )
+    {
+        eprintln!("[WARN] Could not deliver completion webhook: {}", e);
+    }
+
+    result?;
 
     println!("\n[INFO] Finished processing or stopped by user.");
     stats.print();
+    if let Some(summary) = dry_run_summary {
+        summary.print(&stats.snapshot());
+    }
+
+    Ok(())
+}
+
+/// List groups of identical files and the space removing the extra copies would free
+fn report_duplicates(
+    sources: &[std::path::PathBuf],
+    hash: HashAlgorithm,
+    action: &DuplicateAction,
+    export: Option<&Path>,
+    terminate_flag: &AtomicBool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for source in sources {
+        if !source.exists() {
+            return Err(format!("Source '{}' does not exist", source.display()).into());
+        }
+    }
+
+    // The review directory may live inside a source, its copies aren't duplicates to find
+    let review_dirs: Vec<std::path::PathBuf> = match action {
+        DuplicateAction::Report => Vec::new(),
+        DuplicateAction::Consolidate(dir) => vec![dir.clone()],
+    };
+    let report = dupes::find_duplicates(sources, &review_dirs, hash, terminate_flag);
+    for group in &report.groups {
+        println!(
+            "\n[DUPLICATE] {} copies of {} ({})",
+            group.paths.len(),
+            space::format_bytes(group.size),
+            group.hash
+        );
+        for path in &group.paths {
+            println!("  {}", path.display());
+        }
+    }
+    println!(
+        "\n[INFO] Scanned {} files: {} duplicates in {} groups, {} reclaimable",
+        report.scanned,
+        report.duplicates(),
+        report.groups.len(),
+        space::format_bytes(report.reclaimable())
+    );
+    if let Some(path) = export {
+        report
+            .export(path)
+            .map_err(|e| format!("Cannot write '{}': {}", path.display(), e))?;
+        println!("[INFO] Duplicates written to {}", path.display());
+    }
+
+    if let DuplicateAction::Consolidate(dir) = action {
+        let outcome = dupes::consolidate(&report, sources, dir, terminate_flag)
+            .map_err(|e| format!("Cannot consolidate into '{}': {}", dir.display(), e))?;
+        println!(
+            "[INFO] Moved {} duplicates to {} (listed in {}), {} failed",
+            outcome.moved,
+            dir.display(),
+            dupes::MAPPING_FILE,
+            outcome.failed
+        );
+    }
+    Ok(())
+}
+
+/// Combine organized trees into one destination
+fn merge_trees(
+    trees: &[std::path::PathBuf],
+    destination: &Path,
+    hash: HashAlgorithm,
+    dry_run: bool,
+    export: Option<&Path>,
+    terminate_flag: &AtomicBool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let merged_into = destination
+        .canonicalize()
+        .or_else(|_| std::path::absolute(destination))?;
+    for tree in trees {
+        let canonical = tree
+            .canonicalize()
+            .map_err(|e| format!("Cannot open tree '{}': {}", tree.display(), e))?;
+        if !canonical.is_dir() {
+            return Err(format!("Tree '{}' is not a directory", tree.display()).into());
+        }
+        // Copies would turn up again in the tree being walked
+        if merged_into.starts_with(&canonical) || canonical.starts_with(&merged_into) {
+            return Err(format!(
+                "Tree '{}' and destination '{}' overlap",
+                tree.display(),
+                destination.display()
+            )
+            .into());
+        }
+    }
+
+    println!(
+        "[INFO] Merging {} trees into {}",
+        trees.len(),
+        destination.display()
+    );
+    let summary = merge::merge_trees(trees, destination, hash, dry_run, terminate_flag)
+        .map_err(|e| format!("Cannot merge into '{}': {}", destination.display(), e))?;
+    println!(
+        "\n[INFO] {} {} files ({}), {} renamed to keep both, {} duplicates left out ({}), {} failed",
+        if dry_run { "Would copy" } else { "Copied" },
+        summary.copied,
+        space::format_bytes(summary.bytes),
+        summary.renamed,
+        summary.duplicates,
+        space::format_bytes(summary.saved),
+        summary.failed
+    );
+    if let Some(path) = export {
+        summary
+            .export(path)
+            .map_err(|e| format!("Cannot write '{}': {}", path.display(), e))?;
+        println!("[INFO] Totals written to {}", path.display());
+    }
+    Ok(())
+}
+
+/// The clock corrections from config.toml's `[clock_offsets]`
+fn clock_offsets() -> Result<ClockOffsets, Box<dyn std::error::Error>> {
+    let config = Config::load().map_err(|e| format!("Cannot read config: {}", e))?;
+    Ok(ClockOffsets::new(&config.clock_offsets))
+}
+
+/// Print the date distribution of a source before organizing it
+fn analyze_source(
+    args: &Args,
+    terminate_flag: &AtomicBool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(Command::Analyze {
+        source,
+        export,
+        exiftool,
+        metadata_json,
+        no_cache,
+    }) = &args.command
+    else {
+        unreachable!("analyze_source is only called for the analyze command");
+    };
+
+    let source = source::resolve_source(source)
+        .map_err(|e| format!("Cannot open source '{}': {}", source.display(), e))?;
+    // Nothing is placed, so the source doubles as the destination
+    let mut organizer =
+        Organizer::new(source.clone(), source.clone(), true).with_clock_offsets(clock_offsets()?);
+    if let Some(p) = exiftool.clone() {
+        organizer = organizer.with_exiftool(p);
+    }
+    if !no_cache && let Some(path) = MetadataCache::default_path() {
+        organizer = organizer.with_cache(MetadataCache::load(path));
+    }
+    if let Some(path) = metadata_json {
+        organizer = organizer.with_metadata_dump(MetadataDump::load(path, &source)?);
+    }
+
+    println!("[INFO] Reading dates in {}", source.display());
+    let analysis = organizer.analyze(terminate_flag)?;
+    analysis.print();
+    if let Some(path) = export {
+        analysis
+            .export(path)
+            .map_err(|e| format!("Cannot write '{}': {}", path.display(), e))?;
+        println!("\n[INFO] Analysis written to {}", path.display());
+    }
+    Ok(())
+}
+
+/// Time each date extraction engine over a sample of the source
+fn bench_engines(
+    source: &std::path::Path,
+    files: usize,
+    exiftool: Option<std::path::PathBuf>,
+    terminate_flag: &Arc<AtomicBool>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let source = source::resolve_source(source)
+        .map_err(|e| format!("Cannot open source '{}': {}", source.display(), e))?;
+    // Nothing is placed, so the source doubles as the destination
+    let mut organizer = Organizer::new(source.clone(), source, true);
+    if let Some(p) = exiftool {
+        organizer = organizer.with_exiftool(p);
+    }
+    organizer.bench(files, terminate_flag)?.print();
+    Ok(())
+}
+
+/// Copy every camera card into the library, then optionally clear the cards
+fn import_cards(
+    args: &Args,
+    terminate_flag: &Arc<AtomicBool>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(Command::Import {
+        card,
+        destination,
+        verify,
+        erase,
+        use_trash,
+        dry_run,
+        exiftool,
+        preset,
+    }) = &args.command
+    else {
+        unreachable!("import_cards is only called for the import command");
+    };
+
+    let use_trash = trash_enabled(*use_trash)?;
+    let config = Config::load().map_err(|e| format!("Cannot read config: {}", e))?;
+    let clock_offsets = ClockOffsets::new(&config.clock_offsets);
+    let destination = match destination {
+        Some(destination) => destination.clone(),
+        None => config
+            .default_destination
+            .ok_or("No destination given and no default_destination set in config.toml, use -d")?,
+    };
+    let cards = match card {
+        Some(card) => vec![card.clone()],
+        None => import::find_camera_cards(),
+    };
+    if cards.is_empty() {
+        return Err("No camera card found (no mounted volume has a DCIM folder)".into());
+    }
+
+    for card in cards {
+        if terminate_flag.load(std::sync::atomic::Ordering::SeqCst) {
+            break;
+        }
+        println!(
+            "[INFO] Importing {} into {}",
+            card.display(),
+            destination.display()
+        );
+
+        let stats = Arc::new(Stats::new());
+        let placed = Arc::new(timekeeper::metadata::PlacedSources::default());
+        let mut organizer = Organizer::new(card.clone(), destination.clone(), *dry_run)
+            .with_preset(*preset)
+            .with_clock_offsets(clock_offsets.clone())
+            .with_verify(*verify)
+            .with_placed_log(Arc::clone(&placed))
+            .with_observer(Arc::new(ConsoleProgress::new(Arc::clone(&stats))));
+        if let Some(p) = exiftool.clone() {
+            organizer = organizer.with_exiftool(p);
+        }
+        if let Some(path) = MetadataCache::default_path() {
+            organizer = organizer.with_cache(MetadataCache::load(path));
+        }
+
+        organizer.run(Arc::clone(&stats), Arc::clone(terminate_flag))?;
+        stats.print();
+        if *dry_run {
+            continue;
+        }
+
+        let placed = placed.lock().unwrap();
+        let complete = placed.len() == stats.total.load(std::sync::atomic::Ordering::SeqCst)
+            && !terminate_flag.load(std::sync::atomic::Ordering::SeqCst);
+        let erase = match erase {
+            Some(erase) => *erase,
+            None => ask_erase(&card, placed.len(), complete)?,
+        };
+        match erase {
+            Erase::None => {}
+            Erase::Imported => {
+                let removed = import::erase_files(&card, &placed, use_trash)?;
+                println!(
+                    "[INFO] Erased {} imported files from {}",
+                    removed,
+                    card.display()
+                );
+            }
+            Erase::All if !complete => {
+                eprintln!(
+                    "[WARN] Not erasing {}: only {} of {} files were imported",
+                    card.display(),
+                    placed.len(),
+                    stats.total.load(std::sync::atomic::Ordering::SeqCst)
+                );
+            }
+            Erase::All => {
+                let removed = import::erase_dcim(&card, use_trash)?;
+                println!("[INFO] Erased {} files from {}", removed, card.display());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy what's new in a source into its organized mirror
+fn mirror_source(
+    args: &Args,
+    terminate_flag: &Arc<AtomicBool>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(Command::Mirror {
+        source,
+        destination,
+        flag_removed,
+        dry_run,
+        exiftool,
+        preset,
+    }) = &args.command
+    else {
+        unreachable!("mirror_source is only called for the mirror command");
+    };
+    if !source.is_dir() {
+        return Err(format!("Source '{}' is not a directory", source.display()).into());
+    }
+
+    let mirror = Arc::new(
+        MirrorIndex::open(source, destination)
+            .map_err(|e| format!("Cannot read the mirror index: {}", e))?,
+    );
+    println!(
+        "[INFO] Mirroring {} into {} ({} files mirrored so far)",
+        source.display(),
+        destination.display(),
+        mirror.len()
+    );
+
+    let stats = Arc::new(Stats::new());
+    let mut organizer = Organizer::new(source.clone(), destination.clone(), *dry_run)
+        .with_preset(*preset)
+        .with_clock_offsets(clock_offsets()?)
+        .with_mirror(Arc::clone(&mirror))
+        .with_observer(Arc::new(ConsoleProgress::new(Arc::clone(&stats))));
+    if let Some(p) = exiftool.clone() {
+        organizer = organizer.with_exiftool(p);
+    }
+    if let Some(path) = MetadataCache::default_path() {
+        organizer = organizer.with_cache(MetadataCache::load(path));
+    }
+
+    organizer.run(Arc::clone(&stats), Arc::clone(terminate_flag))?;
+    stats.print();
 
+    if *flag_removed {
+        let removed = mirror.removed();
+        for (source_file, copy) in &removed {
+            println!(
+                "{} {} (mirrored as {})",
+                color::yellow("[REMOVED]"),
+                source_file,
+                copy.display()
+            );
+        }
+        println!(
+            "[INFO] {} mirrored files are gone from the source",
+            removed.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Run organize jobs sent over a local socket
+#[cfg(unix)]
+fn serve_jobs(
+    socket: &Path,
+    terminate_flag: &AtomicBool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("[INFO] Accepting jobs on {}", socket.display());
+    timekeeper::serve::serve(socket, Box::new(job_organizer), terminate_flag)
+        .map_err(|e| format!("Cannot serve on '{}': {}", socket.display(), e))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn serve_jobs(
+    _socket: &Path,
+    _terminate_flag: &AtomicBool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    Err("serve needs Unix domain sockets, it isn't available on this platform".into())
+}
+
+/// The organizer for a served job, from organize flags keyed by their long names
+#[cfg(unix)]
+fn job_organizer(
+    params: &serde_json::Map<String, serde_json::Value>,
+    stats: &Arc<Stats>,
+) -> Result<Organizer, String> {
+    let flags =
+        toml::Table::try_from(params).map_err(|e| format!("invalid job settings: {}", e))?;
+    let argv = std::iter::once("timekeeper".to_string()).chain(Settings { flags }.to_args()?);
+    let matches = Args::command()
+        .try_get_matches_from(argv)
+        .map_err(|e| e.render().to_string())?;
+    let args = Args::from_arg_matches(&matches).map_err(|e| e.to_string())?;
+    if args.command.is_some() {
+        return Err("a job runs organize, not a subcommand".into());
+    }
+    if args.schedule.is_some()
+        || args.status_port.is_some()
+        || args.report_dir.is_some()
+        || args.log_file.is_some()
+    {
+        return Err(
+            "a job runs once, without --schedule, --status-port, --report-dir or --log-file".into(),
+        );
+    }
+    let (sources, destination) = source_paths(&args).map_err(|e| e.to_string())?;
+    let [source] = sources.as_slice() else {
+        return Err("a job organizes a single source".into());
+    };
+    build_organizer(&args, source, destination.as_deref(), stats, &[]).map_err(|e| e.to_string())
+}
+
+/// Organize flags that belong to one run rather than to a preset
+const PER_RUN_FLAGS: [&str; 1] = ["source"];
+
+/// Parse organize flags and write the ones given as a preset
+fn save_settings(name: &str, flags: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let command = Args::command();
+    // Report bad flags the way clap reports them for any other command line
+    let matches = command
+        .clone()
+        .try_get_matches_from(
+            std::iter::once("timekeeper".to_string()).chain(flags.iter().cloned()),
+        )
+        .unwrap_or_else(|e| e.exit());
+    if matches.subcommand().is_some() {
+        return Err("a preset holds organize flags, not a subcommand".into());
+    }
+
+    let mut settings = Settings::default();
+    for arg in command.get_arguments() {
+        let id = arg.get_id().as_str();
+        let (Some(flag), Some(clap::parser::ValueSource::CommandLine)) =
+            (arg.get_long(), matches.value_source(id))
+        else {
+            continue;
+        };
+        if PER_RUN_FLAGS.contains(&id) {
+            continue;
+        }
+        let mut values: Vec<toml::Value> = matches
+            .get_raw(id)
+            .into_iter()
+            .flatten()
+            .map(|value| toml::Value::String(value.to_string_lossy().into_owned()))
+            .collect();
+        let value = match arg.get_action() {
+            ArgAction::SetTrue => toml::Value::Boolean(true),
+            ArgAction::Append => toml::Value::Array(values),
+            _ if values.len() == 1 => values.remove(0),
+            _ => toml::Value::Array(values),
+        };
+        settings.flags.insert(flag.to_string(), value);
+    }
+
+    let path = Settings::path_for(name)?;
+    settings
+        .save(&path)
+        .map_err(|e| format!("Cannot write preset '{}': {}", path.display(), e))?;
+    println!(
+        "[INFO] Saved {} settings to {}",
+        settings.flags.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Organize arguments from a preset, followed by the flags given with it
+fn load_settings(name: &str, flags: &[String]) -> Result<Args, Box<dyn std::error::Error>> {
+    let path = Settings::path_for(name)?;
+    let settings = Settings::load(&path)
+        .map_err(|e| format!("Cannot read preset '{}': {}", path.display(), e))?;
+    let args = std::iter::once("timekeeper".to_string())
+        .chain(settings.to_args()?)
+        .chain(flags.iter().cloned());
+    let matches = Args::command()
+        .try_get_matches_from(args)
+        .unwrap_or_else(|e| e.exit());
+    if matches.subcommand().is_some() {
+        return Err("a preset runs organize, not a subcommand".into());
+    }
+    Ok(Args::from_arg_matches(&matches)?)
+}
+
+/// The progress report asked for with `--progress` and `--progress-fd`, along with
+/// the other `watchers` of the run
+fn progress_observer(
+    args: &Args,
+    stats: &Arc<Stats>,
+    watchers: &[Arc<dyn ProgressObserver>],
+) -> Result<Arc<dyn ProgressObserver>, Box<dyn std::error::Error>> {
+    let stats = Arc::clone(stats);
+    let progress: Arc<dyn ProgressObserver> = match (args.progress_fd, args.progress) {
+        (Some(fd), _) => Arc::new(PlainProgress::new(stats, progress_stream(fd)?)),
+        (None, ProgressStyle::Human) => Arc::new(ConsoleProgress::new(stats)),
+        (None, ProgressStyle::Plain) => {
+            Arc::new(PlainProgress::new(stats, Box::new(std::io::stderr())))
+        }
+    };
+    if watchers.is_empty() {
+        return Ok(progress);
+    }
+    Ok(Arc::new(
+        std::iter::once(progress)
+            .chain(watchers.iter().cloned())
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// Open a file descriptor the parent process passed down, e.g. `3>progress.pipe`
+#[cfg(unix)]
+fn progress_stream(fd: i32) -> Result<Box<dyn std::io::Write + Send>, Box<dyn std::error::Error>> {
+    use std::os::fd::BorrowedFd;
+
+    // SAFETY: the descriptor is only borrowed for the duplicate, and duplicating a
+    // descriptor that isn't open fails with EBADF instead of touching anything
+    let owned = unsafe { BorrowedFd::borrow_raw(fd) }
+        .try_clone_to_owned()
+        .map_err(|e| format!("Cannot write progress to file descriptor {}: {}", fd, e))?;
+    Ok(Box::new(std::fs::File::from(owned)))
+}
+
+#[cfg(not(unix))]
+fn progress_stream(_fd: i32) -> Result<Box<dyn std::io::Write + Send>, Box<dyn std::error::Error>> {
+    Err("--progress-fd is only supported on Unix, use --progress plain".into())
+}
+
+/// Plan a dry run of the sources quietly (of the `only` files when given) and let the
+/// user pick the files to organize from it. `None` when the picking is cancelled.
+#[cfg(feature = "pick")]
+fn pick_files(
+    args: &Args,
+    sources: &[std::path::PathBuf],
+    destination: Option<&Path>,
+    only: Option<Arc<HashSet<std::path::PathBuf>>>,
+) -> Result<Option<Arc<HashSet<std::path::PathBuf>>>, Box<dyn std::error::Error>> {
+    use std::io::IsTerminal;
+    use timekeeper::outcome::Action;
+    use timekeeper::pick::{self, PickItem};
+
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        return Err("--pick needs a terminal".into());
+    }
+    let plan_args = Args {
+        dry_run: true,
+        itemize: false,
+        group_by_dest: false,
+        report_dir: None,
+        log_file: None,
+        ..args.clone()
+    };
+    let stats = Arc::new(Stats::new());
+    let terminate_flag = Arc::new(AtomicBool::new(false));
+    println!("[INFO] Planning the run...");
+
+    let mut items = Vec::new();
+    let mut root = None;
+    for source in sources {
+        let mut organizer = build_organizer(&plan_args, source, destination, &stats, &[])?;
+        if let Some(only) = &only {
+            organizer = organizer.with_only_files(Arc::clone(only));
+        }
+        organizer.observer = None;
+        root.get_or_insert_with(|| organizer.destination.clone());
+        let mut outcomes =
+            Arc::new(organizer).outcomes(Arc::clone(&stats), Arc::clone(&terminate_flag));
+        for outcome in &mut outcomes {
+            if let (Action::Copy | Action::Move, Some(destination)) =
+                (outcome.action, outcome.destination)
+            {
+                let bytes = std::fs::metadata(&outcome.source).map_or(0, |m| m.len());
+                items.push(PickItem {
+                    source: outcome.source,
+                    destination,
+                    bytes,
+                });
+            }
+        }
+        outcomes.finish()?;
+    }
+    if items.is_empty() {
+        return Ok(Some(Arc::new(HashSet::new())));
+    }
+
+    let picked = pick::pick(items, &root.unwrap_or_default())?;
+    Ok(picked.map(Arc::new))
+}
+
+#[cfg(not(feature = "pick"))]
+fn pick_files(
+    _args: &Args,
+    _sources: &[std::path::PathBuf],
+    _destination: Option<&Path>,
+    _only: Option<Arc<HashSet<std::path::PathBuf>>>,
+) -> Result<Option<Arc<HashSet<std::path::PathBuf>>>, Box<dyn std::error::Error>> {
+    Err("--pick needs timekeeper built with the pick feature".into())
+}
+
+/// Check that `--use-trash` can be honored rather than silently deleting for good
+fn trash_enabled(use_trash: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    if use_trash && cfg!(not(feature = "trash")) {
+        return Err("--use-trash needs timekeeper built with the trash feature".into());
+    }
+    Ok(use_trash)
+}
+
+/// Show what a large run is about to do and ask whether to go ahead, so a mistyped
+/// destination is caught before thousands of files land in it. Dry runs, `--yes`
+/// and runs without a terminal to ask on go ahead right away.
+fn confirm_run(args: &Args, organizers: &[Organizer]) -> Result<bool, Box<dyn std::error::Error>> {
+    use std::io::{BufRead, IsTerminal, Write};
+
+    if args.dry_run || args.yes || !std::io::stdin().is_terminal() {
+        return Ok(true);
+    }
+    let (mut files, mut bytes) = (0, 0);
+    for organizer in organizers {
+        let (source_files, source_bytes) = organizer.count_media()?;
+        files += source_files;
+        bytes += source_bytes;
+    }
+    if files <= args.confirm_over_files && bytes <= args.confirm_over_size {
+        return Ok(true);
+    }
+
+    let organizer = &organizers[0];
+    let action = if args.rename_only {
+        "rename"
+    } else if organizer.use_copy {
+        "copy"
+    } else {
+        "move"
+    };
+    println!(
+        "About to {} {} files ({})",
+        action,
+        files,
+        space::format_bytes(bytes)
+    );
+    for organizer in organizers {
+        println!("  from   {}", organizer.source.display());
+    }
+    if !args.rename_only {
+        println!("  into   {}", organizer.destination.display());
+        let example = organizer.layout.dest_for(&FileMeta::new(
+            &organizer.source,
+            &organizer.source,
+            Local::now(),
+        ));
+        println!("  layout {} for a file taken today", example.display());
+    }
+    print!("Continue? [y/N]: ");
+    std::io::stdout().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Ask whether to clear the card. Without a terminal to ask on, nothing is erased.
+fn ask_erase(
+    card: &std::path::Path,
+    imported: usize,
+    complete: bool,
+) -> Result<Erase, Box<dyn std::error::Error>> {
+    use std::io::{BufRead, IsTerminal, Write};
+
+    if imported == 0 || !std::io::stdin().is_terminal() {
+        return Ok(Erase::None);
+    }
+    if complete {
+        print!(
+            "Erase {}? [a]ll of DCIM, only the [i]mported files, or [N]othing: ",
+            card.display()
+        );
+    } else {
+        print!(
+            "Not every file was imported. Erase the {} imported files from {}? [i]mported, or [N]othing: ",
+            imported,
+            card.display()
+        );
+    }
+    std::io::stdout().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(match answer.trim().to_lowercase().as_str() {
+        "a" | "all" if complete => Erase::All,
+        "i" | "imported" => Erase::Imported,
+        _ => Erase::None,
+    })
+}
+
+/// Re-hash the next part of a destination's manifest, exiting with 1 on rotten files
+fn scrub(
+    destination: &Path,
+    fraction: f64,
+    terminate_flag: &AtomicBool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let manifest_path = [HashAlgorithm::Sha256, HashAlgorithm::Blake3]
+        .iter()
+        .map(|algorithm| destination.join(algorithm.file_name()))
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            format!(
+                "No manifest in '{}', organize into it with --manifest first",
+                destination.display()
+            )
+        })?;
+    let manifest = Manifest::load(&manifest_path)?;
+    let report = manifest.scrub(fraction, terminate_flag)?;
+
+    for relative in &report.missing {
+        println!("[MISSING] {}", relative);
+    }
+    for relative in &report.modified {
+        println!("[MODIFIED] {}", relative);
+    }
+    for relative in &report.corrupted {
+        println!("{}", color::red(&format!("[CORRUPTED] {}", relative)));
+    }
+    println!(
+        "[INFO] Scrubbed {} of {} files: {} missing, {} modified, {} corrupted",
+        report.checked,
+        report.total,
+        report.missing.len(),
+        report.modified.len(),
+        report.corrupted.len()
+    );
+
+    if !report.corrupted.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Check an organized tree against its manifest, exiting non-zero on any mismatch
+fn verify_manifest(path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+    let manifest = Manifest::load(path)?;
+    let report = manifest.verify();
+
+    for relative in &report.missing {
+        println!("[MISSING] {}", relative);
+    }
+    for relative in &report.modified {
+        println!("[MODIFIED] {}", relative);
+    }
+    for relative in &report.extra {
+        println!("[EXTRA] {}", relative);
+    }
+    println!(
+        "[INFO] Checked {} files: {} missing, {} modified, {} extra",
+        report.checked,
+        report.missing.len(),
+        report.modified.len(),
+        report.extra.len()
+    );
+
+    if !report.is_clean() {
+        std::process::exit(1);
+    }
     Ok(())
 }
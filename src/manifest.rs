@@ -0,0 +1,495 @@
+use chrono::{DateTime, Local};
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::error::{Result, TimekeeperError};
+
+/// Hash used for the archival manifest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// Manifest file name written at the root of the destination
+    pub fn file_name(&self) -> String {
+        format!("MANIFEST.{}", self.name())
+    }
+
+    /// Hex digest of the file's contents
+    pub fn hash_file(&self, path: &Path) -> io::Result<String> {
+        let mut file = File::open(path)?;
+        match self {
+            HashAlgorithm::Sha256 => {
+                let mut hasher = Sha256::new();
+                io::copy(&mut file, &mut hasher)?;
+                Ok(format!("{:x}", hasher.finalize()))
+            }
+            HashAlgorithm::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                io::copy(&mut file, &mut hasher)?;
+                Ok(hasher.finalize().to_hex().to_string())
+            }
+        }
+    }
+
+    /// Hex digest of data already in memory
+    pub fn hash_bytes(&self, data: &[u8]) -> String {
+        match self {
+            HashAlgorithm::Sha256 => format!("{:x}", Sha256::digest(data)),
+            HashAlgorithm::Blake3 => blake3::hash(data).to_hex().to_string(),
+        }
+    }
+}
+
+impl std::str::FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            _ => Err(format!(
+                "unknown hash algorithm '{}' (expected sha256 or blake3)",
+                s
+            )),
+        }
+    }
+}
+
+/// Header naming the columns of the manifest lines
+const COLUMNS_HEADER: &str = "# hash\tsize\tcaptured\tmodified\tpath";
+
+/// Column header of manifests written before the modification time was recorded
+const LEGACY_COLUMNS_HEADER: &str = "# hash\tsize\tcaptured\tpath";
+
+/// Where `scrub` remembers how far through the manifest it got, next to the manifest
+const SCRUB_STATE_FILE: &str = "MANIFEST.scrub";
+
+/// One placed file in the manifest
+#[derive(Debug, Clone)]
+pub struct ManifestEntry {
+    pub hash: String,
+    pub size: u64,
+    pub captured: DateTime<Local>,
+    /// Modification time when the file was hashed, in seconds since the Unix epoch.
+    /// `None` for entries of manifests written before it was recorded.
+    pub modified: Option<i64>,
+}
+
+/// Manifest of every file placed in the destination: its hash, size, capture date
+/// and modification time. Stored as one tab-separated `hash size captured modified path`
+/// line per file, with paths relative to the manifest's directory and `#` comment
+/// lines as a header. Manifests without the `modified` column are still read.
+#[derive(Debug)]
+pub struct Manifest {
+    path: PathBuf,
+    root: PathBuf,
+    algorithm: HashAlgorithm,
+    entries: Mutex<BTreeMap<String, ManifestEntry>>,
+    /// Paths recorded since the manifest was last saved
+    recorded: Mutex<BTreeSet<String>>,
+}
+
+impl Manifest {
+    /// Open the manifest kept at the root of `destination`, picking up the entries
+    /// of earlier runs so the manifest keeps covering the whole collection
+    pub fn open(destination: &Path, algorithm: HashAlgorithm) -> Result<Self> {
+        let path = destination.join(algorithm.file_name());
+        if path.exists() {
+            return Self::load(&path);
+        }
+
+        Ok(Manifest {
+            path,
+            root: destination.to_path_buf(),
+            algorithm,
+            entries: Mutex::new(BTreeMap::new()),
+            recorded: Mutex::new(BTreeSet::new()),
+        })
+    }
+
+    /// Read an existing manifest. The algorithm comes from the file extension.
+    pub fn load(path: &Path) -> Result<Self> {
+        let algorithm: HashAlgorithm = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .ok_or_else(|| format!("Cannot tell the hash algorithm of '{}'", path.display()))
+            .and_then(str::parse)
+            .map_err(TimekeeperError::ParseError)?;
+
+        let mut entries = BTreeMap::new();
+        let mut legacy = false;
+        for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+            if line == LEGACY_COLUMNS_HEADER {
+                legacy = true;
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (relative, entry) = parse_line(line, legacy).ok_or_else(|| {
+                TimekeeperError::ParseError(format!(
+                    "Malformed manifest line {} in '{}'",
+                    number + 1,
+                    path.display()
+                ))
+            })?;
+            entries.insert(relative, entry);
+        }
+
+        Ok(Manifest {
+            path: path.to_path_buf(),
+            root: path.parent().unwrap_or(Path::new(".")).to_path_buf(),
+            algorithm,
+            entries: Mutex::new(entries),
+            recorded: Mutex::new(BTreeSet::new()),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Hash a file that was just placed and add it to the manifest
+    pub fn record(&self, file: &Path, captured: &DateTime<Local>) -> io::Result<()> {
+        let relative = relative_key(&self.root, file).ok_or_else(|| {
+            io::Error::other(format!(
+                "'{}' is outside the manifest root '{}'",
+                file.display(),
+                self.root.display()
+            ))
+        })?;
+        let metadata = fs::metadata(file)?;
+        let entry = ManifestEntry {
+            hash: self.algorithm.hash_file(file)?,
+            size: metadata.len(),
+            captured: *captured,
+            modified: modified_secs(&metadata),
+        };
+
+        self.entries.lock().unwrap().insert(relative.clone(), entry);
+        self.recorded.lock().unwrap().insert(relative);
+        Ok(())
+    }
+
+    /// Write the manifest if anything was recorded. Written atomically via a temp file.
+    /// Entries saved to the file since it was opened, by the run of another source
+    /// into the same destination, are merged in rather than overwritten.
+    pub fn save(&self) -> io::Result<()> {
+        let recorded = std::mem::take(&mut *self.recorded.lock().unwrap());
+        if recorded.is_empty() {
+            return Ok(());
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if self.path.exists() {
+            let saved = Self::load(&self.path).map_err(io::Error::other)?;
+            for (relative, entry) in saved.entries.into_inner().unwrap() {
+                if !recorded.contains(&relative) {
+                    entries.insert(relative, entry);
+                }
+            }
+        }
+        let mut out = format!(
+            "# timekeeper manifest ({})\n{}\n",
+            self.algorithm.name(),
+            COLUMNS_HEADER
+        );
+        for (relative, entry) in entries.iter() {
+            out.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                entry.hash,
+                entry.size,
+                entry.captured.format("%Y-%m-%dT%H:%M:%S%:z"),
+                entry
+                    .modified
+                    .map(|secs| secs.to_string())
+                    .unwrap_or_default(),
+                relative
+            ));
+        }
+
+        fs::create_dir_all(&self.root)?;
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, out)?;
+        fs::rename(&tmp_path, &self.path)
+    }
+
+    /// Re-hash every listed file and compare the tree under the manifest's directory
+    /// against it. Manifest files themselves are not counted as extra.
+    pub fn verify(&self) -> VerifyReport {
+        let entries = self.entries.lock().unwrap();
+
+        let mut results: Vec<(&String, Option<bool>)> = entries
+            .par_iter()
+            .map(|(relative, entry)| {
+                let file = self.root.join(relative);
+                let status = match fs::metadata(&file) {
+                    Err(_) => None,
+                    Ok(metadata) if metadata.len() != entry.size => Some(false),
+                    Ok(_) => Some(
+                        self.algorithm
+                            .hash_file(&file)
+                            .is_ok_and(|hash| hash == entry.hash),
+                    ),
+                };
+                (relative, status)
+            })
+            .collect();
+        results.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut report = VerifyReport {
+            checked: results.len(),
+            ..Default::default()
+        };
+        for (relative, status) in results {
+            match status {
+                None => report.missing.push(relative.clone()),
+                Some(false) => report.modified.push(relative.clone()),
+                Some(true) => {}
+            }
+        }
+
+        for entry in ignore::WalkBuilder::new(&self.root)
+            .standard_filters(false)
+            .build()
+            .flatten()
+        {
+            if !entry.file_type().is_some_and(|t| t.is_file())
+                || entry.file_name().to_string_lossy().starts_with("MANIFEST.")
+            {
+                continue;
+            }
+            if let Some(relative) = relative_key(&self.root, entry.path())
+                && !entries.contains_key(&relative)
+            {
+                report.extra.push(relative);
+            }
+        }
+        report.extra.sort();
+
+        report
+    }
+
+    /// Re-hash the next `fraction` of the listed files, carrying on where the previous
+    /// scrub stopped so that repeated scrubs cover the whole collection. A file whose
+    /// contents changed while its modification time didn't has rotted on disk.
+    pub fn scrub(&self, fraction: f64, terminate_flag: &AtomicBool) -> io::Result<ScrubReport> {
+        let entries = self.entries.lock().unwrap();
+        let state_path = self.root.join(SCRUB_STATE_FILE);
+        // The last file checked; a file removed from the manifest since is simply passed
+        let last = fs::read_to_string(&state_path)
+            .map(|text| text.trim_end_matches('\n').to_string())
+            .unwrap_or_default();
+
+        let count = ((entries.len() as f64 * fraction).ceil() as usize).min(entries.len());
+        let after = entries
+            .range::<String, _>((std::ops::Bound::Excluded(&last), std::ops::Bound::Unbounded));
+        let batch: Vec<(&String, &ManifestEntry)> =
+            after.chain(entries.iter()).take(count).collect();
+
+        let results: Vec<(&String, ScrubStatus)> = batch
+            .par_iter()
+            .map(|(relative, entry)| {
+                if terminate_flag.load(Ordering::SeqCst) {
+                    return (*relative, ScrubStatus::Skipped);
+                }
+                (*relative, self.scrub_file(relative, entry))
+            })
+            .collect();
+
+        let mut report = ScrubReport {
+            total: entries.len(),
+            ..Default::default()
+        };
+        for (relative, status) in &results {
+            match status {
+                ScrubStatus::Intact => {}
+                ScrubStatus::Skipped => continue,
+                ScrubStatus::Missing => report.missing.push((*relative).clone()),
+                ScrubStatus::Modified => report.modified.push((*relative).clone()),
+                ScrubStatus::Corrupted => report.corrupted.push((*relative).clone()),
+            }
+            report.checked += 1;
+        }
+
+        // Only files actually checked move the position on
+        let checked_up_to = results
+            .iter()
+            .take_while(|(_, status)| *status != ScrubStatus::Skipped)
+            .last();
+        if let Some((relative, _)) = checked_up_to {
+            fs::write(&state_path, format!("{}\n", relative))?;
+        }
+        Ok(report)
+    }
+
+    fn scrub_file(&self, relative: &str, entry: &ManifestEntry) -> ScrubStatus {
+        let file = self.root.join(relative);
+        let Ok(metadata) = fs::metadata(&file) else {
+            return ScrubStatus::Missing;
+        };
+        let intact = metadata.len() == entry.size
+            && self
+                .algorithm
+                .hash_file(&file)
+                .is_ok_and(|hash| hash == entry.hash);
+        match (intact, entry.modified) {
+            (true, _) => ScrubStatus::Intact,
+            (false, Some(modified)) if modified_secs(&metadata) == Some(modified) => {
+                ScrubStatus::Corrupted
+            }
+            (false, _) => ScrubStatus::Modified,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScrubStatus {
+    Intact,
+    Missing,
+    Modified,
+    Corrupted,
+    /// Not checked, the scrub was interrupted
+    Skipped,
+}
+
+fn parse_line(line: &str, legacy: bool) -> Option<(String, ManifestEntry)> {
+    let mut fields = line.splitn(if legacy { 4 } else { 5 }, '\t');
+    let hash = fields.next()?.to_string();
+    let size = fields.next()?.parse().ok()?;
+    let captured = DateTime::parse_from_rfc3339(fields.next()?)
+        .ok()?
+        .with_timezone(&Local);
+    let modified = match legacy {
+        true => None,
+        false => match fields.next()? {
+            "" => None,
+            secs => Some(secs.parse().ok()?),
+        },
+    };
+    let relative = fields.next()?.to_string();
+
+    Some((
+        relative,
+        ManifestEntry {
+            hash,
+            size,
+            captured,
+            modified,
+        },
+    ))
+}
+
+/// A file's modification time in whole seconds since the Unix epoch
+pub(crate) fn modified_secs(metadata: &fs::Metadata) -> Option<i64> {
+    let modified = metadata.modified().ok()?;
+    Some(DateTime::<chrono::Utc>::from(modified).timestamp())
+}
+
+/// The manifests of a run's destination roots, one at the root of each. Roots below
+/// another root have no manifest of their own, the outer one covers their files.
+#[derive(Debug)]
+pub struct Manifests {
+    manifests: Vec<Manifest>,
+}
+
+impl Manifests {
+    /// Open the manifest of every root in `roots`
+    pub fn open<'a>(
+        roots: impl IntoIterator<Item = &'a PathBuf>,
+        algorithm: HashAlgorithm,
+    ) -> Result<Self> {
+        let roots: Vec<&PathBuf> = roots.into_iter().collect();
+        let mut manifests: Vec<Manifest> = Vec::new();
+        for (index, root) in roots.iter().enumerate() {
+            // A root given twice gets its manifest once, for the first mention
+            let covered = roots.iter().enumerate().any(|(other, outer)| {
+                other != index && root.starts_with(outer) && (root != outer || other < index)
+            });
+            if !covered {
+                manifests.push(Manifest::open(root, algorithm)?);
+            }
+        }
+        Ok(Manifests { manifests })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Manifest> {
+        self.manifests.iter()
+    }
+
+    /// Hash a file that was just placed into the manifest of the root it is under
+    pub fn record(&self, file: &Path, captured: &DateTime<Local>) -> io::Result<()> {
+        self.manifests
+            .iter()
+            .find(|manifest| file.starts_with(&manifest.root))
+            .ok_or_else(|| {
+                io::Error::other(format!(
+                    "'{}' is outside every destination root",
+                    file.display()
+                ))
+            })?
+            .record(file, captured)
+    }
+}
+
+/// `/`-separated path of `file` relative to `root`, the same on every platform
+pub(crate) fn relative_key(root: &Path, file: &Path) -> Option<String> {
+    let relative = file.strip_prefix(root).ok()?;
+    let parts: Vec<_> = relative
+        .components()
+        .map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+
+    Some(parts.join("/"))
+}
+
+/// Differences between a manifest and the tree it describes
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub checked: usize,
+    /// Listed in the manifest but no longer on disk
+    pub missing: Vec<String>,
+    /// Size or hash no longer matches the manifest
+    pub modified: Vec<String>,
+    /// On disk but not listed in the manifest
+    pub extra: Vec<String>,
+}
+
+/// What a scrub found in the part of the collection it re-hashed
+#[derive(Debug, Default)]
+pub struct ScrubReport {
+    /// Files re-hashed by this scrub
+    pub checked: usize,
+    /// Files listed in the manifest
+    pub total: usize,
+    /// Listed in the manifest but no longer on disk
+    pub missing: Vec<String>,
+    /// Contents changed along with the modification time, e.g. edited in place
+    pub modified: Vec<String>,
+    /// Contents changed but the modification time didn't: bit rot or a failing disk
+    pub corrupted: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.modified.is_empty() && self.extra.is_empty()
+    }
+}
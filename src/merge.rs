@@ -0,0 +1,191 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::dupes::is_csv;
+use crate::manifest::HashAlgorithm;
+use crate::metadata;
+use crate::transfer::{self, CopyWatch};
+
+/// Outcome of `merge_trees`
+#[derive(Debug, Default, Serialize)]
+pub struct MergeSummary {
+    /// Files copied into the destination, renamed ones included
+    pub copied: usize,
+    /// Files whose contents the destination already held, left out
+    pub duplicates: usize,
+    /// Copies given a `_1` suffix because a different file had their path
+    pub renamed: usize,
+    pub failed: usize,
+    /// Bytes copied
+    pub bytes: u64,
+    /// Bytes of the duplicates left out
+    pub saved: u64,
+}
+
+impl MergeSummary {
+    /// Write the summary as JSON, or as a one-row CSV for a `.csv` path
+    pub fn export(&self, path: &Path) -> io::Result<()> {
+        let contents = if is_csv(path) {
+            format!(
+                "copied,bytes,renamed,failed,duplicates,saved\n{},{},{},{},{},{}\n",
+                self.copied, self.bytes, self.renamed, self.failed, self.duplicates, self.saved
+            )
+        } else {
+            serde_json::to_string_pretty(self)?
+        };
+        fs::write(path, contents)
+    }
+}
+
+/// Contents held by the destination. Files found there before the merge are only
+/// hashed once a file of the same size comes along.
+struct Contents {
+    algorithm: HashAlgorithm,
+    unhashed: HashMap<u64, Vec<PathBuf>>,
+    known: HashMap<(u64, String), PathBuf>,
+}
+
+impl Contents {
+    fn index(destination: &Path, algorithm: HashAlgorithm) -> Contents {
+        let mut unhashed: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+        // A new destination holds nothing yet
+        let existing = destination.is_dir().then(|| tree_files(destination));
+        for path in existing.into_iter().flatten() {
+            match fs::metadata(&path) {
+                Ok(metadata) => unhashed.entry(metadata.len()).or_default().push(path),
+                Err(e) => eprintln!("[WARN] Cannot read {}: {}", path.display(), e),
+            }
+        }
+        Contents {
+            algorithm,
+            unhashed,
+            known: HashMap::new(),
+        }
+    }
+
+    /// The destination file with the same contents, if there is one
+    fn find(&mut self, size: u64, hash: &str) -> Option<&PathBuf> {
+        for path in self.unhashed.remove(&size).unwrap_or_default() {
+            match self.algorithm.hash_file(&path) {
+                Ok(hash) => {
+                    self.known.entry((size, hash)).or_insert(path);
+                }
+                Err(e) => eprintln!("[WARN] Cannot hash {}: {}", path.display(), e),
+            }
+        }
+        self.known.get(&(size, hash.to_string()))
+    }
+}
+
+/// Combine trees organized before (by timekeeper or by hand) into `destination`.
+/// Every file keeps its path relative to its tree, the layout is already applied.
+/// Files whose contents the destination holds anywhere, from an earlier tree or
+/// from before the merge, are left out. A different file taking the same path gets
+/// the usual `_1` suffix. The trees are only read, and hidden files and the trees'
+/// manifests are left out.
+pub fn merge_trees(
+    trees: &[PathBuf],
+    destination: &Path,
+    algorithm: HashAlgorithm,
+    dry_run: bool,
+    terminate_flag: &AtomicBool,
+) -> io::Result<MergeSummary> {
+    let mut contents = Contents::index(destination, algorithm);
+    // Paths planned in a dry run, which don't exist to be found
+    let mut planned = HashSet::new();
+    let mut summary = MergeSummary::default();
+
+    for tree in trees {
+        for source in tree_files(tree) {
+            if terminate_flag.load(Ordering::SeqCst) {
+                return Ok(summary);
+            }
+            let relative = source.strip_prefix(tree).unwrap_or(&source);
+            if is_manifest(relative) {
+                continue;
+            }
+            let identity = fs::metadata(&source).and_then(|metadata| {
+                algorithm
+                    .hash_file(&source)
+                    .map(|hash| (metadata.len(), hash))
+            });
+            let (size, hash) = match identity {
+                Ok(identity) => identity,
+                Err(e) => {
+                    eprintln!("[WARN] Cannot hash {}: {}", source.display(), e);
+                    summary.failed += 1;
+                    continue;
+                }
+            };
+            if let Some(existing) = contents.find(size, &hash) {
+                println!(
+                    "[SKIP] Same as {}: {}",
+                    existing.display(),
+                    source.display()
+                );
+                summary.duplicates += 1;
+                summary.saved += size;
+                continue;
+            }
+
+            let wanted = destination.join(relative);
+            let target = metadata::get_unique_file_path(&wanted, |path| {
+                Ok(planned.contains(path) || path.exists())
+            })?;
+            if target != wanted {
+                summary.renamed += 1;
+            }
+            println!(
+                "{}Copying: {} -> {}",
+                if dry_run { "[DRY RUN] " } else { "" },
+                source.display(),
+                target.display()
+            );
+            if !dry_run && let Err(e) = copy_into(&source, &target) {
+                eprintln!("[WARN] Cannot copy {}: {}", source.display(), e);
+                summary.failed += 1;
+                continue;
+            }
+            summary.copied += 1;
+            summary.bytes += size;
+            planned.insert(target.clone());
+            contents.known.insert((size, hash), target);
+        }
+    }
+    Ok(summary)
+}
+
+fn copy_into(source: &Path, target: &Path) -> io::Result<()> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    transfer::copy_file(source, target, &CopyWatch::default()).map(|_| ())
+}
+
+/// Manifests at the root of a tree describe that tree, not the merged one
+fn is_manifest(relative: &Path) -> bool {
+    [HashAlgorithm::Sha256, HashAlgorithm::Blake3]
+        .iter()
+        .any(|algorithm| relative == Path::new(&algorithm.file_name()))
+}
+
+/// Regular files below `root`, leaving out hidden ones such as the mirror index
+fn tree_files(root: &Path) -> impl Iterator<Item = PathBuf> {
+    ignore::WalkBuilder::new(root)
+        .standard_filters(false)
+        .hidden(true)
+        .build()
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                eprintln!("[WARN] {}", e);
+                None
+            }
+        })
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .map(ignore::DirEntry::into_path)
+}
@@ -0,0 +1,88 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use regex::Regex;
+use std::path::Path;
+
+lazy_static::lazy_static! {
+    // IMG-20230514-WA0003.jpg, VID-20230514-WA0001.mp4
+    static ref WHATSAPP_LEGACY: Regex =
+        Regex::new(r"^(?:IMG|VID|AUD|PTT|STK)-(\d{4})(\d{2})(\d{2})-WA\d+").unwrap();
+    // WhatsApp Image 2023-05-14 at 10.23.45.jpeg
+    static ref WHATSAPP_DESKTOP: Regex = Regex::new(
+        r"^WhatsApp (?:Image|Video) (\d{4})-(\d{2})-(\d{2}) at (\d{1,2})\.(\d{2})\.(\d{2})"
+    )
+    .unwrap();
+    // photo_2023-05-14_10-23-45.jpg, video_2023-05-14_10-23-45.mp4
+    static ref TELEGRAM: Regex = Regex::new(
+        r"^(?:photo|video)_(\d{4})-(\d{2})-(\d{2})_(\d{2})-(\d{2})-(\d{2})"
+    )
+    .unwrap();
+    // signal-2023-05-14-102345.jpg, signal-2023-05-14-10-23-45-123.jpg
+    static ref SIGNAL: Regex = Regex::new(
+        r"^signal-(\d{4})-(\d{2})-(\d{2})-(\d{2})-?(\d{2})-?(\d{2})"
+    )
+    .unwrap();
+}
+
+/// Messaging apps whose received media is recognized by filename.
+/// These apps strip EXIF on send and the file's mtime is the download date,
+/// so the filename is the only trustworthy hint of when it was taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessagingApp {
+    WhatsApp,
+    Telegram,
+    Signal,
+}
+
+impl std::fmt::Display for MessagingApp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MessagingApp::WhatsApp => write!(f, "WhatsApp"),
+            MessagingApp::Telegram => write!(f, "Telegram"),
+            MessagingApp::Signal => write!(f, "Signal"),
+        }
+    }
+}
+
+/// Detect whether a file looks like media received through a messaging app.
+/// Only the file name is checked, the contents are never read: a received file
+/// that was renamed isn't recognized, and a camera file named like one is.
+pub fn detect_messaging_app(path: &Path) -> Option<MessagingApp> {
+    let name = path.file_name()?.to_str()?;
+
+    if WHATSAPP_LEGACY.is_match(name) || WHATSAPP_DESKTOP.is_match(name) {
+        Some(MessagingApp::WhatsApp)
+    } else if TELEGRAM.is_match(name) {
+        Some(MessagingApp::Telegram)
+    } else if SIGNAL.is_match(name) {
+        Some(MessagingApp::Signal)
+    } else {
+        None
+    }
+}
+
+/// Extract the date embedded in a messaging-app filename.
+/// WhatsApp's legacy names only carry a date, which is interpreted as local midnight.
+pub fn messaging_filename_datetime(path: &Path) -> Option<DateTime<Local>> {
+    let name = path.file_name()?.to_str()?;
+
+    let naive = if let Some(caps) = WHATSAPP_LEGACY.captures(name) {
+        NaiveDate::from_ymd_opt(
+            caps[1].parse().ok()?,
+            caps[2].parse().ok()?,
+            caps[3].parse().ok()?,
+        )?
+        .and_hms_opt(0, 0, 0)?
+    } else {
+        let caps = WHATSAPP_DESKTOP
+            .captures(name)
+            .or_else(|| TELEGRAM.captures(name))
+            .or_else(|| SIGNAL.captures(name))?;
+        let text = format!(
+            "{}-{}-{} {}:{}:{}",
+            &caps[1], &caps[2], &caps[3], &caps[4], &caps[5], &caps[6]
+        );
+        NaiveDateTime::parse_from_str(&text, "%Y-%m-%d %H:%M:%S").ok()?
+    };
+
+    Some(crate::localtime::resolve(&naive))
+}
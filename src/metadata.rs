@@ -1,14 +1,55 @@
-use chrono::{DateTime, Datelike, Local};
+use chrono::{DateTime, Local};
+use std::cell::Cell;
 use std::collections::HashSet;
+use std::ffi::OsString;
 use std::fs;
-use std::path::PathBuf;
-use std::sync::Arc;
-use std::sync::atomic::Ordering;
-
-use crate::exiftool;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::copy_report::CopyReport;
+use crate::error::{ErrorCategory, Result, TimekeeperError};
+use crate::exiftool::ExifWriter;
+use crate::extract::ExtractorChain;
+use crate::layout::{self, FileMeta, FileNaming, Layout};
+use crate::manifest::{HashAlgorithm, Manifests};
+use crate::mirror::MirrorIndex;
+use crate::motion;
+use crate::outcome::DateSource;
+use crate::progress::ProgressObserver;
+use crate::remote::RemoteStore;
+use crate::removal;
+use crate::smb;
 use crate::stats::Stats;
+use crate::template::Sequences;
+use crate::transfer::{self, CopyWatch};
 
 lazy_static::lazy_static! {
+    static ref VIDEO_EXTENSIONS: HashSet<&'static str> = {
+        let mut set = HashSet::new();
+        set.insert("mp4");
+        set.insert("mov");
+        set.insert("avi");
+        set.insert("mkv");
+        set.insert("wmv");
+        set.insert("m4v");
+        set.insert("3gp");
+        set.insert("webm");
+
+        set
+    };
+
+    static ref RAW_EXTENSIONS: HashSet<&'static str> = {
+        let mut set = HashSet::new();
+        set.insert("raw");
+        set.insert("cr2");
+        set.insert("nef");
+        set.insert("arw");
+        set.insert("dng");
+
+        set
+    };
+
     static ref SUPPORTED_EXTENSIONS: HashSet<&'static str> = {
         let mut set = HashSet::new();
         // Image formats
@@ -17,25 +58,13 @@ lazy_static::lazy_static! {
         set.insert("png");
         set.insert("tiff");
         set.insert("tif");
-        set.insert("raw");
-        set.insert("cr2");
-        set.insert("nef");
-        set.insert("arw");
-        set.insert("dng");
+        set.extend(RAW_EXTENSIONS.iter());
         set.insert("heic");
         set.insert("webp");
         set.insert("gif");
 
         // Video formats
-        set.insert("mp4");
-        set.insert("mov");
-        set.insert("avi");
-        set.insert("mkv");
-        set.insert("wmv");
-        set.insert("m4v");
-        set.insert("3gp");
-        set.insert("webm");
-
+        set.extend(VIDEO_EXTENSIONS.iter());
 
         set
     };
@@ -52,154 +81,688 @@ pub fn is_media_file(filename: &str) -> bool {
     }
 }
 
-pub fn process_with_exiftool(
-    exiftool_path: &PathBuf,
-    source_path: &PathBuf,
-    dest_base: &PathBuf,
-    dry_run: bool,
-    use_copy: bool,
+/// Number of files checked per folder before trusting it is already organized
+const ORGANIZED_SAMPLE_SIZE: usize = 3;
+
+/// Check whether a folder already follows the destination layout: a small sample of its
+/// media files must all be dated so that the layout would place them in this very folder.
+/// With `keep_album`, album folders below the date folders count as well.
+/// Only the sample goes through exiftool, not every file in the folder.
+pub fn is_organized_folder(
+    extractors: &ExtractorChain,
+    dir: &Path,
+    layout: &dyn Layout,
+    keep_album: bool,
+) -> bool {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return false;
+    };
+    let samples: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.to_str().is_some_and(is_media_file))
+        .take(ORGANIZED_SAMPLE_SIZE)
+        .collect();
+    let Some(dated) = samples
+        .iter()
+        .map(|sample| Some((sample, extractors.extract(sample).ok()?.datetime)))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return false;
+    };
+
+    // The layout's folders sit `depth` levels below whatever base the folder was
+    // organized into, one more for an album
+    let organized_under = |depth: usize, album: bool| {
+        let Some(base) = dir.ancestors().nth(depth) else {
+            return false;
+        };
+        dated.iter().all(|(sample, datetime)| {
+            let meta = FileMeta::new(sample, base, *datetime);
+            let mut expected = base.join(layout.dest_for(&meta));
+            if album {
+                match meta.folder_title() {
+                    Some(title) => expected.push(title),
+                    None => return false,
+                }
+            }
+            expected == dir
+        })
+    };
+
+    !dated.is_empty()
+        && (organized_under(layout.depth(), false)
+            || keep_album && organized_under(layout.depth() + 1, true))
+}
+
+/// Folder below the destination for files rated high enough with `selects_rating`
+const SELECTS_DIR: &str = "Selects";
+
+/// How files are placed in the destination, shared by every file of a run
+#[derive(Clone)]
+pub struct PlaceOptions {
+    pub layout: Arc<dyn Layout>,
+    pub naming: FileNaming,
+    /// Carry XMP sidecars along with their media file
+    pub sidecars: bool,
+    /// Save the clip inside motion photos next to them
+    pub motion_clips: bool,
+    /// Place videos in a Plex/Jellyfin `Home Videos` library instead of the date tree
+    pub home_videos: bool,
+    /// Put files in a folder named after their source folder, below the date folders
+    pub keep_album: bool,
+    /// Recreate the source's folders below the date folders
+    pub preserve_structure: bool,
+    /// Rename files where they are instead of moving them into the layout
+    pub rename_only: bool,
+    /// Files rated at least this many stars go to the `Selects` tree
+    pub selects_rating: Option<i32>,
+    /// Source root, so a file directly inside it doesn't take the root's name as its title
+    pub source_root: PathBuf,
+    pub dry_run: bool,
+    pub use_copy: bool,
+    /// Archival manifest every placed file is hashed into
+    pub manifest: Option<Arc<Manifests>>,
+    /// Remote store files are uploaded to instead of a local directory
+    pub remote: Option<Arc<dyn RemoteStore>>,
+    /// Hash every local copy and compare it against its source
+    pub verify: bool,
+    /// Lists every verified copy with its hash (`--verify-only-report`)
+    pub copy_report: Option<Arc<CopyReport>>,
+    /// Collects the source of every placed file
+    pub placed: Option<Arc<PlacedSources>>,
+    /// Index of the mirror being updated, noting every copied file
+    pub mirror: Option<Arc<MirrorIndex>>,
+    /// Edits the metadata of placed files (`--write-exif`, `--strip-gps`)
+    pub exif_writer: Option<ExifWriter>,
+    /// Set each placed file's modification time to its capture date
+    pub sync_mtime: bool,
+    /// Send deleted originals to the system trash instead of removing them for good
+    pub use_trash: bool,
+    /// `{seq}` numbers handed out so far
+    pub sequences: Arc<Sequences>,
+    /// Stops copies part way through a file
+    pub terminate_flag: Option<Arc<AtomicBool>>,
+    /// Told how far along each local copy is
+    pub observer: Option<Arc<dyn ProgressObserver>>,
+}
+
+/// Source files placed during a run, for callers that act on them afterwards
+pub type PlacedSources = Mutex<Vec<PathBuf>>;
+
+pub fn is_video_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| VIDEO_EXTENSIONS.contains(ext.to_lowercase().as_str()))
+}
+
+pub fn is_raw_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| RAW_EXTENSIONS.contains(ext.to_lowercase().as_str()))
+}
+
+/// Date a file with the extractor chain and place it under `dest_base`
+pub fn process_file(
+    extractors: &ExtractorChain,
+    source_path: &Path,
+    dest_base: &Path,
+    options: &PlaceOptions,
     stats: &Arc<Stats>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Extract datetime using ExifTool
-    let datetime = exiftool::extract_datetime(exiftool_path, source_path)?;
-    let month_name = datetime.format("%B").to_string();
+) -> Result<()> {
+    let date =
+        extractors
+            .extract(source_path)
+            .map_err(|error| TimekeeperError::ExtractionFailed {
+                path: source_path.to_path_buf(),
+                error,
+            })?;
+    let planned = plan_placement(
+        source_path,
+        &date.datetime,
+        date.date_source,
+        dest_base,
+        options,
+        stats,
+        &HashSet::new(),
+    )?;
+    match planned {
+        Some(placement) => execute_placement(
+            &Placement {
+                write_back: date.write_back,
+                ..placement
+            },
+            options,
+            stats,
+        ),
+        None => Ok(()),
+    }
+}
 
-    // Determine destination directory
-    let dest_dir = dest_base
-        .join(datetime.year().to_string())
-        .join(&month_name);
+/// A file's destination, decided before anything is written
+pub struct Placement {
+    pub source: PathBuf,
+    pub dest: PathBuf,
+    pub datetime: DateTime<Local>,
+    pub date_source: DateSource,
+    /// Let the `exif_writer` write `datetime` into the placed file, set by the caller
+    /// for dates found outside the file (see `DateExtractor::write_back`)
+    pub write_back: bool,
+}
 
-    // Check if the file is already in the correct directory
-    if let Some(current_dir) = source_path.parent() {
-        if current_dir == dest_dir {
-            stats.skipped.fetch_add(1, Ordering::SeqCst);
-            println!(
-                "[SKIP] Already in correct folder: {}",
-                source_path.display()
-            );
-            return Ok(());
+/// Decide where a file dated `datetime` goes under `dest_base`. Paths in `reserved`
+/// are treated as taken, so files planned but not yet written never get the same
+/// name. Returns `None` when the file is already in place.
+pub fn plan_placement(
+    source_path: &Path,
+    datetime: &DateTime<Local>,
+    date_source: DateSource,
+    dest_base: &Path,
+    options: &PlaceOptions,
+    stats: &Arc<Stats>,
+    reserved: &HashSet<PathBuf>,
+) -> Result<Option<Placement>> {
+    // Determine destination directory and file name
+    let mut meta = FileMeta::new(source_path, &options.source_root, *datetime);
+    let (dest_dir, home_video_name) = if options.rename_only {
+        let dir = source_path
+            .parent()
+            .ok_or_else(|| TimekeeperError::InvalidPath(source_path.to_path_buf()))?;
+        (dir.to_path_buf(), None)
+    } else if options.home_videos && is_video_file(source_path) {
+        let title = home_video_title(&meta);
+        let (dir, name) = layout::home_video_destination(dest_base, source_path, datetime, &title);
+        (dir, Some(name))
+    } else {
+        let layout_dir = options.layout.dest_for(&meta);
+        let selected = options
+            .selects_rating
+            .is_some_and(|min| meta.rating().is_some_and(|rating| rating >= min));
+        let mut dest_dir = if selected {
+            dest_base.join(SELECTS_DIR).join(&layout_dir)
+        } else {
+            dest_base.join(&layout_dir)
+        };
+        if options.preserve_structure
+            && let Some(relative) = meta.relative_dir()
+        {
+            // A file organized before is already below its date folders
+            dest_dir.push(relative.strip_prefix(&layout_dir).unwrap_or(relative));
+        } else if options.keep_album
+            // Generic folders (DCIM, 100CANON, dates) are no album worth keeping
+            && let Some(album) = meta.folder_title()
+        {
+            dest_dir.push(album);
         }
+        (dest_dir, None)
+    };
+
+    // Check if the file is already in the correct directory
+    if !options.rename_only && options.remote.is_none() && already_in_place(source_path, &dest_dir)
+    {
+        stats.skipped.fetch_add(1, Ordering::SeqCst);
+        return Ok(None);
     }
 
-    let filename = source_path.file_name().ok_or("Invalid filename")?;
+    let filename = match home_video_name {
+        Some(name) => name,
+        None if options.naming.uses_sequence() => {
+            sequenced_file_name(&mut meta, &dest_dir, options, reserved)?
+        }
+        None => options
+            .naming
+            .file_name(&meta)
+            .ok_or_else(|| TimekeeperError::InvalidPath(source_path.to_path_buf()))?,
+    };
 
     let dest_path = dest_dir.join(filename);
-    let unique_dest_path = get_unique_file_path(&dest_path);
-
-    let prefix = if dry_run { "[DRY RUN] " } else { "" };
-    let action = if use_copy { "Copying" } else { "Moving" };
-    println!(
-        "{}{}: {} -> {}",
-        prefix,
-        action,
-        source_path.display(),
-        unique_dest_path.display()
-    );
-
-    if !dry_run {
-        // Create destination directory
-        fs::create_dir_all(&dest_dir)?;
-
-        if use_copy {
-            fs::copy(source_path, &unique_dest_path)?;
-        } else {
-            // Move the file with cross-platform handling
-            move_file_cross_platform(source_path, &unique_dest_path)?;
+    let unique_dest_path = unique_destination(&dest_path, source_path, options, reserved)?;
+    // Another file, of the run or already at the destination, has the name
+    if unique_dest_path != dest_path {
+        stats.name_conflicts.fetch_add(1, Ordering::SeqCst);
+    }
+    // Renamed by an earlier run
+    if options.rename_only && unique_dest_path == source_path {
+        stats.skipped.fetch_add(1, Ordering::SeqCst);
+        return Ok(None);
+    }
+
+    Ok(Some(Placement {
+        source: source_path.to_path_buf(),
+        dest: unique_dest_path,
+        datetime: *datetime,
+        date_source,
+        write_back: false,
+    }))
+}
+
+/// Write a planned file (and its sidecars) to its destination. Dry runs stop at the plan.
+pub fn execute_placement(
+    placement: &Placement,
+    options: &PlaceOptions,
+    stats: &Arc<Stats>,
+) -> Result<()> {
+    if options.dry_run {
+        return Ok(());
+    }
+    let Placement {
+        source: source_path,
+        dest: dest_path,
+        datetime,
+        ..
+    } = placement;
+
+    if let Some(dest_dir) = dest_path.parent() {
+        create_destination_dir(dest_dir, options)?;
+    }
+    // Cut out before a move takes the photo away
+    let clip = if options.motion_clips {
+        motion::embedded_clip(source_path)?
+    } else {
+        None
+    };
+    transfer(source_path, dest_path, options, stats)?;
+
+    let mut placed = vec![dest_path.clone()];
+    if options.sidecars {
+        placed.extend(transfer_sidecars(source_path, dest_path, options, stats)?);
+    }
+    if let Some(clip) = clip {
+        placed.push(write_motion_clip(&clip, source_path, dest_path, options)?);
+    }
+
+    // Before the manifest hashes the files. They are in place already, so a failed
+    // edit doesn't fail the file.
+    if let Some(writer) = &options.exif_writer {
+        for file in &placed {
+            // Dates only go into the media file, its sidecars keep their own
+            let date = (file == dest_path && placement.write_back).then_some(datetime);
+            if let Err(e) = writer.edit(file, date) {
+                let message = format!("Could not edit the metadata of {}: {}", file.display(), e);
+                eprintln!("[WARN] {}", message);
+                stats.record_error(ErrorCategory::Metadata, message);
+            }
         }
     }
 
+    if options.sync_mtime
+        && options.remote.is_none()
+        && let Err(e) = set_modified(dest_path, datetime)
+    {
+        let message = format!(
+            "Could not set the modification time of {}: {}",
+            dest_path.display(),
+            e
+        );
+        eprintln!("[WARN] {}", message);
+        stats.record_error(ErrorCategory::of_io(&e), message);
+    }
+
+    if let Some(manifest) = &options.manifest {
+        for file in &placed {
+            // The file is already in place, a manifest failure must not trigger a retry
+            if let Err(e) = manifest.record(file, datetime) {
+                let message = format!("Could not add {} to manifest: {}", file.display(), e);
+                eprintln!("[WARN] {}", message);
+                stats.record_error(ErrorCategory::of_io(&e), message);
+            }
+        }
+    }
+    if let Some(placed) = &options.placed {
+        placed.lock().unwrap().push(source_path.clone());
+    }
+    // Not indexed, the file is copied again on the next run
+    if let Some(mirror) = &options.mirror
+        && let Err(e) = mirror.record(source_path, dest_path)
+    {
+        let message = format!(
+            "Could not add {} to the mirror index: {}",
+            source_path.display(),
+            e
+        );
+        eprintln!("[WARN] {}", message);
+        stats.record_error(ErrorCategory::of_io(&e), message);
+    }
+
     Ok(())
 }
 
-pub fn process_file_with_fallback(
-    source_path: &PathBuf,
-    dest_base: &PathBuf,
-    dry_run: bool,
-    use_copy: bool,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Use file modification time as fallback
-    let metadata = fs::metadata(source_path)?;
-    let mod_time = metadata.modified()?;
-    let datetime: DateTime<Local> = mod_time.into();
-    let month_name = datetime.format("%B").to_string();
+/// Give a file the capture date as its modification time, the order gallery apps
+/// and file browsers sort by
+fn set_modified(path: &Path, datetime: &DateTime<Local>) -> std::io::Result<()> {
+    fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_modified((*datetime).into())
+}
 
-    // Create destination directory structure
-    let dest_dir = dest_base
-        .join(datetime.year().to_string())
-        .join(format!("{}", month_name));
+/// Title for a home video: its folder name when that was named by a person,
+/// otherwise the file's own name
+fn home_video_title(meta: &FileMeta) -> String {
+    meta.folder_title().unwrap_or_else(|| {
+        meta.source
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    })
+}
 
-    let filename = source_path.file_name().ok_or("Invalid filename")?;
+/// Move XMP sidecars next to their media file, following its new name.
+/// Both naming conventions are handled: `IMG_1234.jpg.xmp` and `IMG_1234.xmp`.
+fn transfer_sidecars(
+    source_path: &Path,
+    dest_path: &Path,
+    options: &PlaceOptions,
+    stats: &Arc<Stats>,
+) -> Result<Vec<PathBuf>> {
+    let mut placed = Vec::new();
+    let (Some(source_name), Some(source_stem), Some(dest_name), Some(dest_stem)) = (
+        source_path.file_name(),
+        source_path.file_stem(),
+        dest_path.file_name(),
+        dest_path.file_stem(),
+    ) else {
+        return Ok(placed);
+    };
 
-    let dest_path = dest_dir.join(filename);
-    let unique_dest_path = get_unique_file_path(&dest_path);
-
-    let prefix = if dry_run { "[DRY RUN] " } else { "" };
-    let action = if use_copy { "Copying" } else { "Moving" };
-    println!(
-        "{}[FALLBACK] {}: {} -> {}",
-        prefix,
-        action,
-        source_path.display(),
-        unique_dest_path.display()
-    );
-
-    if !dry_run {
-        // Create destination directory
-        fs::create_dir_all(&dest_dir)?;
-
-        if use_copy {
-            fs::copy(source_path, &unique_dest_path)?;
-        } else {
-            // Move the file with cross-platform handling
-            move_file_cross_platform(source_path, &unique_dest_path)?;
+    for (source_base, dest_base) in [(source_name, dest_name), (source_stem, dest_stem)] {
+        for ext in ["xmp", "XMP"] {
+            let mut sidecar_name = source_base.to_os_string();
+            sidecar_name.push(format!(".{}", ext));
+            let sidecar = source_path.with_file_name(sidecar_name);
+            if !sidecar.is_file() {
+                continue;
+            }
+
+            let mut dest_sidecar_name = dest_base.to_os_string();
+            dest_sidecar_name.push(format!(".{}", ext));
+            let dest_sidecar = unique_destination(
+                &dest_path.with_file_name(dest_sidecar_name),
+                &sidecar,
+                options,
+                &HashSet::new(),
+            )?;
+            transfer(&sidecar, &dest_sidecar, options, stats)?;
+            placed.push(dest_sidecar);
+        }
+    }
+
+    Ok(placed)
+}
+
+/// Save a motion photo's clip as `<photo>_motion.mp4` beside the placed photo
+fn write_motion_clip(
+    clip: &[u8],
+    source_path: &Path,
+    dest_path: &Path,
+    options: &PlaceOptions,
+) -> Result<PathBuf> {
+    let mut name = dest_path.file_stem().unwrap_or_default().to_os_string();
+    name.push(motion::CLIP_SUFFIX);
+    let dest_clip = unique_destination(
+        &dest_path.with_file_name(name),
+        source_path,
+        options,
+        &HashSet::new(),
+    )?;
+    match &options.remote {
+        None => fs::write(&dest_clip, clip)?,
+        Some(remote) => {
+            // Uploads go from a file, staged in the temp directory for the moment
+            let staged = std::env::temp_dir().join(format!(
+                "timekeeper-{}-{}",
+                std::process::id(),
+                dest_clip.file_name().unwrap_or_default().to_string_lossy()
+            ));
+            fs::write(&staged, clip)?;
+            let uploaded = remote.upload(&staged, &remote.key_for(&dest_clip)?);
+            let _ = fs::remove_file(&staged);
+            uploaded?;
+        }
+    }
+    Ok(dest_clip)
+}
+
+fn create_destination_dir(dir: &Path, options: &PlaceOptions) -> std::io::Result<()> {
+    match &options.remote {
+        Some(remote) => remote.create_dir_all(&remote.key_for(dir)?),
+        None => fs::create_dir_all(dir),
+    }
+}
+
+/// First free destination path for a file that isn't in `reserved`,
+/// checked locally or against the remote store. The file's own path counts as
+/// free, for files renamed where they are.
+fn unique_destination(
+    dest_path: &Path,
+    source_path: &Path,
+    options: &PlaceOptions,
+    reserved: &HashSet<PathBuf>,
+) -> std::io::Result<PathBuf> {
+    get_unique_file_path(dest_path, |path| {
+        if path == source_path {
+            return Ok(false);
+        }
+        is_taken(path, options, reserved)
+    })
+}
+
+/// Whether a destination path is already used by a file or by a planned one
+fn is_taken(
+    path: &Path,
+    options: &PlaceOptions,
+    reserved: &HashSet<PathBuf>,
+) -> std::io::Result<bool> {
+    if reserved.contains(path) {
+        return Ok(true);
+    }
+    match &options.remote {
+        Some(remote) => Ok(remote.stat(&remote.key_for(path)?)?.is_some()),
+        None => Ok(path.exists()),
+    }
+}
+
+/// Name a file with the next free `{seq}` number of its sequence. Numbers taken by
+/// files from earlier runs are skipped, so a folder's numbering carries on.
+fn sequenced_file_name(
+    meta: &mut FileMeta,
+    dest_dir: &Path,
+    options: &PlaceOptions,
+    reserved: &HashSet<PathBuf>,
+) -> Result<OsString> {
+    let mut counters = options.sequences.counters.lock().unwrap();
+    let counter = counters
+        .entry(options.sequences.scope.key(dest_dir, &meta.datetime))
+        .or_insert(0);
+    loop {
+        *counter += 1;
+        meta.seq = Some(*counter);
+        let name = options
+            .naming
+            .file_name(meta)
+            .ok_or_else(|| TimekeeperError::InvalidPath(meta.source.to_path_buf()))?;
+        if !is_taken(&dest_dir.join(&name), options, reserved)? {
+            return Ok(name);
         }
     }
+}
+
+/// Place a file at `dest`, uploading it when the destination is a remote store
+fn transfer(source: &Path, dest: &Path, options: &PlaceOptions, stats: &Arc<Stats>) -> Result<()> {
+    let move_failed = |error| TimekeeperError::MoveFailed {
+        source: source.to_path_buf(),
+        dest: dest.to_path_buf(),
+        error,
+    };
+    let Some(remote) = &options.remote else {
+        transfer_file(source, dest, options, stats).map_err(move_failed)?;
+        if options.verify && options.use_copy {
+            verify_copy(source, dest, options.copy_report.as_deref())?;
+        }
+        return Ok(());
+    };
 
+    let key = remote.key_for(dest)?;
+    let bytes = fs::metadata(source)?.len();
+    remote.upload(source, &key).map_err(move_failed)?;
+    if !options.use_copy {
+        // Delete the original only once the stored copy is confirmed complete
+        if remote.stat(&key)? != Some(bytes) {
+            return Err(TimekeeperError::VerifyFailed {
+                original: source.to_path_buf(),
+                copy: dest.to_path_buf(),
+            });
+        }
+        removal::remove_file(source, options.use_trash)?;
+    }
+    stats.bytes_transferred.fetch_add(bytes, Ordering::SeqCst);
+
+    Ok(())
+}
+
+/// Copy or move a file into place and account for the transferred bytes as they
+/// are written, so progress moves along during a large copy
+fn transfer_file(
+    source: &Path,
+    dest: &Path,
+    options: &PlaceOptions,
+    stats: &Arc<Stats>,
+) -> std::io::Result<()> {
+    let total = fs::metadata(source)?.len();
+    let copied = Cell::new(0);
+    let on_chunk = |bytes: u64| {
+        copied.set(copied.get() + bytes);
+        stats.bytes_transferred.fetch_add(bytes, Ordering::SeqCst);
+        if let Some(observer) = &options.observer {
+            observer.on_copy_progress(source, copied.get(), total);
+        }
+    };
+    let watch = CopyWatch {
+        terminate_flag: options.terminate_flag.as_deref(),
+        on_chunk: &on_chunk,
+    };
+
+    let result = if options.use_copy {
+        transfer::copy_file(source, dest, &watch).map(|_| ())
+    } else {
+        // Move the file with cross-platform handling
+        move_file_cross_platform(source, dest, options.use_trash, &watch)
+    };
+    match result {
+        // A rename moves everything at once
+        Ok(()) => stats
+            .bytes_transferred
+            .fetch_add(total.saturating_sub(copied.get()), Ordering::SeqCst),
+        // The partial copy is gone again
+        Err(_) => stats
+            .bytes_transferred
+            .fetch_sub(copied.get(), Ordering::SeqCst),
+    };
+    result
+}
+
+/// Compare a fresh copy against its source by hash. A copy that doesn't match is
+/// removed again so a later run retries it instead of trusting a corrupt file.
+fn verify_copy(source: &Path, dest: &Path, report: Option<&CopyReport>) -> Result<()> {
+    let algorithm = report.map_or(HashAlgorithm::Blake3, CopyReport::algorithm);
+    let hash = algorithm.hash_file(source)?;
+    let verified = hash == algorithm.hash_file(dest)?;
+    if let Some(report) = report {
+        report.record(source, dest, hash, fs::metadata(source)?.len(), verified);
+    }
+    if !verified {
+        fs::remove_file(dest)?;
+        return Err(TimekeeperError::VerifyFailed {
+            original: source.to_path_buf(),
+            copy: dest.to_path_buf(),
+        });
+    }
     Ok(())
 }
 
+/// Check whether the source already lives in `dest_dir`, either literally or through
+/// a symlink/bind mount that resolves to the same directory or the same file.
+fn already_in_place(source_path: &Path, dest_dir: &Path) -> bool {
+    if let Some(current_dir) = source_path.parent() {
+        if current_dir == dest_dir {
+            return true;
+        }
+        if let (Ok(current), Ok(dest)) = (current_dir.canonicalize(), dest_dir.canonicalize())
+            && current == dest
+        {
+            return true;
+        }
+    }
+
+    match source_path.file_name() {
+        Some(filename) => is_same_file(source_path, &dest_dir.join(filename)),
+        None => false,
+    }
+}
+
+/// Compare two paths by device and inode, so hard links and bind mounts are detected
+#[cfg(unix)]
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Compare two paths after resolving symlinks and junctions
+#[cfg(not(unix))]
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
 /// Cross-platform file moving that handles different filesystems
-fn move_file_cross_platform(
-    source: &PathBuf,
-    dest: &PathBuf,
-) -> Result<(), Box<dyn std::error::Error>> {
+pub(crate) fn move_file_cross_platform(
+    source: &Path,
+    dest: &Path,
+    use_trash: bool,
+    watch: &CopyWatch,
+) -> std::io::Result<()> {
     // First try a simple rename (fastest, works within same filesystem)
     match fs::rename(source, dest) {
         Ok(()) => Ok(()),
-        Err(e) => {
-            // Check if it's a cross-device/filesystem error
-            #[cfg(unix)]
-            {
-                if e.raw_os_error() == Some(18) {
-                    // EXDEV - Cross-device link
-                    return copy_and_delete(source, dest);
-                }
-            }
-
-            #[cfg(windows)]
+        // EXDEV on Unix, ERROR_NOT_SAME_DEVICE on Windows
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            // Two mounts of one SMB share look like different devices, but the
+            // server can still move the file without sending it over the network
+            if let Some((from, to)) = smb::same_share_rename(source, dest)
+                && fs::rename(&from, &to).is_ok()
             {
-                // On Windows, ERROR_NOT_SAME_DEVICE = 17
-                if e.raw_os_error() == Some(17) {
-                    return copy_and_delete(source, dest);
-                }
+                return Ok(());
             }
-
+            copy_and_delete(source, dest, use_trash, watch)
+        }
+        Err(e) => {
             // For other platforms or error codes, try copy+delete as fallback
             match e.kind() {
                 std::io::ErrorKind::InvalidInput | std::io::ErrorKind::PermissionDenied => {
                     // These might indicate cross-filesystem issues on some platforms
-                    copy_and_delete(source, dest)
+                    copy_and_delete(source, dest, use_trash, watch)
                 }
-                _ => Err(e.into()),
+                _ => Err(e),
             }
         }
     }
 }
 
 /// Copy file and delete original (fallback for cross-filesystem moves)
-fn copy_and_delete(source: &PathBuf, dest: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
-    // Copy the file
-    fs::copy(source, dest)?;
+fn copy_and_delete(
+    source: &Path,
+    dest: &Path,
+    use_trash: bool,
+    watch: &CopyWatch,
+) -> std::io::Result<()> {
+    // Copy the file, a partial copy is removed again
+    transfer::copy_file(source, dest, watch)?;
 
     // Verify the copy was successful by checking file sizes
     let source_metadata = fs::metadata(source)?;
@@ -208,18 +771,23 @@ fn copy_and_delete(source: &PathBuf, dest: &PathBuf) -> Result<(), Box<dyn std::
     if source_metadata.len() != dest_metadata.len() {
         // Cleanup the incomplete copy
         let _ = fs::remove_file(dest);
-        return Err("File copy verification failed: size mismatch".into());
+        return Err(std::io::Error::other(
+            "File copy verification failed: size mismatch",
+        ));
     }
 
     // Delete the original only after successful verification
-    fs::remove_file(source)?;
+    removal::remove_file(source, use_trash)?;
 
     Ok(())
 }
 
-fn get_unique_file_path(original_path: &PathBuf) -> PathBuf {
-    if !original_path.exists() {
-        return original_path.clone();
+pub(crate) fn get_unique_file_path(
+    original_path: &Path,
+    exists: impl Fn(&Path) -> std::io::Result<bool>,
+) -> std::io::Result<PathBuf> {
+    if !exists(original_path)? {
+        return Ok(original_path.to_path_buf());
     }
 
     let parent = original_path
@@ -243,8 +811,8 @@ fn get_unique_file_path(original_path: &PathBuf) -> PathBuf {
         };
 
         let new_path = parent.join(new_filename);
-        if !new_path.exists() {
-            return new_path;
+        if !exists(&new_path)? {
+            return Ok(new_path);
         }
         counter += 1;
     }
@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::manifest::{modified_secs, relative_key};
+
+/// Index kept at the root of a mirror, listing the source files it already holds
+pub const INDEX_FILE: &str = ".timekeeper-mirror";
+
+/// Header naming the columns of the index lines
+const COLUMNS_HEADER: &str = "# size\tmodified\tsource\tdestination";
+
+/// A source file copied into the mirror, as it was when copied
+#[derive(Debug, Clone)]
+struct MirrorEntry {
+    size: u64,
+    /// Seconds since the Unix epoch
+    modified: Option<i64>,
+    /// Path of the copy relative to the mirror root
+    dest: String,
+}
+
+/// Which files of a source an organized mirror already holds, so each `mirror` run
+/// only copies what is new or changed. Stored as tab-separated `size modified source
+/// destination` lines, both paths relative to their root.
+#[derive(Debug)]
+pub struct MirrorIndex {
+    path: PathBuf,
+    source: PathBuf,
+    destination: PathBuf,
+    entries: Mutex<BTreeMap<String, MirrorEntry>>,
+    dirty: AtomicBool,
+}
+
+impl MirrorIndex {
+    /// Open the index of the mirror of `source` at `destination`, empty for a new mirror
+    pub fn open(source: &Path, destination: &Path) -> io::Result<Self> {
+        let path = destination.join(INDEX_FILE);
+        let mut entries = BTreeMap::new();
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                for (number, line) in text.lines().enumerate() {
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+                    let (relative, entry) = parse_line(line).ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "Malformed mirror index line {} in '{}'",
+                                number + 1,
+                                path.display()
+                            ),
+                        )
+                    })?;
+                    entries.insert(relative, entry);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        Ok(MirrorIndex {
+            path,
+            source: source.to_path_buf(),
+            destination: destination.to_path_buf(),
+            entries: Mutex::new(entries),
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the mirror holds `file` as it is now. A file whose size or modification
+    /// time changed since it was copied is copied again.
+    pub fn contains(&self, file: &Path) -> bool {
+        let Some(relative) = relative_key(&self.source, file) else {
+            return false;
+        };
+        let Ok(metadata) = fs::metadata(file) else {
+            return false;
+        };
+        self.entries
+            .lock()
+            .unwrap()
+            .get(&relative)
+            .is_some_and(|entry| {
+                entry.size == metadata.len() && entry.modified == modified_secs(&metadata)
+            })
+    }
+
+    /// Note that `source` was copied to `dest`
+    pub fn record(&self, source: &Path, dest: &Path) -> io::Result<()> {
+        let outside = |file: &Path, root: &Path| {
+            io::Error::other(format!(
+                "'{}' is outside '{}'",
+                file.display(),
+                root.display()
+            ))
+        };
+        let relative =
+            relative_key(&self.source, source).ok_or_else(|| outside(source, &self.source))?;
+        let dest = relative_key(&self.destination, dest)
+            .ok_or_else(|| outside(dest, &self.destination))?;
+        let metadata = fs::metadata(source)?;
+        let entry = MirrorEntry {
+            size: metadata.len(),
+            modified: modified_secs(&metadata),
+            dest,
+        };
+        self.entries.lock().unwrap().insert(relative, entry);
+        self.dirty.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Source files the mirror holds that are gone from the source, with the path of
+    /// their copy. The copies are kept, deciding what to do with them is up to the user.
+    pub fn removed(&self) -> Vec<(String, PathBuf)> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(relative, _)| !self.source.join(relative).exists())
+            .map(|(relative, entry)| (relative.clone(), self.destination.join(&entry.dest)))
+            .collect()
+    }
+
+    /// Write the index if anything was copied since it was opened
+    pub fn save(&self) -> io::Result<()> {
+        if !self.dirty.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let entries = self.entries.lock().unwrap();
+        let mut out = format!(
+            "# timekeeper mirror of {}\n{}\n",
+            self.source.display(),
+            COLUMNS_HEADER
+        );
+        for (relative, entry) in entries.iter() {
+            out.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                entry.size,
+                entry
+                    .modified
+                    .map(|secs| secs.to_string())
+                    .unwrap_or_default(),
+                relative,
+                entry.dest
+            ));
+        }
+
+        fs::create_dir_all(&self.destination)?;
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, out)?;
+        fs::rename(&tmp_path, &self.path)
+    }
+}
+
+fn parse_line(line: &str) -> Option<(String, MirrorEntry)> {
+    let mut fields = line.splitn(4, '\t');
+    let size = fields.next()?.parse().ok()?;
+    let modified = match fields.next()? {
+        "" => None,
+        secs => Some(secs.parse().ok()?),
+    };
+    let source = fields.next()?.to_string();
+    let dest = fields.next()?.to_string();
+    Some((
+        source,
+        MirrorEntry {
+            size,
+            modified,
+            dest,
+        },
+    ))
+}
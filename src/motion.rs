@@ -0,0 +1,78 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Markers Google (`GCamera:MotionPhoto`, the older `GCamera:MicroVideo`) and Samsung
+/// (`MotionPhoto_Data`) leave in a motion photo
+const MOTION_MARKERS: [&[u8]; 2] = [b"MotionPhoto", b"MicroVideo"];
+
+/// Name added to a motion photo's stem for its extracted clip
+pub const CLIP_SUFFIX: &str = "_motion.mp4";
+
+/// Whether the file can be a motion photo at all, going by its extension
+fn is_jpeg(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("jpg") || ext.eq_ignore_ascii_case("jpeg"))
+}
+
+/// The MP4 clip a Samsung or Pixel motion photo carries after its JPEG still,
+/// `None` for any other file
+pub fn embedded_clip(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    if !is_jpeg(path) {
+        return Ok(None);
+    }
+    let mut bytes = fs::read(path)?;
+    Ok(clip_offset(&bytes).map(|offset| bytes.split_off(offset)))
+}
+
+/// Whether the file is a motion photo, a still with a video inside
+pub fn is_motion_photo(path: &Path) -> bool {
+    is_jpeg(path) && fs::read(path).is_ok_and(|bytes| clip_offset(&bytes).is_some())
+}
+
+/// Where the MP4 starts: the first `ftyp` box after the still, in a file marked as a
+/// motion photo
+fn clip_offset(bytes: &[u8]) -> Option<usize> {
+    let marked = MOTION_MARKERS
+        .iter()
+        .any(|marker| bytes.windows(marker.len()).any(|window| window == *marker));
+    if !marked {
+        return None;
+    }
+    // An MP4 opens with a box `<size:u32> ftyp <brand:4 chars>`
+    bytes
+        .windows(4)
+        .enumerate()
+        .skip(4)
+        .filter(|(_, window)| *window == b"ftyp")
+        .map(|(index, _)| index - 4)
+        .find(|&start| {
+            let size = u32::from_be_bytes(bytes[start..start + 4].try_into().unwrap());
+            let brand = bytes.get(start + 8..start + 12);
+            (16..=256).contains(&size)
+                && brand
+                    .is_some_and(|brand| brand.iter().all(|b| b.is_ascii_graphic() || *b == b' '))
+        })
+}
+
+/// The motion photo a video was exported from, for clips saved next to their
+/// still (`PXL_1234.MP.mp4` or `20230514_102345.mp4` beside the `.jpg`), so the two
+/// are dated alike
+pub fn clip_still(path: &Path) -> Option<PathBuf> {
+    if !crate::metadata::is_video_file(path) {
+        return None;
+    }
+    let stem = path.file_stem()?.to_str()?;
+    let stem = stem
+        .strip_suffix(CLIP_SUFFIX.trim_end_matches(".mp4"))
+        .unwrap_or(stem);
+    ["jpg", "JPG", "jpeg", "JPEG"]
+        .into_iter()
+        .flat_map(|ext| {
+            // Google exports PXL_1234.MP.mp4 for PXL_1234.MP.jpg, Samsung the same stem
+            [format!("{}.{}", stem, ext), format!("{}.MP.{}", stem, ext)]
+        })
+        .map(|name| path.with_file_name(name))
+        .find(|still| still.is_file() && is_motion_photo(still))
+}
@@ -0,0 +1,30 @@
+use crate::RunOutcome;
+use crate::stats::Stats;
+
+/// Show a native desktop notification summarizing the run.
+/// Notification failures (no notification daemon, headless session) are returned
+/// to the caller, which should treat them as non-fatal.
+pub fn notify_completion(
+    outcome: RunOutcome,
+    stats: &Stats,
+) -> Result<(), notify_rust::error::Error> {
+    let snapshot = stats.snapshot();
+
+    let summary = match outcome {
+        RunOutcome::Finished => "Timekeeper finished",
+        RunOutcome::Stopped => "Timekeeper stopped",
+        RunOutcome::Failed => "Timekeeper failed",
+    };
+    let body = format!(
+        "Processed {} of {} files\nSkipped: {}\nErrors: {}",
+        snapshot.processed, snapshot.total, snapshot.skipped, snapshot.errors
+    );
+
+    notify_rust::Notification::new()
+        .appname("timekeeper")
+        .summary(summary)
+        .body(&body)
+        .show()?;
+
+    Ok(())
+}
@@ -0,0 +1,196 @@
+use crossbeam_channel::Receiver;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::thread::JoinHandle;
+
+use crate::Organizer;
+use crate::color;
+use crate::error::{Result, TimekeeperError};
+use crate::messaging;
+use crate::stats::Stats;
+
+/// What a run did with a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Copied (or, in a dry run, would be copied) to its destination
+    Copy,
+    /// Moved (or, in a dry run, would be moved) to its destination
+    Move,
+    /// Already in the folder it belongs in
+    AlreadyInPlace,
+    /// Skipped because it is still being written
+    NotSettled,
+    /// Skipped because only a placeholder of it is here, its contents are in the cloud
+    OnlineOnly,
+    /// Could not be dated or placed, see `error`
+    Failed,
+}
+
+/// Where a file's date came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateSource {
+    /// The file's own metadata (or its imported exiftool dump)
+    Metadata,
+    /// A messaging app file name or the modification time
+    Fallback,
+}
+
+/// The result for one file of a run
+#[derive(Debug)]
+pub struct FileOutcome {
+    pub source: PathBuf,
+    /// Where the file went; `None` when it failed before a destination was picked
+    pub destination: Option<PathBuf>,
+    pub action: Action,
+    /// `None` when the file was skipped or failed before it was dated
+    pub date_source: Option<DateSource>,
+    pub error: Option<TimekeeperError>,
+}
+
+impl FileOutcome {
+    pub(crate) fn failed(
+        source: PathBuf,
+        destination: Option<PathBuf>,
+        date_source: Option<DateSource>,
+        error: TimekeeperError,
+    ) -> Self {
+        FileOutcome {
+            source,
+            destination,
+            action: Action::Failed,
+            date_source,
+            error: Some(error),
+        }
+    }
+
+    /// Print the outcome the way the console always showed it: one line per file,
+    /// or the tab-separated `--itemize` line
+    pub fn print(&self, dry_run: bool, itemize: bool) {
+        let fallback = self.date_source == Some(DateSource::Fallback);
+        if itemize
+            && matches!(
+                self.action,
+                Action::AlreadyInPlace | Action::Copy | Action::Move
+            )
+        {
+            let code = match self.action {
+                Action::Copy => 'c',
+                Action::Move => 'm',
+                _ => '=',
+            };
+            println!(
+                "{}\t{}\t{}\t{}",
+                code,
+                if fallback { "mtime" } else { "exif" },
+                self.source.display(),
+                self.destination.as_ref().unwrap_or(&self.source).display()
+            );
+            return;
+        }
+
+        let message = self.message(dry_run);
+        match self.action {
+            Action::Failed => eprintln!("{}", color::red(&message)),
+            Action::NotSettled | Action::OnlineOnly | Action::AlreadyInPlace => {
+                println!("{}", color::cyan(&message))
+            }
+            // Guessed dates stand out from the ones read from metadata
+            Action::Copy | Action::Move if fallback => println!("{}", color::yellow(&message)),
+            Action::Copy | Action::Move => println!("{}", color::green(&message)),
+        }
+    }
+
+    /// The console line for the outcome, without colors
+    pub fn message(&self, dry_run: bool) -> String {
+        match self.action {
+            Action::Failed => format!(
+                "Error processing {}: {}",
+                self.source.display(),
+                self.error
+                    .as_ref()
+                    .map(ToString::to_string)
+                    .unwrap_or_default()
+            ),
+            Action::NotSettled => {
+                format!("[SKIP] Still being written: {}", self.source.display())
+            }
+            Action::OnlineOnly => {
+                format!(
+                    "[SKIP] Online-only, not downloaded from the cloud: {}",
+                    self.source.display()
+                )
+            }
+            Action::AlreadyInPlace => {
+                format!(
+                    "[SKIP] Already in correct folder: {}",
+                    self.source.display()
+                )
+            }
+            Action::Copy | Action::Move => format!(
+                "{}{}{}{}: {} -> {}",
+                if dry_run { "[DRY RUN] " } else { "" },
+                if self.date_source == Some(DateSource::Fallback) {
+                    "[FALLBACK] "
+                } else {
+                    ""
+                },
+                received_tag(&self.source),
+                if self.action == Action::Copy {
+                    "Copying"
+                } else {
+                    "Moving"
+                },
+                self.source.display(),
+                self.destination.as_ref().unwrap_or(&self.source).display()
+            ),
+        }
+    }
+}
+
+/// Output tag for media received through a messaging app, empty otherwise
+fn received_tag(source_path: &std::path::Path) -> String {
+    match messaging::detect_messaging_app(source_path) {
+        Some(app) => format!("[RECEIVED:{}] ", app),
+        None => String::new(),
+    }
+}
+
+/// Outcomes of a run started with `Organizer::outcomes`, yielded as files finish
+pub struct Outcomes {
+    receiver: Receiver<FileOutcome>,
+    run: JoinHandle<Result<()>>,
+}
+
+impl Iterator for Outcomes {
+    type Item = FileOutcome;
+
+    fn next(&mut self) -> Option<FileOutcome> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl Outcomes {
+    /// Wait for the run to end and return its overall result. Outcomes not read yet
+    /// are dropped.
+    pub fn finish(self) -> Result<()> {
+        drop(self.receiver);
+        self.run
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+}
+
+impl Organizer {
+    /// Run in the background and yield a `FileOutcome` per file instead of printing.
+    /// Call `Outcomes::finish` for the run's own result (e.g. a missing exiftool).
+    pub fn outcomes(
+        self: Arc<Self>,
+        stats: Arc<Stats>,
+        terminate_flag: Arc<AtomicBool>,
+    ) -> Outcomes {
+        let (sender, receiver) = crossbeam_channel::bounded(self.queue_size);
+        let run = std::thread::spawn(move || self.run_with_outcomes(stats, terminate_flag, sender));
+        Outcomes { receiver, run }
+    }
+}
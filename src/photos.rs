@@ -0,0 +1,61 @@
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::metadata::is_media_file;
+
+lazy_static::lazy_static! {
+    // Edited copies Photos and iCloud exports next to the original: IMG_E1234.JPG for IMG_1234.HEIC
+    static ref EDITED_NAME: Regex = Regex::new(r"(?i)^(IMG_)E(\d{4,})$").unwrap();
+    // Photos' "Moment Name" export folders: "Paris, Île-de-France, June 3, 2021" or just the date
+    static ref MOMENT_FOLDER: Regex = Regex::new(
+        r"^(?:(.+), )?(?:January|February|March|April|May|June|July|August|September|October|November|December) \d{1,2}, \d{4}$"
+    )
+    .unwrap();
+}
+
+/// The folder holding the original files of a macOS Photos library: `originals`
+/// since Photos 5 (macOS 10.15), `Masters` before. `None` for anything but a
+/// `.photoslibrary` bundle.
+pub fn library_originals(path: &Path) -> Option<PathBuf> {
+    let is_library = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("photoslibrary"));
+    if !is_library || !path.is_dir() {
+        return None;
+    }
+    ["originals", "Masters"]
+        .into_iter()
+        .map(|folder| path.join(folder))
+        .find(|folder| folder.is_dir())
+}
+
+/// The original an edited copy in Apple's naming (`IMG_E1234.JPG`) was made from,
+/// when it sits in the same folder under any media extension (`IMG_1234.HEIC`)
+pub fn edited_original(path: &Path) -> Option<PathBuf> {
+    let stem = path.file_stem()?.to_str()?;
+    let captures = EDITED_NAME.captures(stem)?;
+    let original_stem = format!("{}{}", &captures[1], &captures[2]);
+
+    fs::read_dir(path.parent()?)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|candidate| {
+            candidate != path
+                && candidate
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .is_some_and(|stem| stem.eq_ignore_ascii_case(&original_stem))
+                && candidate.to_str().is_some_and(is_media_file)
+        })
+}
+
+/// The place in a folder named after a Photos moment, `""` when the name is only
+/// the moment's date. Other folder names are returned as they are.
+pub fn moment_place(folder_name: &str) -> &str {
+    match MOMENT_FOLDER.captures(folder_name) {
+        Some(captures) => captures.get(1).map_or("", |place| place.as_str()),
+        None => folder_name,
+    }
+}
@@ -0,0 +1,284 @@
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::{cursor, execute, queue, terminal};
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::space::format_bytes;
+
+/// A file a run would place, offered for picking
+#[derive(Debug, Clone)]
+pub struct PickItem {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub bytes: u64,
+}
+
+/// Let the user check and uncheck planned files, or whole destination folders, in a
+/// full-screen list narrowed down by typing (fzf-style: the letters of each word
+/// in order). Returns the sources of the checked files, or `None` when cancelled.
+pub fn pick(items: Vec<PickItem>, destination: &Path) -> io::Result<Option<HashSet<PathBuf>>> {
+    let mut picker = Picker::new(items, destination);
+    let _screen = Screen::enter()?;
+    loop {
+        picker.draw()?;
+        if let Event::Key(key) = event::read()?
+            && key.kind != KeyEventKind::Release
+            && let Some(done) = picker.handle(key)
+        {
+            return Ok(done.then(|| picker.checked()));
+        }
+    }
+}
+
+/// Raw mode on the alternate screen, restored however the picker ends
+struct Screen;
+
+impl Screen {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(Screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// A line of the list: a destination folder with the shown files in it, or a file
+enum Row {
+    Folder(PathBuf, Vec<usize>),
+    File(usize),
+}
+
+struct Picker {
+    items: Vec<PickItem>,
+    checked: Vec<bool>,
+    /// Items' destinations relative to the run's destination, what the list shows
+    labels: Vec<String>,
+    filter: String,
+    rows: Vec<Row>,
+    cursor: usize,
+    scroll: usize,
+}
+
+impl Picker {
+    fn new(mut items: Vec<PickItem>, destination: &Path) -> Self {
+        items.sort_by(|a, b| a.destination.cmp(&b.destination));
+        let labels = items
+            .iter()
+            .map(|item| {
+                item.destination
+                    .strip_prefix(destination)
+                    .unwrap_or(&item.destination)
+                    .display()
+                    .to_string()
+            })
+            .collect();
+        let mut picker = Picker {
+            checked: vec![true; items.len()],
+            items,
+            labels,
+            filter: String::new(),
+            rows: Vec::new(),
+            cursor: 0,
+            scroll: 0,
+        };
+        picker.refresh();
+        picker
+    }
+
+    /// Rebuild the rows for the current filter
+    fn refresh(&mut self) {
+        let terms: Vec<String> = self
+            .filter
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .collect();
+        self.rows.clear();
+        // Items are sorted by destination, so a folder's files come in one run
+        let mut folder_row = None;
+        for (index, item) in self.items.iter().enumerate() {
+            let haystack =
+                format!("{} {}", self.labels[index], item.source.display()).to_lowercase();
+            if !terms.iter().all(|term| fuzzy_match(&haystack, term)) {
+                continue;
+            }
+            let folder = item.destination.parent().unwrap_or(Path::new(""));
+            match folder_row.and_then(|row| self.rows.get_mut(row)) {
+                Some(Row::Folder(last, files)) if last == folder => files.push(index),
+                _ => {
+                    folder_row = Some(self.rows.len());
+                    self.rows
+                        .push(Row::Folder(folder.to_path_buf(), vec![index]));
+                }
+            }
+            self.rows.push(Row::File(index));
+        }
+        self.cursor = self.cursor.min(self.rows.len().saturating_sub(1));
+    }
+
+    /// Apply a key press. `Some(true)` confirms the picked files, `Some(false)` cancels.
+    fn handle(&mut self, key: KeyEvent) -> Option<bool> {
+        let page = terminal::size().map_or(20, |(_, rows)| rows.saturating_sub(3).max(1) as usize);
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Enter => return Some(true),
+            KeyCode::Esc => return Some(false),
+            KeyCode::Char('c') if control => return Some(false),
+            KeyCode::Char('a') if control => {
+                let shown: Vec<usize> = self.shown_files().collect();
+                self.toggle(&shown);
+            }
+            KeyCode::Up => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Down => self.cursor = (self.cursor + 1).min(self.last_row()),
+            KeyCode::PageUp => self.cursor = self.cursor.saturating_sub(page),
+            KeyCode::PageDown => self.cursor = (self.cursor + page).min(self.last_row()),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.last_row(),
+            KeyCode::Char(' ') | KeyCode::Tab => {
+                let files = match self.rows.get(self.cursor) {
+                    Some(Row::Folder(_, files)) => files.clone(),
+                    Some(Row::File(index)) => vec![*index],
+                    None => Vec::new(),
+                };
+                self.toggle(&files);
+            }
+            KeyCode::Backspace => {
+                self.filter.pop();
+                self.refresh();
+            }
+            KeyCode::Char(c) if !control => {
+                self.filter.push(c);
+                self.cursor = 0;
+                self.refresh();
+            }
+            _ => {}
+        }
+        None
+    }
+
+    fn last_row(&self) -> usize {
+        self.rows.len().saturating_sub(1)
+    }
+
+    fn shown_files(&self) -> impl Iterator<Item = usize> + '_ {
+        self.rows.iter().filter_map(|row| match row {
+            Row::File(index) => Some(*index),
+            Row::Folder(..) => None,
+        })
+    }
+
+    /// Uncheck the files if all of them are checked, check them all otherwise
+    fn toggle(&mut self, files: &[usize]) {
+        let check = !files.iter().all(|&index| self.checked[index]);
+        for &index in files {
+            self.checked[index] = check;
+        }
+    }
+
+    fn checked(&self) -> HashSet<PathBuf> {
+        self.items
+            .iter()
+            .zip(&self.checked)
+            .filter(|(_, checked)| **checked)
+            .map(|(item, _)| item.source.clone())
+            .collect()
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, list_height) = (width as usize, height.saturating_sub(3).max(1) as usize);
+        if self.cursor < self.scroll {
+            self.scroll = self.cursor;
+        } else if self.cursor >= self.scroll + list_height {
+            self.scroll = self.cursor + 1 - list_height;
+        }
+
+        let (count, bytes) = self
+            .items
+            .iter()
+            .zip(&self.checked)
+            .filter(|(_, checked)| **checked)
+            .fold((0, 0), |(count, bytes), (item, _)| {
+                (count + 1, bytes + item.bytes)
+            });
+        let mut out = io::stdout().lock();
+        queue!(
+            out,
+            terminal::Clear(terminal::ClearType::All),
+            cursor::MoveTo(0, 0)
+        )?;
+        let header = format!(
+            "{} of {} files checked ({}). Space toggles, Ctrl-A all shown, Enter organizes, Esc cancels",
+            count,
+            self.items.len(),
+            format_bytes(bytes)
+        );
+        queue!(out, Print(fit(&header, width)), cursor::MoveTo(0, 1))?;
+        queue!(out, Print(fit(&format!("> {}", self.filter), width)))?;
+
+        for (line, row) in self
+            .rows
+            .iter()
+            .enumerate()
+            .skip(self.scroll)
+            .take(list_height)
+        {
+            let text = match row {
+                Row::Folder(folder, files) => {
+                    let checked = files.iter().filter(|&&index| self.checked[index]).count();
+                    let mark = match checked {
+                        0 => ' ',
+                        n if n == files.len() => 'x',
+                        _ => '-',
+                    };
+                    let label = Path::new(&self.labels[files[0]])
+                        .parent()
+                        .map(|parent| parent.display().to_string())
+                        .filter(|parent| !parent.is_empty())
+                        .unwrap_or_else(|| folder.display().to_string());
+                    format!("[{}] {}/ ({} files)", mark, label, files.len())
+                }
+                Row::File(index) => {
+                    let item = &self.items[*index];
+                    format!(
+                        "    [{}] {}  <- {}",
+                        if self.checked[*index] { 'x' } else { ' ' },
+                        item.destination
+                            .file_name()
+                            .unwrap_or_default()
+                            .to_string_lossy(),
+                        item.source.display()
+                    )
+                }
+            };
+            queue!(out, cursor::MoveTo(0, (line - self.scroll + 2) as u16))?;
+            if line == self.cursor {
+                queue!(out, SetAttribute(Attribute::Reverse))?;
+            }
+            queue!(
+                out,
+                Print(fit(&text, width)),
+                SetAttribute(Attribute::Reset)
+            )?;
+        }
+        out.flush()
+    }
+}
+
+/// Whether the letters of `term` appear in `haystack` in order
+fn fuzzy_match(haystack: &str, term: &str) -> bool {
+    let mut letters = haystack.chars();
+    term.chars().all(|wanted| letters.any(|c| c == wanted))
+}
+
+/// Cut a line to the terminal's width
+fn fit(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
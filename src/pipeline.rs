@@ -0,0 +1,617 @@
+use chrono::{DateTime, Local};
+use crossbeam_channel::{Sender, bounded};
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, RandomState};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::android::{self, TrashedFiles};
+use crate::cloud;
+use crate::color;
+use crate::error::{Result, TimekeeperError};
+use crate::extract::ExtractorChain;
+use crate::hooks::ExecHooks;
+use crate::messaging::detect_messaging_app;
+use crate::metadata::{PlaceOptions, Placement, execute_placement, plan_placement};
+use crate::outcome::{Action, DateSource, FileOutcome};
+use crate::progress::ProgressObserver;
+use crate::route::{BytesPerRoot, Destinations};
+use crate::space;
+use crate::stability;
+use crate::stats::Stats;
+use crate::{Backend, Organizer, ProcessingOrder};
+
+/// Files waiting between two stages unless `--queue-size` says otherwise. Keeps a fast
+/// stage from running far ahead of a slow one while still smoothing out single slow files.
+pub(crate) const DEFAULT_QUEUE_SIZE: usize = 256;
+
+/// Threads writing files. Transfers are bound by the disk or network, not the CPU,
+/// so a few are enough to keep a destination busy.
+const MOVER_THREADS: usize = 4;
+
+/// Transfers the async backend keeps in flight at once
+#[cfg(feature = "async")]
+const ASYNC_TRANSFERS: usize = 16;
+
+/// A file whose capture date is known, on its way to the planner
+struct Dated {
+    source: PathBuf,
+    datetime: DateTime<Local>,
+    date_source: DateSource,
+    received: bool,
+    /// See `DateExtractor::write_back`
+    write_back: bool,
+}
+
+/// What became of a file at the extractors
+enum Dating {
+    Dated(Dated),
+    /// Left alone for now, the reason is the action reported
+    Skipped(Action),
+}
+
+/// Hands every stage's per-file results to the run's consumer and keeps the error
+/// count, stopping the run once `max_errors` files have failed
+#[derive(Clone)]
+struct Reporter {
+    outcomes: Sender<FileOutcome>,
+    stats: Arc<Stats>,
+    terminate_flag: Arc<AtomicBool>,
+    max_errors: Option<usize>,
+    observer: Option<Arc<dyn ProgressObserver>>,
+}
+
+/// Everything the mover stage needs, owned so transfers can also run as tokio tasks
+#[derive(Clone)]
+struct Mover {
+    options: PlaceOptions,
+    destinations: Destinations,
+    /// Check the destination's free space before each copy
+    check_space: bool,
+    reporter: Reporter,
+    /// `--exec-before`/`--exec-after` commands, `None` in dry runs
+    hooks: Option<Arc<ExecHooks>>,
+    /// Destinations planned but not written yet, released once their file is in place
+    reserved: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+/// Picks destinations, one file at a time
+struct Planner<'a> {
+    organizer: &'a Organizer,
+    destinations: Destinations,
+    options: &'a PlaceOptions,
+    /// Shared with the movers, which release each name once its file is written
+    reserved: Arc<Mutex<HashSet<PathBuf>>>,
+    reporter: &'a Reporter,
+}
+
+impl Organizer {
+    /// Run `files` through the processing stages, each connected to the next by a
+    /// bounded channel:
+    ///
+    /// 1. the walker feeds the files in
+    /// 2. extractors (one per core) read each file's date with exiftool
+    /// 3. a single planner picks every destination in the run's `ProcessingOrder`, so
+    ///    no two files can claim a name and clashes resolve the same way on every run
+    ///    (unless the order is random). In path order files are planned in walk order
+    ///    as they come in, the other orders plan every file once all are dated.
+    /// 4. movers copy, move or upload the planned files
+    pub(crate) fn run_pipeline(
+        &self,
+        extractors: &ExtractorChain,
+        files: impl Iterator<Item = PathBuf> + Send,
+        stats: &Arc<Stats>,
+        terminate_flag: &Arc<AtomicBool>,
+        outcomes: &Sender<FileOutcome>,
+    ) -> Result<()> {
+        let stopped = || terminate_flag.load(Ordering::SeqCst);
+        let options = self.place_options(terminate_flag);
+        let reporter = Reporter {
+            outcomes: outcomes.clone(),
+            stats: Arc::clone(stats),
+            terminate_flag: Arc::clone(terminate_flag),
+            max_errors: self.max_errors,
+            observer: self.observer.clone(),
+        };
+        let reporter = &reporter;
+
+        // Files are numbered in walk order, and every number reaches the planner,
+        // `None` for files that were skipped or failed
+        let (path_tx, path_rx) = bounded::<(usize, PathBuf)>(self.queue_size);
+        let (dated_tx, dated_rx) = bounded::<(usize, Option<Dated>)>(self.queue_size);
+        // The walker takes a ticket for each file and the planner hands it back once
+        // the file is planned, so the files held back waiting for an earlier one
+        // stay within what the channels and extractors hold anyway
+        let window = 2 * self.queue_size + rayon::current_num_threads();
+        let (ticket_tx, ticket_rx) = bounded::<()>(window);
+        for _ in 0..window {
+            let _ = ticket_tx.send(());
+        }
+
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                for (index, path) in files.enumerate() {
+                    if stopped()
+                        || ticket_rx.recv().is_err()
+                        || path_tx.send((index, path)).is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+
+            for _ in 0..rayon::current_num_threads() {
+                let path_rx = path_rx.clone();
+                let dated_tx = dated_tx.clone();
+                scope.spawn(move || {
+                    for (index, path) in path_rx {
+                        if stopped() {
+                            break;
+                        }
+                        if let Some(observer) = &reporter.observer {
+                            observer.on_file_start(&path);
+                        }
+                        let dated = match self.date_file(extractors, &path, stats) {
+                            Ok(Dating::Dated(dated)) => Some(dated),
+                            Ok(Dating::Skipped(action)) => {
+                                reporter.send(FileOutcome {
+                                    source: path,
+                                    destination: None,
+                                    action,
+                                    date_source: None,
+                                    error: None,
+                                });
+                                None
+                            }
+                            Err(e) => {
+                                reporter.fail(path, None, None, e);
+                                None
+                            }
+                        };
+                        if dated_tx.send((index, dated)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(path_rx);
+            drop(dated_tx);
+
+            let mover = Mover {
+                options: options.clone(),
+                destinations: self.destinations(),
+                check_space: self.use_copy && self.check_free_space && !self.dry_run,
+                reporter: reporter.clone(),
+                hooks: self.exec_hooks.clone().filter(|_| !self.dry_run),
+                reserved: Arc::new(Mutex::new(HashSet::new())),
+            };
+            let planner = Planner {
+                organizer: self,
+                destinations: mover.destinations.clone(),
+                options: &options,
+                reserved: Arc::clone(&mover.reserved),
+                reporter,
+            };
+            // Renaming or moving files within the tree frees names a later file may
+            // want, so those runs pick every name before the first file goes
+            let hold_placements = !self.dry_run
+                && (self.rename_only
+                    || !self.use_copy
+                        && planner.destinations.all().any(|root| {
+                            root.starts_with(&self.source) || self.source.starts_with(root)
+                        }));
+            // The planner hands each placement to the backend's movers through `send`,
+            // which reports false once they stopped taking files
+            let plan = move |mut send: Box<dyn FnMut(Placement) -> bool + Send>| {
+                let mut held = Vec::new();
+
+                let order = self.planning_order();
+                if order == ProcessingOrder::Path {
+                    let mut emit = |placement: Placement| {
+                        if hold_placements {
+                            held.push(placement);
+                            true
+                        } else {
+                            send(placement)
+                        }
+                    };
+                    // The walk is sorted by name, so walk order is path order. Files
+                    // finishing ahead of an earlier one wait for it.
+                    let mut waiting = HashMap::new();
+                    let mut next = 0;
+                    'files: for (index, dated) in dated_rx {
+                        waiting.insert(index, dated);
+                        while let Some(dated) = waiting.remove(&next) {
+                            next += 1;
+                            let _ = ticket_tx.send(());
+                            let Some(dated) = dated else { continue };
+                            if stopped() {
+                                break 'files;
+                            }
+                            if let Some(placement) = planner.plan(file_size(&dated), dated)
+                                && !emit(placement)
+                            {
+                                break 'files;
+                            }
+                        }
+                    }
+                } else {
+                    // Sorting needs every file's date, only the small `Dated` records
+                    // are kept until then
+                    let mut dated: Vec<(u64, Dated)> = dated_rx
+                        .iter()
+                        .filter_map(|(_, dated)| {
+                            let _ = ticket_tx.send(());
+                            dated
+                        })
+                        .map(|dated| (file_size(&dated), dated))
+                        .collect();
+                    match order {
+                        ProcessingOrder::Date => dated.sort_by(|(_, a), (_, b)| {
+                            (a.datetime, &a.source).cmp(&(b.datetime, &b.source))
+                        }),
+                        ProcessingOrder::Size => dated.sort_by(|(a_size, a), (b_size, b)| {
+                            (a_size, &a.source).cmp(&(b_size, &b.source))
+                        }),
+                        ProcessingOrder::Random => {
+                            // Hashed with the process's random keys, a fresh shuffle each run
+                            let keys = RandomState::new();
+                            dated.sort_by_cached_key(|(_, dated)| keys.hash_one(&dated.source));
+                        }
+                        ProcessingOrder::Path => unreachable!("path order is planned as it comes"),
+                    }
+                    // Every file is planned before the first is written, so names
+                    // and `{seq}` numbers follow the order whatever the movers do
+                    let mut bytes = BytesPerRoot::new();
+                    for (size, dated) in dated {
+                        if stopped() {
+                            break;
+                        }
+                        let root = planner
+                            .destinations
+                            .for_file(&dated.source, size)
+                            .to_path_buf();
+                        if let Some(placement) = planner.plan(size, dated) {
+                            *bytes.entry(root).or_default() += size;
+                            held.push(placement);
+                        }
+                    }
+                    // Only what still has to be written, files already in place are
+                    // left out
+                    self.check_free_space(&bytes)?;
+                }
+
+                for placement in held {
+                    if stopped() || !send(placement) {
+                        break;
+                    }
+                }
+                Ok(())
+            };
+
+            let planning = match self.backend {
+                Backend::Threads => {
+                    let (planned_tx, planned_rx) = bounded::<Placement>(self.queue_size);
+                    let planning =
+                        scope.spawn(move || plan(Box::new(move |p| planned_tx.send(p).is_ok())));
+                    for _ in 0..MOVER_THREADS {
+                        let planned_rx = planned_rx.clone();
+                        let mover = mover.clone();
+                        scope.spawn(move || {
+                            for placement in planned_rx {
+                                if mover.stopped() {
+                                    break;
+                                }
+                                mover.place(placement);
+                            }
+                        });
+                    }
+                    planning
+                }
+                #[cfg(feature = "async")]
+                Backend::Async => {
+                    let (planned_tx, planned_rx) = tokio::sync::mpsc::channel(self.queue_size);
+                    let planning = scope.spawn(move || {
+                        plan(Box::new(move |p| planned_tx.blocking_send(p).is_ok()))
+                    });
+                    scope.spawn(move || run_async_movers(mover, planned_rx));
+                    planning
+                }
+            };
+            planning.join().expect("planner thread panicked")
+        })
+    }
+
+    /// Read a file's capture date, falling back to its file times. Files still being
+    /// written and online-only cloud files (unless hydrating them) are skipped.
+    fn date_file(
+        &self,
+        extractors: &ExtractorChain,
+        source_path: &Path,
+        stats: &Arc<Stats>,
+    ) -> Result<Dating> {
+        if (self.min_age.is_some() || self.stable_for.is_some())
+            && !stability::is_file_settled(source_path, self.min_age, self.stable_for)?
+        {
+            stats.skipped.fetch_add(1, Ordering::SeqCst);
+            return Ok(Dating::Skipped(Action::NotSettled));
+        }
+        // Checked before anything reads the file, which would download it
+        if cloud::is_placeholder(source_path) {
+            if !self.hydrate_placeholders {
+                stats.skipped.fetch_add(1, Ordering::SeqCst);
+                stats.cloud_placeholders.fetch_add(1, Ordering::SeqCst);
+                return Ok(Dating::Skipped(Action::OnlineOnly));
+            }
+            cloud::hydrate(source_path)?;
+        }
+
+        stats.processed.fetch_add(1, Ordering::SeqCst);
+
+        // Media received through messaging apps can be kept apart from camera originals
+        let received = detect_messaging_app(source_path).is_some();
+        if received {
+            stats.received.fetch_add(1, Ordering::SeqCst);
+        }
+
+        let date =
+            extractors
+                .extract(source_path)
+                .map_err(|error| TimekeeperError::ExtractionFailed {
+                    path: source_path.to_path_buf(),
+                    error,
+                })?;
+        if let Some(adjustment) = &date.dst_adjustment {
+            println!("[INFO] {}: {}", source_path.display(), adjustment);
+        }
+        match date.date_source {
+            DateSource::Metadata => {
+                stats.exif_count.fetch_add(1, Ordering::SeqCst);
+            }
+            DateSource::Fallback => {
+                stats.fallback_count.fetch_add(1, Ordering::SeqCst);
+                match date.failure {
+                    None => {
+                        stats.no_date.fetch_add(1, Ordering::SeqCst);
+                    }
+                    Some(e) => {
+                        let message = format!(
+                            "Could not read metadata of {}: {}",
+                            source_path.display(),
+                            e
+                        );
+                        eprintln!("[WARN] {}", message);
+                        stats.record_extraction_failure(message);
+                    }
+                }
+            }
+        }
+
+        Ok(Dating::Dated(Dated {
+            source: source_path.to_path_buf(),
+            datetime: date.datetime,
+            date_source: date.date_source,
+            received,
+            write_back: date.write_back,
+        }))
+    }
+}
+
+impl Planner<'_> {
+    /// Reserve a dated file's destination, `None` when it is already in place or
+    /// could not be planned, which is reported
+    fn plan(&self, size: u64, dated: Dated) -> Option<Placement> {
+        let organizer = self.organizer;
+        let root = self.destinations.for_file(&dated.source, size);
+        let dest_base = if organizer.android_trashed == TrashedFiles::Review
+            && android::is_trashed(&dated.source)
+        {
+            root.join(android::TRASH_REVIEW_DIR)
+        } else if dated.received && organizer.separate_received {
+            root.join("Received")
+        } else {
+            root.to_path_buf()
+        };
+        let mut reserved = self.reserved.lock().unwrap();
+        let planned = plan_placement(
+            &dated.source,
+            &dated.datetime,
+            dated.date_source,
+            &dest_base,
+            self.options,
+            &self.reporter.stats,
+            &reserved,
+        );
+        if let Ok(Some(placement)) = &planned {
+            reserved.insert(placement.dest.clone());
+        }
+        drop(reserved);
+        match planned {
+            Ok(Some(placement)) => Some(Placement {
+                write_back: dated.write_back,
+                ..placement
+            }),
+            Ok(None) => {
+                self.reporter.send(FileOutcome {
+                    source: dated.source,
+                    destination: None,
+                    action: Action::AlreadyInPlace,
+                    date_source: Some(dated.date_source),
+                    error: None,
+                });
+                None
+            }
+            Err(e) => {
+                self.reporter
+                    .fail(dated.source, None, Some(dated.date_source), e);
+                None
+            }
+        }
+    }
+}
+
+fn file_size(dated: &Dated) -> u64 {
+    std::fs::metadata(&dated.source).map_or(0, |m| m.len())
+}
+
+impl Reporter {
+    fn send(&self, outcome: FileOutcome) {
+        if let Some(observer) = &self.observer {
+            observer.on_file_done(&outcome);
+        }
+        // A consumer that stopped listening doesn't stop the run
+        let _ = self.outcomes.send(outcome);
+    }
+
+    /// Count a failed file and stop the run once `max_errors` files have failed, so a
+    /// systematically broken run (read-only destination, dead disk) ends early
+    fn fail(
+        &self,
+        source: PathBuf,
+        destination: Option<PathBuf>,
+        date_source: Option<DateSource>,
+        error: TimekeeperError,
+    ) {
+        if let Some(observer) = &self.observer {
+            observer.on_error(&source, &error);
+        }
+        self.stats.record_error(
+            error.category(),
+            format!("Error processing {}: {}", source.display(), error),
+        );
+        self.send(FileOutcome::failed(source, destination, date_source, error));
+
+        if let Some(max_errors) = self.max_errors
+            && self.stats.errors.load(Ordering::SeqCst) >= max_errors
+            && !self.terminate_flag.swap(true, Ordering::SeqCst)
+        {
+            eprintln!(
+                "{}",
+                color::red(&format!(
+                    "[ERROR] Stopping after {} failed file{}",
+                    max_errors,
+                    if max_errors == 1 { "" } else { "s" }
+                ))
+            );
+        }
+    }
+}
+
+impl Mover {
+    fn stopped(&self) -> bool {
+        self.reporter.terminate_flag.load(Ordering::SeqCst)
+    }
+
+    /// Write a planned file once the destination is known to have room for it
+    fn place(&self, placement: Placement) {
+        let result = if self.check_space {
+            self.ensure_space_for(&placement.source)
+        } else {
+            Ok(())
+        };
+        let result = result
+            .and_then(|()| match &self.hooks {
+                Some(hooks) => hooks.before(&placement.source, &placement.dest),
+                None => Ok(()),
+            })
+            .and_then(|()| execute_placement(&placement, &self.options, &self.reporter.stats));
+        // The file is in place, a failing after-hook only gets reported
+        if result.is_ok()
+            && let Some(hooks) = &self.hooks
+            && let Err(e) = hooks.after(&placement.source, &placement.dest)
+        {
+            eprintln!("[WARN] {}", e);
+            self.reporter
+                .stats
+                .record_error(e.category(), e.to_string());
+        }
+        // Written files are found on disk from now on. Dry runs write nothing, so their
+        // reservations have to stay.
+        if !self.options.dry_run {
+            self.reserved.lock().unwrap().remove(&placement.dest);
+        }
+        let Placement {
+            source,
+            dest,
+            date_source,
+            ..
+        } = placement;
+        match result {
+            Ok(()) => self.reporter.send(FileOutcome {
+                source,
+                destination: Some(dest),
+                action: if self.options.use_copy {
+                    Action::Copy
+                } else {
+                    Action::Move
+                },
+                date_source: Some(date_source),
+                error: None,
+            }),
+            Err(e) => self.reporter.fail(source, Some(dest), Some(date_source), e),
+        }
+    }
+
+    /// Per-file free space check, the volume may be shared with other writers.
+    /// Running out stops the whole run instead of failing every remaining file.
+    fn ensure_space_for(&self, source_path: &Path) -> Result<()> {
+        let required = std::fs::metadata(source_path)?.len();
+        let destination = self.destinations.for_file(source_path, required);
+        let available = space::available_space(destination)?;
+        if required > available {
+            if !self.reporter.terminate_flag.swap(true, Ordering::SeqCst) {
+                eprintln!(
+                    "{}",
+                    color::red(&format!(
+                        "[ERROR] Destination '{}' is out of space, stopping",
+                        destination.display()
+                    ))
+                );
+            }
+            return Err(TimekeeperError::OutOfSpace {
+                path: destination.to_path_buf(),
+                required,
+                available,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Write planned files from a tokio runtime with up to `ASYNC_TRANSFERS` in flight.
+/// The stores' clients block, so each transfer runs on tokio's blocking pool; waiting
+/// for the next file or a free slot never ties up a thread of its own.
+#[cfg(feature = "async")]
+fn run_async_movers(mover: Mover, mut planned_rx: tokio::sync::mpsc::Receiver<Placement>) {
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .max_blocking_threads(ASYNC_TRANSFERS)
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!(
+                "{}",
+                color::red(&format!("[ERROR] Could not start the async backend: {}", e))
+            );
+            mover.reporter.terminate_flag.store(true, Ordering::SeqCst);
+            return;
+        }
+    };
+
+    runtime.block_on(async move {
+        let mut transfers = tokio::task::JoinSet::new();
+        while let Some(placement) = planned_rx.recv().await {
+            if mover.stopped() {
+                break;
+            }
+            if transfers.len() >= ASYNC_TRANSFERS {
+                transfers.join_next().await;
+            }
+            let mover = mover.clone();
+            transfers.spawn_blocking(move || mover.place(placement));
+        }
+        while transfers.join_next().await.is_some() {}
+    });
+}
@@ -0,0 +1,188 @@
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error::TimekeeperError;
+use crate::outcome::FileOutcome;
+use crate::space::format_bytes;
+use crate::stats::{Stats, StatsSnapshot};
+
+/// Hooks into a run as it goes, for progress bars, GUIs and tests. Every method
+/// has an empty default, so observers only implement what they need. Methods are
+/// called from the pipeline's worker threads and should return quickly.
+pub trait ProgressObserver: Send + Sync {
+    /// A file is about to be dated
+    fn on_file_start(&self, _source: &Path) {}
+
+    /// A file is done, whatever happened to it (failures included)
+    fn on_file_done(&self, _outcome: &FileOutcome) {}
+
+    /// Another chunk of a local copy was written, `copied` of `total` bytes so far
+    fn on_copy_progress(&self, _source: &Path, _copied: u64, _total: u64) {}
+
+    /// A file failed, just before its outcome is reported
+    fn on_error(&self, _source: &Path, _error: &TimekeeperError) {}
+
+    /// The run is over, finished or stopped
+    fn on_finish(&self, _stats: &StatsSnapshot) {}
+}
+
+/// How often `ConsoleProgress` prints at most
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The CLI's progress report: a `[PROGRESS]` line at most every ten seconds
+pub struct ConsoleProgress {
+    stats: Arc<Stats>,
+    last_printed: Mutex<Instant>,
+}
+
+impl ConsoleProgress {
+    pub fn new(stats: Arc<Stats>) -> Self {
+        ConsoleProgress {
+            stats,
+            last_printed: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+impl ConsoleProgress {
+    fn print(&self, copying: Option<(&Path, u64, u64)>) {
+        let mut last_printed = self.last_printed.lock().unwrap();
+        if last_printed.elapsed() < PROGRESS_INTERVAL {
+            return;
+        }
+        *last_printed = Instant::now();
+        match copying {
+            Some((source, copied, total)) => println!(
+                "[PROGRESS] {}, copying {} ({}/{})",
+                self.stats.progress_line(),
+                source.display(),
+                format_bytes(copied),
+                format_bytes(total)
+            ),
+            None => println!("[PROGRESS] {}", self.stats.progress_line()),
+        }
+    }
+}
+
+impl ProgressObserver for ConsoleProgress {
+    fn on_file_done(&self, _outcome: &FileOutcome) {
+        self.print(None);
+    }
+
+    fn on_copy_progress(&self, source: &Path, copied: u64, total: u64) {
+        // A long copy prints too, rather than staying silent until it is done
+        self.print(Some((source, copied, total)));
+    }
+}
+
+/// How the CLI reports progress
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProgressStyle {
+    /// `ConsoleProgress` lines for people
+    #[default]
+    Human,
+    /// `PlainProgress` lines for programs wrapping timekeeper
+    Plain,
+}
+
+impl std::str::FromStr for ProgressStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "human" => Ok(ProgressStyle::Human),
+            "plain" => Ok(ProgressStyle::Plain),
+            _ => Err(format!(
+                "unknown progress style '{}' (expected human or plain)",
+                s
+            )),
+        }
+    }
+}
+
+/// How often `PlainProgress` writes at most, the last line of a run is always written
+const PLAIN_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Progress for GUI wrappers: `PROGRESS <done> <total> <bytes_done> <bytes_total>`
+/// lines on a stream of their own, so a progress bar needs no parsing of human output
+pub struct PlainProgress {
+    stats: Arc<Stats>,
+    out: Mutex<(Box<dyn Write + Send>, Option<Instant>)>,
+}
+
+impl PlainProgress {
+    pub fn new(stats: Arc<Stats>, out: Box<dyn Write + Send>) -> Self {
+        PlainProgress {
+            stats,
+            out: Mutex::new((out, None)),
+        }
+    }
+
+    fn write(&self, snapshot: &StatsSnapshot, force: bool) {
+        let mut out = self.out.lock().unwrap();
+        let (writer, last_written) = &mut *out;
+        if !force && last_written.is_some_and(|at| at.elapsed() < PLAIN_INTERVAL) {
+            return;
+        }
+        *last_written = Some(Instant::now());
+        // A wrapper that stopped reading shouldn't fail the run
+        let _ = writeln!(
+            writer,
+            "PROGRESS {} {} {} {}",
+            snapshot.files_done(),
+            snapshot.total,
+            snapshot.bytes_transferred,
+            snapshot.bytes_total
+        )
+        .and_then(|()| writer.flush());
+    }
+}
+
+impl ProgressObserver for PlainProgress {
+    fn on_file_done(&self, _outcome: &FileOutcome) {
+        self.write(&self.stats.snapshot(), false);
+    }
+
+    fn on_copy_progress(&self, _source: &Path, _copied: u64, _total: u64) {
+        self.write(&self.stats.snapshot(), false);
+    }
+
+    fn on_finish(&self, stats: &StatsSnapshot) {
+        self.write(stats, true);
+    }
+}
+
+/// Several observers watching one run, called in order
+impl ProgressObserver for Vec<Arc<dyn ProgressObserver>> {
+    fn on_file_start(&self, source: &Path) {
+        for observer in self {
+            observer.on_file_start(source);
+        }
+    }
+
+    fn on_file_done(&self, outcome: &FileOutcome) {
+        for observer in self {
+            observer.on_file_done(outcome);
+        }
+    }
+
+    fn on_copy_progress(&self, source: &Path, copied: u64, total: u64) {
+        for observer in self {
+            observer.on_copy_progress(source, copied, total);
+        }
+    }
+
+    fn on_error(&self, source: &Path, error: &TimekeeperError) {
+        for observer in self {
+            observer.on_error(source, error);
+        }
+    }
+
+    fn on_finish(&self, stats: &StatsSnapshot) {
+        for observer in self {
+            observer.on_finish(stats);
+        }
+    }
+}
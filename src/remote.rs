@@ -0,0 +1,189 @@
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::manifest::relative_key;
+
+/// Attempts made for a remote request before giving up
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Wait before the first retry, doubled for every further attempt
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Ranges of one large file in flight at once, each over its own request or
+/// connection, so a high-latency link isn't left waiting on a single stream
+const PARALLEL_RANGES: usize = 4;
+
+/// A destination that isn't a local directory. Destination paths are still built by
+/// joining the layout onto the `--destination` URL, the store maps them to its own
+/// `/`-separated keys.
+pub trait RemoteStore: Send + Sync {
+    /// The `--destination` URL the store was opened from
+    fn root(&self) -> &Path;
+
+    /// Size of the file stored at `key`, or `None` when there is none
+    fn stat(&self, key: &str) -> io::Result<Option<u64>>;
+
+    /// Upload a local file to `key`
+    fn upload(&self, local: &Path, key: &str) -> io::Result<()>;
+
+    /// Create the directory `key` and its parents, for stores with real directories
+    fn create_dir_all(&self, _key: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Key of a destination path below the store's root
+    fn key_for(&self, path: &Path) -> io::Result<String> {
+        relative_key(self.root(), path).ok_or_else(|| {
+            io::Error::other(format!(
+                "'{}' is outside the destination '{}'",
+                path.display(),
+                self.root().display()
+            ))
+        })
+    }
+}
+
+/// Open the remote store a `--destination` URL points at, or `None` for a local path
+pub fn open_destination(destination: &Path) -> Option<io::Result<Arc<dyn RemoteStore>>> {
+    let url = destination.to_str()?;
+    let (scheme, _) = url.split_once("://")?;
+
+    Some(match scheme {
+        #[cfg(feature = "s3")]
+        "s3" => crate::s3::S3Store::from_url(url).map(|store| Arc::new(store) as _),
+        #[cfg(feature = "sftp")]
+        "sftp" => crate::sftp::SftpStore::from_url(url).map(|store| Arc::new(store) as _),
+        #[cfg(feature = "webdav")]
+        "dav" | "davs" => {
+            crate::webdav::WebDavStore::from_url(url).map(|store| Arc::new(store) as _)
+        }
+        _ => Err(io::Error::other(format!(
+            "Unsupported destination '{}://' (is timekeeper built with the matching feature?)",
+            scheme
+        ))),
+    })
+}
+
+/// Outcome of one failed attempt at a remote request
+#[cfg_attr(
+    not(any(feature = "s3", feature = "sftp", feature = "webdav")),
+    allow(dead_code)
+)]
+pub(crate) enum Attempt {
+    /// Worth trying again: connection trouble, server errors, rate limiting
+    Retry(io::Error),
+    /// Worth trying again, but not before the server-requested delay (`Retry-After`)
+    #[cfg_attr(not(any(feature = "s3", feature = "webdav")), allow(dead_code))]
+    RetryAfter(io::Error, Duration),
+    /// Trying again won't help
+    Fail(io::Error),
+}
+
+impl Attempt {
+    /// Retryable failure, waiting as long as the server asked when it sent `Retry-After`
+    #[cfg_attr(not(any(feature = "s3", feature = "webdav")), allow(dead_code))]
+    pub(crate) fn retry_after(error: io::Error, delay: Option<Duration>) -> Self {
+        match delay {
+            Some(delay) => Attempt::RetryAfter(error, delay),
+            None => Attempt::Retry(error),
+        }
+    }
+}
+
+/// Run `attempt` until it succeeds, fails for good or runs out of attempts,
+/// backing off exponentially between tries
+#[cfg_attr(
+    not(any(feature = "s3", feature = "sftp", feature = "webdav")),
+    allow(dead_code)
+)]
+pub(crate) fn with_retries<T>(mut attempt: impl FnMut() -> Result<T, Attempt>) -> io::Result<T> {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempts = 1;
+    loop {
+        match attempt() {
+            Ok(value) => return Ok(value),
+            Err(Attempt::Retry(_)) if attempts < MAX_ATTEMPTS => {
+                thread::sleep(backoff);
+                backoff *= 2;
+                attempts += 1;
+            }
+            Err(Attempt::RetryAfter(_, delay)) if attempts < MAX_ATTEMPTS => {
+                thread::sleep(delay.max(backoff));
+                backoff *= 2;
+                attempts += 1;
+            }
+            Err(Attempt::Retry(e)) | Err(Attempt::RetryAfter(e, _)) | Err(Attempt::Fail(e)) => {
+                return Err(e);
+            }
+        }
+    }
+}
+
+/// Split a file of `size` bytes into ranges of `range_size` and hand each to
+/// `transfer` as `(index, start, len)`, up to `PARALLEL_RANGES` at a time. Results
+/// come back in range order; after a failure no new range is started.
+#[cfg_attr(not(any(feature = "s3", feature = "sftp")), allow(dead_code))]
+pub(crate) fn in_parallel_ranges<T: Send>(
+    size: u64,
+    range_size: u64,
+    transfer: impl Fn(usize, u64, u64) -> io::Result<T> + Sync,
+) -> io::Result<Vec<T>> {
+    let count = size.div_ceil(range_size).max(1) as usize;
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let results = Mutex::new((0..count).map(|_| None).collect::<Vec<_>>());
+
+    thread::scope(|scope| {
+        for _ in 0..PARALLEL_RANGES.min(count) {
+            scope.spawn(|| {
+                while !failed.load(Ordering::SeqCst) {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    if index >= count {
+                        break;
+                    }
+                    let start = index as u64 * range_size;
+                    let result = transfer(index, start, range_size.min(size - start));
+                    if result.is_err() {
+                        failed.store(true, Ordering::SeqCst);
+                    }
+                    results.lock().unwrap()[index] = Some(result);
+                }
+            });
+        }
+    });
+
+    let results = results.into_inner().unwrap();
+    let mut done = Vec::with_capacity(count);
+    let mut error = None;
+    for result in results {
+        match result {
+            Some(Ok(value)) => done.push(value),
+            Some(Err(e)) => return Err(e),
+            None => error = Some(io::Error::other("transfer stopped part way")),
+        }
+    }
+    match error {
+        Some(e) => Err(e),
+        None => Ok(done),
+    }
+}
+
+/// Percent-encode everything but unreserved characters, keeping `/` in paths
+#[cfg_attr(not(any(feature = "s3", feature = "webdav")), allow(dead_code))]
+pub(crate) fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
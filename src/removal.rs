@@ -0,0 +1,41 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Remove a file for good, or move it to the system trash when `use_trash` is set so
+/// a mistake can still be undone from the file manager
+pub fn remove_file(path: &Path, use_trash: bool) -> io::Result<()> {
+    if use_trash {
+        return move_to_trash(path);
+    }
+    fs::remove_file(path)
+}
+
+/// Like `remove_file`, for a folder and everything in it
+pub fn remove_dir_all(path: &Path, use_trash: bool) -> io::Result<()> {
+    if use_trash {
+        return move_to_trash(path);
+    }
+    fs::remove_dir_all(path)
+}
+
+#[cfg(feature = "trash")]
+fn move_to_trash(path: &Path) -> io::Result<()> {
+    // The trash crate reports a missing file as a generic error, keep NotFound
+    // recognizable for callers that tolerate files that are already gone
+    if fs::symlink_metadata(path).is_err() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} does not exist", path.display()),
+        ));
+    }
+    trash::delete(path).map_err(io::Error::other)
+}
+
+#[cfg(not(feature = "trash"))]
+fn move_to_trash(_path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "the system trash needs timekeeper built with the trash feature",
+    ))
+}
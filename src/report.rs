@@ -0,0 +1,180 @@
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::RunOutcome;
+use crate::outcome::{Action, FileOutcome};
+use crate::progress::ProgressObserver;
+use crate::stats::StatsSnapshot;
+
+/// Every file of the run, one console line each
+pub const LOG_FILE: &str = "timekeeper.log";
+/// The files copied or moved, as tab-separated `action source destination` lines
+pub const JOURNAL_FILE: &str = "journal.tsv";
+/// How the run ended and its counters
+pub const SUMMARY_FILE: &str = "summary.json";
+/// The files that failed and why, plus the run's own error
+pub const ERRORS_FILE: &str = "errors.log";
+
+/// Audit trail for unattended runs: each run gets a folder named after its start
+/// time below the report directory, holding the log, the journal, the JSON summary
+/// and the errors of that run.
+pub struct RunReport {
+    dir: PathBuf,
+    current: Mutex<Option<ReportRun>>,
+}
+
+/// The folder of the run in progress
+struct ReportRun {
+    path: PathBuf,
+    started: DateTime<Local>,
+    sources: Vec<PathBuf>,
+    destination: Option<PathBuf>,
+    dry_run: bool,
+    log: BufWriter<File>,
+    journal: BufWriter<File>,
+    errors: BufWriter<File>,
+}
+
+#[derive(Serialize)]
+struct RunSummary<'a> {
+    outcome: RunOutcome,
+    error: Option<&'a str>,
+    started: DateTime<Local>,
+    finished: DateTime<Local>,
+    dry_run: bool,
+    sources: &'a [PathBuf],
+    destination: Option<&'a Path>,
+    stats: &'a StatsSnapshot,
+}
+
+impl RunReport {
+    pub fn new(dir: PathBuf) -> Self {
+        RunReport {
+            dir,
+            current: Mutex::new(None),
+        }
+    }
+
+    /// Start the folder of a new run and return its path. Files reported until
+    /// `finish` are written into it.
+    pub fn begin(
+        &self,
+        sources: &[PathBuf],
+        destination: Option<&Path>,
+        dry_run: bool,
+    ) -> io::Result<PathBuf> {
+        let started = Local::now();
+        fs::create_dir_all(&self.dir)?;
+        let path = self.unique_folder(&started)?;
+
+        let mut journal = BufWriter::new(File::create(path.join(JOURNAL_FILE))?);
+        if dry_run {
+            writeln!(journal, "# dry run, nothing was copied or moved")?;
+        }
+        writeln!(journal, "# action\tsource\tdestination")?;
+        let run = ReportRun {
+            log: BufWriter::new(File::create(path.join(LOG_FILE))?),
+            journal,
+            errors: BufWriter::new(File::create(path.join(ERRORS_FILE))?),
+            path: path.clone(),
+            started,
+            sources: sources.to_vec(),
+            destination: destination.map(Path::to_path_buf),
+            dry_run,
+        };
+        *self.current.lock().unwrap() = Some(run);
+        Ok(path)
+    }
+
+    /// Close the current run, writing its summary with how it ended
+    pub fn finish(
+        &self,
+        outcome: RunOutcome,
+        error: Option<&str>,
+        stats: &StatsSnapshot,
+    ) -> io::Result<()> {
+        let Some(mut run) = self.current.lock().unwrap().take() else {
+            return Ok(());
+        };
+        if let Some(error) = error {
+            writeln!(run.errors, "{}", error)?;
+            writeln!(run.log, "{} {}", timestamp(), error)?;
+        }
+        run.log.flush()?;
+        run.journal.flush()?;
+        run.errors.flush()?;
+
+        let summary = RunSummary {
+            outcome,
+            error,
+            started: run.started,
+            finished: Local::now(),
+            dry_run: run.dry_run,
+            sources: &run.sources,
+            destination: run.destination.as_deref(),
+            stats,
+        };
+        let json = serde_json::to_string_pretty(&summary).map_err(io::Error::other)?;
+        fs::write(run.path.join(SUMMARY_FILE), json + "\n")
+    }
+
+    /// `<dir>/<start time>`, with a counter when runs start within the same second
+    fn unique_folder(&self, started: &DateTime<Local>) -> io::Result<PathBuf> {
+        // No colons, which Windows doesn't allow in names
+        let name = started.format("%Y-%m-%dT%H-%M-%S").to_string();
+        let mut path = self.dir.join(&name);
+        let mut counter = 1;
+        loop {
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(path),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    counter += 1;
+                    path = self.dir.join(format!("{}-{}", name, counter));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl ProgressObserver for RunReport {
+    fn on_file_done(&self, outcome: &FileOutcome) {
+        let mut current = self.current.lock().unwrap();
+        let Some(run) = current.as_mut() else {
+            return;
+        };
+        // A full disk shows as an error when the run is finished and flushed
+        let _ = run.record(outcome);
+    }
+}
+
+impl ReportRun {
+    fn record(&mut self, outcome: &FileOutcome) -> io::Result<()> {
+        let message = outcome.message(self.dry_run);
+        writeln!(self.log, "{} {}", timestamp(), message)?;
+        match (outcome.action, &outcome.destination) {
+            (Action::Failed, _) => writeln!(self.errors, "{}", message)?,
+            (Action::Copy | Action::Move, Some(destination)) => writeln!(
+                self.journal,
+                "{}\t{}\t{}",
+                if outcome.action == Action::Copy {
+                    "copy"
+                } else {
+                    "move"
+                },
+                outcome.source.display(),
+                destination.display()
+            )?,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn timestamp() -> String {
+    Local::now().format("%Y-%m-%dT%H:%M:%S%:z").to_string()
+}
@@ -0,0 +1,35 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::metadata::is_video_file;
+
+/// The destination roots of a run and which files go to which
+#[derive(Clone)]
+pub(crate) struct Destinations {
+    pub(crate) main: PathBuf,
+    /// Where videos go instead of `main`
+    pub(crate) videos: Option<PathBuf>,
+    /// Files of at least this many bytes go to the given root, before any other rule
+    pub(crate) large: Option<(u64, PathBuf)>,
+}
+
+impl Destinations {
+    /// The root a file of `size` bytes is laid out under
+    pub(crate) fn for_file(&self, source_path: &Path, size: u64) -> &Path {
+        match (&self.large, &self.videos) {
+            (Some((threshold, large)), _) if size >= *threshold => large,
+            (_, Some(videos)) if is_video_file(source_path) => videos,
+            _ => &self.main,
+        }
+    }
+
+    /// Every root files may be written to
+    pub(crate) fn all(&self) -> impl Iterator<Item = &PathBuf> {
+        std::iter::once(&self.main)
+            .chain(&self.videos)
+            .chain(self.large.as_ref().map(|(_, large)| large))
+    }
+}
+
+/// Bytes headed for each destination root
+pub(crate) type BytesPerRoot = BTreeMap<PathBuf, u64>;
@@ -0,0 +1,381 @@
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use ureq::http::{self, Response};
+
+use crate::remote::{Attempt, RemoteStore, in_parallel_ranges, uri_encode, with_retries};
+
+/// Files up to this size go up in a single PUT, larger ones as a multipart upload
+const MULTIPART_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+/// Smallest part of a multipart upload, raised for huge files to stay within `MAX_PARTS`
+const PART_SIZE: u64 = 16 * 1024 * 1024;
+
+/// S3 refuses multipart uploads with more parts than this
+const MAX_PARTS: u64 = 10_000;
+
+/// Payload hash sent with every request, bodies are protected by TLS rather than signed
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// Request body, re-created for every attempt
+enum Payload<'a> {
+    Empty,
+    Bytes(&'a [u8]),
+    File(&'a Path),
+}
+
+/// S3-compatible object storage (AWS, Backblaze B2, Wasabi, MinIO) addressed as
+/// `s3://bucket/prefix`. Credentials, region and endpoint come from the usual
+/// `AWS_*` environment variables; requests use path-style addressing.
+pub struct S3Store {
+    root: PathBuf,
+    bucket: String,
+    prefix: String,
+    endpoint: String,
+    host: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl S3Store {
+    /// Open `s3://bucket/prefix`. Reads `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`,
+    /// `AWS_SESSION_TOKEN`, `AWS_REGION` (or `AWS_DEFAULT_REGION`) and
+    /// `AWS_ENDPOINT_URL_S3` (or `AWS_ENDPOINT_URL`) for non-AWS providers.
+    pub fn from_url(url: &str) -> io::Result<Self> {
+        let location = url
+            .strip_prefix("s3://")
+            .ok_or_else(|| io::Error::other(format!("Not an s3:// URL: '{}'", url)))?;
+        let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+        if bucket.is_empty() {
+            return Err(io::Error::other(format!("No bucket in '{}'", url)));
+        }
+
+        let env = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let (Some(access_key), Some(secret_key)) =
+            (env("AWS_ACCESS_KEY_ID"), env("AWS_SECRET_ACCESS_KEY"))
+        else {
+            return Err(io::Error::other(
+                "S3 destinations need AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY",
+            ));
+        };
+        let region = env("AWS_REGION")
+            .or_else(|| env("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|| "us-east-1".to_string());
+        let endpoint = env("AWS_ENDPOINT_URL_S3")
+            .or_else(|| env("AWS_ENDPOINT_URL"))
+            .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region));
+        let endpoint = endpoint.trim_end_matches('/').to_string();
+        let host = endpoint
+            .split_once("://")
+            .map_or(endpoint.as_str(), |(_, rest)| rest)
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+
+        let agent = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .build()
+            .into();
+
+        Ok(S3Store {
+            root: PathBuf::from(url),
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+            endpoint,
+            host,
+            region,
+            access_key,
+            secret_key,
+            session_token: env("AWS_SESSION_TOKEN"),
+            agent,
+        })
+    }
+
+    /// Full object key, with the URL's prefix in front
+    fn object_key(&self, key: &str) -> String {
+        if self.prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}/{}", self.prefix, key)
+        }
+    }
+
+    /// Send one signed request. Server errors, throttling and transport failures are
+    /// retryable, any other response is handed back for the caller to judge.
+    fn send(
+        &self,
+        method: &str,
+        key: &str,
+        query: &[(&str, &str)],
+        payload: Payload,
+    ) -> Result<Response<ureq::Body>, Attempt> {
+        let uri = format!(
+            "/{}/{}",
+            uri_encode(&self.bucket, false),
+            uri_encode(&self.object_key(key), false)
+        );
+        let mut pairs: Vec<_> = query
+            .iter()
+            .map(|(k, v)| format!("{}={}", uri_encode(k, true), uri_encode(v, true)))
+            .collect();
+        pairs.sort();
+        let query = pairs.join("&");
+
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+
+        let mut headers = vec![
+            ("host", self.host.clone()),
+            ("x-amz-content-sha256", UNSIGNED_PAYLOAD.to_string()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method, uri, query, canonical_headers, signed_headers, UNSIGNED_PAYLOAD
+        );
+
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{:x}",
+            amz_date,
+            scope,
+            Sha256::digest(canonical_request.as_bytes())
+        );
+        let mut signing_key = hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), &date);
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            signing_key = hmac_sha256(&signing_key, part);
+        }
+        let signature = hex(&hmac_sha256(&signing_key, &string_to_sign));
+
+        let url = if query.is_empty() {
+            format!("{}{}", self.endpoint, uri)
+        } else {
+            format!("{}{}?{}", self.endpoint, uri, query)
+        };
+        let mut request = http::Request::builder().method(method).uri(url).header(
+            "authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.access_key, scope, signed_headers, signature
+            ),
+        );
+        for (name, value) in headers.into_iter().filter(|(name, _)| *name != "host") {
+            request = request.header(name, value);
+        }
+
+        let fail = |e: http::Error| Attempt::Fail(io::Error::other(e));
+        let result = match payload {
+            Payload::Empty => self.agent.run(request.body(()).map_err(fail)?),
+            Payload::Bytes(bytes) => self.agent.run(request.body(bytes).map_err(fail)?),
+            Payload::File(path) => {
+                let file = File::open(path).map_err(Attempt::Fail)?;
+                self.agent.run(request.body(file).map_err(fail)?)
+            }
+        };
+
+        let response = result.map_err(|e| Attempt::Retry(io::Error::other(e)))?;
+        let status = response.status();
+        if status.is_server_error() || status == http::StatusCode::TOO_MANY_REQUESTS {
+            let delay = response
+                .headers()
+                .get("retry-after")
+                .and_then(|value| value.to_str().ok()?.trim().parse().ok())
+                .map(Duration::from_secs);
+            return Err(Attempt::retry_after(status_error(response), delay));
+        }
+        Ok(response)
+    }
+
+    fn multipart_upload(&self, local: &Path, key: &str, size: u64) -> io::Result<()> {
+        let created = with_retries(|| {
+            let response = self.send("POST", key, &[("uploads", "")], Payload::Empty)?;
+            read_body(expect_success(response)?)
+        })?;
+        let upload_id = xml_value(&created, "UploadId")
+            .ok_or_else(|| io::Error::other("S3 did not return an upload id"))?;
+
+        let result = self
+            .upload_parts(
+                local,
+                key,
+                &upload_id,
+                size,
+                PART_SIZE.max(size.div_ceil(MAX_PARTS)),
+            )
+            .and_then(|etags| self.complete_upload(key, &upload_id, &etags));
+
+        if result.is_err() {
+            // Don't leave billed, invisible parts behind
+            let _ = with_retries(|| {
+                self.send("DELETE", key, &[("uploadId", &upload_id)], Payload::Empty)
+                    .map(|_| ())
+            });
+        }
+        result
+    }
+
+    /// Upload the parts of a multipart upload, several at once, returning their ETags
+    fn upload_parts(
+        &self,
+        local: &Path,
+        key: &str,
+        upload_id: &str,
+        size: u64,
+        part_size: u64,
+    ) -> io::Result<Vec<String>> {
+        in_parallel_ranges(size, part_size, |index, start, len| {
+            let mut file = File::open(local)?;
+            file.seek(SeekFrom::Start(start))?;
+            let mut buffer = Vec::with_capacity(len as usize);
+            file.take(len).read_to_end(&mut buffer)?;
+            if (buffer.len() as u64) < len {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("{} shrank while uploading", local.display()),
+                ));
+            }
+
+            let part_number = (index + 1).to_string();
+            with_retries(|| {
+                let response = self.send(
+                    "PUT",
+                    key,
+                    &[("partNumber", &part_number), ("uploadId", upload_id)],
+                    Payload::Bytes(&buffer),
+                )?;
+                let response = expect_success(response)?;
+                response
+                    .headers()
+                    .get("etag")
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string)
+                    .ok_or_else(|| Attempt::Fail(io::Error::other("S3 part has no ETag")))
+            })
+        })
+    }
+
+    fn complete_upload(&self, key: &str, upload_id: &str, etags: &[String]) -> io::Result<()> {
+        let mut body = String::from("<CompleteMultipartUpload>");
+        for (index, etag) in etags.iter().enumerate() {
+            body.push_str(&format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                index + 1,
+                etag
+            ));
+        }
+        body.push_str("</CompleteMultipartUpload>");
+
+        with_retries(|| {
+            let response = self.send(
+                "POST",
+                key,
+                &[("uploadId", upload_id)],
+                Payload::Bytes(body.as_bytes()),
+            )?;
+            // S3 can report a failed completion inside a 200 response
+            let reply = read_body(expect_success(response)?)?;
+            if reply.contains("<Error>") {
+                return Err(Attempt::Retry(io::Error::other(format!(
+                    "S3 could not complete the upload: {}",
+                    xml_value(&reply, "Message").unwrap_or(reply)
+                ))));
+            }
+            Ok(())
+        })
+    }
+}
+
+impl RemoteStore for S3Store {
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn stat(&self, key: &str) -> io::Result<Option<u64>> {
+        with_retries(|| {
+            let response = self.send("HEAD", key, &[], Payload::Empty)?;
+            if response.status() == http::StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            let response = expect_success(response)?;
+            Ok(response
+                .headers()
+                .get("content-length")
+                .and_then(|value| value.to_str().ok()?.parse().ok()))
+        })
+    }
+
+    fn upload(&self, local: &Path, key: &str) -> io::Result<()> {
+        let size = fs::metadata(local)?.len();
+        if size > MULTIPART_THRESHOLD {
+            return self.multipart_upload(local, key, size);
+        }
+
+        with_retries(|| {
+            let response = self.send("PUT", key, &[], Payload::File(local))?;
+            expect_success(response).map(|_| ())
+        })
+    }
+}
+
+fn expect_success(response: Response<ureq::Body>) -> Result<Response<ureq::Body>, Attempt> {
+    if response.status().is_success() {
+        Ok(response)
+    } else {
+        Err(Attempt::Fail(status_error(response)))
+    }
+}
+
+fn status_error(mut response: Response<ureq::Body>) -> io::Error {
+    let status = response.status();
+    let body = response.body_mut().read_to_string().unwrap_or_default();
+    match xml_value(&body, "Message") {
+        Some(message) => io::Error::other(format!("S3 returned {}: {}", status, message)),
+        None => io::Error::other(format!("S3 returned {}", status)),
+    }
+}
+
+fn read_body(mut response: Response<ureq::Body>) -> Result<String, Attempt> {
+    response
+        .body_mut()
+        .read_to_string()
+        .map_err(|e| Attempt::Retry(io::Error::other(e)))
+}
+
+/// Text of the first `<tag>` element, enough for S3's flat XML replies
+fn xml_value(xml: &str, tag: &str) -> Option<String> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(xml[start..end].to_string())
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
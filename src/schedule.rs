@@ -0,0 +1,63 @@
+use chrono::Local;
+use cron::Schedule;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Longest uninterrupted sleep between wall-clock checks. Monotonic sleeps don't
+/// advance while the machine is suspended, so waking up regularly is what lets
+/// a missed run be caught up shortly after resume.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Parse a cron expression. Standard 5-field expressions (`0 3 * * *`) are accepted
+/// alongside the 6/7-field form with seconds and years.
+pub fn parse_schedule(expr: &str) -> Result<Schedule, String> {
+    let expr = expr.trim();
+    let expr = if expr.split_whitespace().count() == 5 {
+        format!("0 {}", expr)
+    } else {
+        expr.to_string()
+    };
+
+    Schedule::from_str(&expr).map_err(|e| format!("invalid cron expression '{}': {}", expr, e))
+}
+
+/// Run `job` every time `schedule` fires until `terminate_flag` is set.
+/// Runs missed while the machine was asleep (or while a previous run was still going)
+/// are collapsed into a single catch-up run rather than replayed one by one.
+pub fn run_on_schedule(
+    schedule: &Schedule,
+    terminate_flag: &Arc<AtomicBool>,
+    mut job: impl FnMut(),
+) {
+    let Some(mut next) = schedule.upcoming(Local).next() else {
+        return;
+    };
+    println!(
+        "[INFO] Next scheduled run at {}",
+        next.format("%Y-%m-%d %H:%M")
+    );
+
+    while !terminate_flag.load(Ordering::SeqCst) {
+        let now = Local::now();
+        if now < next {
+            let wait = (next - now).to_std().unwrap_or(Duration::ZERO);
+            std::thread::sleep(wait.min(POLL_INTERVAL));
+            continue;
+        }
+
+        job();
+
+        match schedule.after(&Local::now()).next() {
+            Some(upcoming) => {
+                next = upcoming;
+                println!(
+                    "[INFO] Next scheduled run at {}",
+                    next.format("%Y-%m-%d %H:%M")
+                );
+            }
+            None => return,
+        }
+    }
+}
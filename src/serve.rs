@@ -0,0 +1,284 @@
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::Organizer;
+use crate::stats::{Stats, StatsSnapshot};
+
+/// How often `status` with `follow` sends a progress notification
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the accept loop looks at the terminate flag
+const ACCEPT_POLL: Duration = Duration::from_millis(200);
+
+// JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// The job's settings were rejected
+const JOB_REJECTED: i64 = -32000;
+
+/// Builds the organizer for a `start-job` request from its params, the organize
+/// flags keyed by their long names (`{"source": "/media/card", "dry-run": true}`)
+pub type JobFactory =
+    dyn Fn(&Map<String, Value>, &Arc<Stats>) -> Result<Organizer, String> + Send + Sync;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum JobState {
+    Running,
+    Finished,
+    Cancelled,
+    Failed,
+}
+
+struct Job {
+    stats: Arc<Stats>,
+    terminate_flag: Arc<AtomicBool>,
+    state: Mutex<(JobState, Option<String>)>,
+}
+
+#[derive(Serialize)]
+struct JobStatus {
+    job: u64,
+    state: JobState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    progress_percent: f64,
+    bytes_progress_percent: f64,
+    stats: StatsSnapshot,
+}
+
+impl Job {
+    fn status(&self, id: u64) -> JobStatus {
+        let (state, error) = self.state.lock().unwrap().clone();
+        let stats = self.stats.snapshot();
+        JobStatus {
+            job: id,
+            state,
+            error,
+            progress_percent: stats.progress_percent(),
+            bytes_progress_percent: stats.bytes_progress_percent(),
+            stats,
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.state.lock().unwrap().0 == JobState::Running
+    }
+}
+
+/// Runs organize jobs submitted over a local socket
+struct JobServer {
+    factory: Box<JobFactory>,
+    jobs: Mutex<BTreeMap<u64, Arc<Job>>>,
+    next_id: AtomicU64,
+}
+
+/// Serve JSON-RPC 2.0 on the Unix socket at `socket_path` until `terminate_flag` is
+/// set, one request per line and one response per line. Methods:
+///
+/// - `start-job` with the organize flags as params, returns `{"job": id}`
+/// - `cancel-job` with `{"job": id}`, stops the job after the files in flight
+/// - `status` with `{"job": id}` for one job, or no params for every job. With
+///   `"follow": true` a `progress` notification is sent every second until the job
+///   ends, followed by the final status as the response.
+///
+/// Jobs run in the background, several at once. When serving stops, running jobs
+/// are cancelled and the socket file is removed.
+pub fn serve(
+    socket_path: &Path,
+    factory: Box<JobFactory>,
+    terminate_flag: &AtomicBool,
+) -> io::Result<()> {
+    if socket_path.exists() {
+        if UnixStream::connect(socket_path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is already being served", socket_path.display()),
+            ));
+        }
+        // Left behind by a server that didn't shut down cleanly
+        std::fs::remove_file(socket_path)?;
+    }
+    let listener = UnixListener::bind(socket_path)?;
+    // Whoever can connect can move files around as this user
+    std::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(0o600))?;
+    listener.set_nonblocking(true)?;
+
+    let server = Arc::new(JobServer {
+        factory,
+        jobs: Mutex::new(BTreeMap::new()),
+        next_id: AtomicU64::new(1),
+    });
+    while !terminate_flag.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let server = Arc::clone(&server);
+                std::thread::spawn(move || {
+                    // A misbehaving client must never take the server down with it
+                    let _ = server.handle_connection(stream);
+                });
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(ACCEPT_POLL),
+            Err(e) => eprintln!("[WARN] Cannot accept connection: {}", e),
+        }
+    }
+
+    for job in server.jobs.lock().unwrap().values() {
+        job.terminate_flag.store(true, Ordering::SeqCst);
+    }
+    std::fs::remove_file(socket_path)
+}
+
+impl JobServer {
+    fn handle_connection(&self, stream: UnixStream) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = match serde_json::from_str::<Value>(&line) {
+                Ok(request) => self.handle_request(&request, &mut writer)?,
+                Err(e) => Some(error_response(Value::Null, PARSE_ERROR, &e.to_string())),
+            };
+            if let Some(response) = response {
+                writeln!(writer, "{}", response)?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Answer one request. Notifications (requests without an id) get no response.
+    fn handle_request(
+        &self,
+        request: &Value,
+        writer: &mut UnixStream,
+    ) -> io::Result<Option<Value>> {
+        let id = request.get("id").cloned();
+        let Some(method) = request.get("method").and_then(Value::as_str) else {
+            return Ok(Some(error_response(
+                id.unwrap_or(Value::Null),
+                INVALID_REQUEST,
+                "missing method",
+            )));
+        };
+        let empty = Map::new();
+        let params = match request.get("params") {
+            None | Some(Value::Null) => &empty,
+            Some(Value::Object(params)) => params,
+            Some(_) => {
+                return Ok(Some(error_response(
+                    id.unwrap_or(Value::Null),
+                    INVALID_PARAMS,
+                    "params must be an object",
+                )));
+            }
+        };
+
+        let result = match method {
+            "start-job" => self.start_job(params),
+            "cancel-job" => self.cancel_job(params),
+            "status" => self.status(params, writer)?,
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method '{}'", method))),
+        };
+        Ok(id.map(|id| match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err((code, message)) => error_response(id, code, &message),
+        }))
+    }
+
+    fn start_job(&self, params: &Map<String, Value>) -> Result<Value, (i64, String)> {
+        let stats = Arc::new(Stats::new());
+        let organizer = (self.factory)(params, &stats).map_err(|e| (JOB_REJECTED, e))?;
+
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let job = Arc::new(Job {
+            stats: Arc::clone(&stats),
+            terminate_flag: Arc::new(AtomicBool::new(false)),
+            state: Mutex::new((JobState::Running, None)),
+        });
+        self.jobs.lock().unwrap().insert(id, Arc::clone(&job));
+
+        std::thread::spawn(move || {
+            let result = organizer.run(stats, Arc::clone(&job.terminate_flag));
+            let cancelled = job.terminate_flag.load(Ordering::SeqCst);
+            *job.state.lock().unwrap() = match result {
+                Err(e) => (JobState::Failed, Some(e.to_string())),
+                Ok(()) if cancelled => (JobState::Cancelled, None),
+                Ok(()) => (JobState::Finished, None),
+            };
+        });
+        Ok(json!({ "job": id }))
+    }
+
+    fn cancel_job(&self, params: &Map<String, Value>) -> Result<Value, (i64, String)> {
+        let (id, job) = self.job(params)?;
+        job.terminate_flag.store(true, Ordering::SeqCst);
+        Ok(json!({ "job": id, "cancelling": job.is_running() }))
+    }
+
+    fn status(
+        &self,
+        params: &Map<String, Value>,
+        writer: &mut UnixStream,
+    ) -> io::Result<Result<Value, (i64, String)>> {
+        if !params.contains_key("job") {
+            let jobs = self.jobs.lock().unwrap();
+            let statuses: Vec<JobStatus> = jobs.iter().map(|(id, job)| job.status(*id)).collect();
+            return Ok(Ok(json!(statuses)));
+        }
+        let (id, job) = match self.job(params) {
+            Ok(job) => job,
+            Err(e) => return Ok(Err(e)),
+        };
+
+        if params.get("follow").and_then(Value::as_bool) == Some(true) {
+            while job.is_running() {
+                let notification = json!({
+                    "jsonrpc": "2.0",
+                    "method": "progress",
+                    "params": job.status(id),
+                });
+                writeln!(writer, "{}", notification)?;
+                writer.flush()?;
+                std::thread::sleep(FOLLOW_INTERVAL);
+            }
+        }
+        Ok(Ok(json!(job.status(id))))
+    }
+
+    fn job(&self, params: &Map<String, Value>) -> Result<(u64, Arc<Job>), (i64, String)> {
+        let id = params
+            .get("job")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| (INVALID_PARAMS, "expected a job id".to_string()))?;
+        let job = self
+            .jobs
+            .lock()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| (INVALID_PARAMS, format!("no job {}", id)))?;
+        Ok((id, job))
+    }
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
@@ -0,0 +1,83 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Organize settings saved under a name, so a team can share one ingest setup.
+///
+/// Stored as TOML keyed by the long flag names, e.g.
+///
+/// ```toml
+/// destination = "/mnt/archive/weddings"
+/// layout = "{year}/{month_num}-{month_name}"
+/// require-keyword = ["wedding", "ceremony"]
+/// keep-album = true
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    pub flags: toml::Table,
+}
+
+impl Settings {
+    /// Folder named presets are kept in, inside the user's config directory
+    pub fn presets_dir() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("timekeeper").join("presets"))
+    }
+
+    /// File of the preset `name`. Anything that looks like a path (`.toml` extension or
+    /// a folder in it) is used as is, so shared files load without being installed.
+    pub fn path_for(name: &str) -> io::Result<PathBuf> {
+        let path = Path::new(name);
+        if path.extension().is_some_and(|ext| ext == "toml") || path.components().count() > 1 {
+            return Ok(path.to_path_buf());
+        }
+        Self::presets_dir()
+            .map(|dir| dir.join(format!("{}.toml", name)))
+            .ok_or_else(|| io::Error::other("no config directory to keep presets in"))
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let flags = toml::from_str(&text).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })?;
+        Ok(Settings { flags })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = toml::to_string(&self.flags).map_err(io::Error::other)?;
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, format!("# timekeeper settings preset\n{}", text))
+    }
+
+    /// The settings as command line flags, `--flag=value` so values starting with a
+    /// dash (negative coordinates, clock offsets) aren't taken for flags
+    pub fn to_args(&self) -> Result<Vec<String>, String> {
+        let mut args = Vec::new();
+        for (flag, value) in &self.flags {
+            let values = match value {
+                toml::Value::Boolean(true) => {
+                    args.push(format!("--{}", flag));
+                    continue;
+                }
+                toml::Value::Boolean(false) => continue,
+                toml::Value::Array(values) => values.iter().collect(),
+                value => vec![value],
+            };
+            for value in values {
+                let text = match value {
+                    toml::Value::String(text) => text.clone(),
+                    toml::Value::Integer(number) => number.to_string(),
+                    toml::Value::Float(number) => number.to_string(),
+                    _ => return Err(format!("unsupported value for '{}' in preset", flag)),
+                };
+                args.push(format!("--{}={}", flag, text));
+            }
+        }
+        Ok(args)
+    }
+}
@@ -0,0 +1,261 @@
+use ssh2::{CheckResult, ErrorCode, KnownHostFileKind, OpenFlags, OpenType, Session, Sftp};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::net::TcpStream;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::remote::{Attempt, RemoteStore, in_parallel_ranges, with_retries};
+
+/// SFTP status code for a path that doesn't exist
+const NO_SUCH_FILE: i32 = 2;
+
+/// How long a single SSH operation may block before it counts as a dropped connection
+const TIMEOUT_MS: u32 = 30_000;
+
+/// Files larger than this go up in ranges over several connections at once
+const PARALLEL_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// Size of each range of a parallel upload
+const RANGE_SIZE: u64 = 32 * 1024 * 1024;
+
+/// Keys tried, in order, when ssh-agent can't authenticate
+const KEY_FILES: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// A directory on a remote machine reached over SFTP, addressed as
+/// `sftp://[user@]host[:port]/path`. `sftp://host/~/photos` is relative to the
+/// user's home directory. Authenticates with ssh-agent or the default keys in
+/// `~/.ssh` and only connects to hosts listed in `~/.ssh/known_hosts`.
+pub struct SftpStore {
+    root: PathBuf,
+    user: String,
+    host: String,
+    port: u16,
+    base: String,
+    /// Idle connections, one is checked out per concurrent transfer
+    pool: Mutex<Vec<Sftp>>,
+}
+
+impl SftpStore {
+    /// Open `sftp://...`, connecting once up front so bad credentials or an unknown
+    /// host key fail before the run starts
+    pub fn from_url(url: &str) -> io::Result<Self> {
+        let location = url
+            .strip_prefix("sftp://")
+            .ok_or_else(|| io::Error::other(format!("Not an sftp:// URL: '{}'", url)))?;
+        let (authority, path) = location.split_once('/').unwrap_or((location, ""));
+        let (user, host_port) = match authority.rsplit_once('@') {
+            Some((user, host_port)) => (user.to_string(), host_port),
+            None => (
+                std::env::var("USER")
+                    .or_else(|_| std::env::var("USERNAME"))
+                    .map_err(|_| io::Error::other(format!("No user in '{}'", url)))?,
+                authority,
+            ),
+        };
+        let (host, port) = match host_port.split_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .map_err(|_| io::Error::other(format!("Invalid port in '{}'", url)))?,
+            ),
+            None => (host_port, 22),
+        };
+        if host.is_empty() {
+            return Err(io::Error::other(format!("No host in '{}'", url)));
+        }
+
+        let path = path.trim_end_matches('/');
+        let base = match path.strip_prefix('~') {
+            Some(home_relative) => home_relative.trim_start_matches('/').to_string(),
+            None => format!("/{}", path),
+        };
+
+        let store = SftpStore {
+            root: PathBuf::from(url),
+            user,
+            host: host.to_string(),
+            port,
+            base,
+            pool: Mutex::new(Vec::new()),
+        };
+        let sftp = store.connect()?;
+        store.pool.lock().unwrap().push(sftp);
+        Ok(store)
+    }
+
+    fn connect(&self) -> io::Result<Sftp> {
+        let tcp = TcpStream::connect((self.host.as_str(), self.port))?;
+        let mut session = Session::new()?;
+        session.set_tcp_stream(tcp);
+        session.set_timeout(TIMEOUT_MS);
+        session.handshake()?;
+
+        self.check_host_key(&session)?;
+        self.authenticate(&session)?;
+        Ok(session.sftp()?)
+    }
+
+    fn check_host_key(&self, session: &Session) -> io::Result<()> {
+        let (key, _) = session
+            .host_key()
+            .ok_or_else(|| io::Error::other(format!("{} sent no host key", self.host)))?;
+
+        let mut known_hosts = session.known_hosts()?;
+        if let Some(file) = dirs::home_dir().map(|home| home.join(".ssh").join("known_hosts"))
+            && file.exists()
+        {
+            known_hosts.read_file(&file, KnownHostFileKind::OpenSSH)?;
+        }
+
+        match known_hosts.check_port(&self.host, self.port, key) {
+            CheckResult::Match => Ok(()),
+            CheckResult::Mismatch => Err(io::Error::other(format!(
+                "Host key of {} does not match ~/.ssh/known_hosts",
+                self.host
+            ))),
+            CheckResult::NotFound | CheckResult::Failure => Err(io::Error::other(format!(
+                "{} is not in ~/.ssh/known_hosts, connect once with ssh to trust it",
+                self.host
+            ))),
+        }
+    }
+
+    fn authenticate(&self, session: &Session) -> io::Result<()> {
+        if session.userauth_agent(&self.user).is_ok() && session.authenticated() {
+            return Ok(());
+        }
+
+        if let Some(ssh_dir) = dirs::home_dir().map(|home| home.join(".ssh")) {
+            for name in KEY_FILES {
+                let key = ssh_dir.join(name);
+                if key.exists()
+                    && session
+                        .userauth_pubkey_file(&self.user, None, &key, None)
+                        .is_ok()
+                    && session.authenticated()
+                {
+                    return Ok(());
+                }
+            }
+        }
+
+        Err(io::Error::other(format!(
+            "Could not authenticate as {}@{} with ssh-agent or the keys in ~/.ssh",
+            self.user, self.host
+        )))
+    }
+
+    fn remote_path(&self, key: &str) -> PathBuf {
+        if self.base.is_empty() {
+            PathBuf::from(key)
+        } else if key.is_empty() {
+            PathBuf::from(&self.base)
+        } else {
+            PathBuf::from(format!("{}/{}", self.base.trim_end_matches('/'), key))
+        }
+    }
+
+    /// Run `operation` on a pooled connection. A connection that failed at the
+    /// transport level is dropped and the operation retried on a fresh one.
+    fn with_connection<T>(&self, operation: impl Fn(&Sftp) -> Result<T, Attempt>) -> io::Result<T> {
+        with_retries(|| {
+            let pooled = self.pool.lock().unwrap().pop();
+            let sftp = match pooled {
+                Some(sftp) => sftp,
+                None => self.connect().map_err(Attempt::Retry)?,
+            };
+
+            let result = operation(&sftp);
+            if !matches!(result, Err(Attempt::Retry(_))) {
+                self.pool.lock().unwrap().push(sftp);
+            }
+            result
+        })
+    }
+}
+
+/// Errors the server reported are final, anything else means the connection broke
+fn classify(e: ssh2::Error) -> Attempt {
+    match e.code() {
+        ErrorCode::SFTP(_) => Attempt::Fail(e.into()),
+        ErrorCode::Session(_) => Attempt::Retry(e.into()),
+    }
+}
+
+impl RemoteStore for SftpStore {
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn stat(&self, key: &str) -> io::Result<Option<u64>> {
+        let path = self.remote_path(key);
+        self.with_connection(|sftp| match sftp.stat(&path) {
+            Ok(stat) => Ok(stat.size),
+            Err(e) if e.code() == ErrorCode::SFTP(NO_SUCH_FILE) => Ok(None),
+            Err(e) => Err(classify(e)),
+        })
+    }
+
+    /// Upload under a hidden `.part` name and rename into place on the server,
+    /// so an interrupted transfer never leaves a truncated file at the real name.
+    /// Large files are written in ranges over several connections at once, a
+    /// dropped connection only sends its own range again.
+    fn upload(&self, local: &Path, key: &str) -> io::Result<()> {
+        let dest = self.remote_path(key);
+        let name = dest
+            .file_name()
+            .ok_or_else(|| io::Error::other(format!("No file name in '{}'", key)))?;
+        let partial = dest.with_file_name(format!(".{}.part", name.to_string_lossy()));
+
+        let size = fs::metadata(local)?.len();
+        if size <= PARALLEL_THRESHOLD {
+            return self.with_connection(|sftp| {
+                let mut source = File::open(local).map_err(Attempt::Fail)?;
+                let mut remote = sftp.create(&partial).map_err(classify)?;
+                io::copy(&mut source, &mut remote).map_err(Attempt::Retry)?;
+                drop(remote);
+                sftp.rename(&partial, &dest, None).map_err(classify)
+            });
+        }
+
+        self.with_connection(|sftp| sftp.create(&partial).map(drop).map_err(classify))?;
+        in_parallel_ranges(size, RANGE_SIZE, |_, start, len| {
+            self.with_connection(|sftp| {
+                let mut source = File::open(local).map_err(Attempt::Fail)?;
+                source.seek(SeekFrom::Start(start)).map_err(Attempt::Fail)?;
+                let mut remote = sftp
+                    .open_mode(&partial, OpenFlags::WRITE, 0o644, OpenType::File)
+                    .map_err(classify)?;
+                remote
+                    .seek(SeekFrom::Start(start))
+                    .map_err(Attempt::Retry)?;
+                io::copy(&mut source.take(len), &mut remote).map_err(Attempt::Retry)?;
+                Ok(())
+            })
+        })?;
+        self.with_connection(|sftp| sftp.rename(&partial, &dest, None).map_err(classify))
+    }
+
+    fn create_dir_all(&self, key: &str) -> io::Result<()> {
+        let full = self.remote_path(key);
+        self.with_connection(|sftp| {
+            let mut path = PathBuf::new();
+            for component in full.components() {
+                path.push(component);
+                if !matches!(component, Component::Normal(_))
+                    || sftp.stat(&path).is_ok_and(|stat| stat.is_dir())
+                {
+                    continue;
+                }
+                if let Err(e) = sftp.mkdir(&path, 0o755) {
+                    // Another transfer may have created it in the meantime
+                    if !sftp.stat(&path).is_ok_and(|stat| stat.is_dir()) {
+                        return Err(classify(e));
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+}
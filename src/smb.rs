@@ -0,0 +1,133 @@
+use std::path::{Path, PathBuf};
+
+/// Paths to rename between when `source` and `dest` sit on the same SMB share but
+/// reach it through different mounts (or mapped drives), which makes a plain
+/// rename fail as cross-device. Renaming within one view of the share lets the
+/// server move the file instead of copying it over the network.
+pub fn same_share_rename(source: &Path, dest: &Path) -> Option<(PathBuf, PathBuf)> {
+    let source = source.canonicalize().ok()?;
+    let dest = dest.parent()?.canonicalize().ok()?.join(dest.file_name()?);
+
+    // Windows resolves mapped drives to `\\?\UNC\server\share\...`, and the server
+    // can rename between two UNC paths on the same share
+    if let (Some(source_share), Some(dest_share)) = (unc_share(&source), unc_share(&dest)) {
+        return source_share
+            .eq_ignore_ascii_case(&dest_share)
+            .then_some((source, dest));
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        let (source_mount, _) = mounts::locate(&source)?;
+        let (dest_mount, dest_on_share) = mounts::locate(&dest)?;
+        if source_mount.share != dest_mount.share
+            || source_mount.mount_point == dest_mount.mount_point
+        {
+            return None;
+        }
+
+        // Express the destination through the source's mount
+        let relative = dest_on_share.strip_prefix(&source_mount.remote).ok()?;
+        Some((source, source_mount.mount_point.join(relative)))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    None
+}
+
+/// `server\share` of a verbatim UNC path
+fn unc_share(path: &Path) -> Option<String> {
+    use std::path::{Component, Prefix};
+
+    match path.components().next()? {
+        Component::Prefix(prefix) => match prefix.kind() {
+            Prefix::VerbatimUNC(server, share) | Prefix::UNC(server, share) => Some(format!(
+                "{}\\{}",
+                server.to_string_lossy(),
+                share.to_string_lossy()
+            )),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod mounts {
+    use std::path::{Path, PathBuf};
+
+    /// A CIFS/SMB mount from `/proc/self/mountinfo`
+    pub struct SmbMount {
+        pub mount_point: PathBuf,
+        /// `//server/share`, lowercased as SMB names are case-insensitive
+        pub share: String,
+        /// Directory on the share shown at the mount point
+        pub remote: PathBuf,
+    }
+
+    lazy_static::lazy_static! {
+        static ref SMB_MOUNTS: Vec<SmbMount> = std::fs::read_to_string("/proc/self/mountinfo")
+            .map(|info| info.lines().filter_map(parse_mountinfo_line).collect())
+            .unwrap_or_default();
+    }
+
+    /// The SMB mount holding `path` and the path's location on the share
+    pub fn locate(path: &Path) -> Option<(&'static SmbMount, PathBuf)> {
+        let mount = SMB_MOUNTS
+            .iter()
+            .filter(|mount| path.starts_with(&mount.mount_point))
+            .max_by_key(|mount| mount.mount_point.components().count())?;
+        let relative = path.strip_prefix(&mount.mount_point).ok()?;
+        Some((mount, mount.remote.join(relative)))
+    }
+
+    fn parse_mountinfo_line(line: &str) -> Option<SmbMount> {
+        let (mount_fields, fs_fields) = line.split_once(" - ")?;
+        let mut mount_fields = mount_fields.split(' ');
+        let root = unescape(mount_fields.nth(3)?);
+        let mount_point = unescape(mount_fields.next()?);
+
+        let mut fs_fields = fs_fields.split(' ');
+        let fs_type = fs_fields.next()?;
+        if !matches!(fs_type, "cifs" | "smb3" | "smbfs") {
+            return None;
+        }
+
+        // `//server/share/optional/prefix`
+        let source = unescape(fs_fields.next()?).replace('\\', "/");
+        let mut parts = source.trim_start_matches('/').splitn(3, '/');
+        let server = parts.next()?;
+        let share = parts.next()?;
+        let prefix = parts.next().unwrap_or("");
+
+        let mut remote = PathBuf::from("/").join(prefix);
+        remote.push(root.trim_start_matches('/'));
+
+        Some(SmbMount {
+            mount_point: PathBuf::from(mount_point),
+            share: format!("//{}/{}", server, share).to_lowercase(),
+            remote,
+        })
+    }
+
+    /// Undo mountinfo's octal escapes (`\040` for a space)
+    fn unescape(field: &str) -> String {
+        let mut out = Vec::with_capacity(field.len());
+        let bytes = field.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'\\'
+                && let Some(code) = field
+                    .get(i + 1..i + 4)
+                    .and_then(|digits| u8::from_str_radix(digits, 8).ok())
+            {
+                out.push(code);
+                i += 4;
+            } else {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    }
+}
@@ -0,0 +1,128 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::photos;
+
+/// Turn a `--source` into a local path the walker can read. Plain paths are returned
+/// as they are, except a macOS Photos library, which resolves to the folder of its
+/// originals; `mtp://Device/path` resolves to the device's GVFS mount so phones and
+/// cameras that don't mount as mass storage can be organized without copying first.
+pub fn resolve_source(source: &Path) -> io::Result<PathBuf> {
+    let Some((scheme, location)) = source.to_str().and_then(|s| s.split_once("://")) else {
+        return Ok(photos::library_originals(source).unwrap_or_else(|| source.to_path_buf()));
+    };
+
+    match scheme {
+        "mtp" => resolve_mtp(location),
+        _ => Err(io::Error::other(format!(
+            "Unsupported source '{}://'",
+            scheme
+        ))),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resolve_mtp(_location: &str) -> io::Result<PathBuf> {
+    Err(io::Error::other(
+        "mtp:// sources need GVFS and are only supported on Linux",
+    ))
+}
+
+/// Find `Device/path` below the GVFS MTP mounts, mounting the device with `gio`
+/// first when it isn't mounted yet. The device name is matched loosely
+/// (`Pixel7` finds `Google_Pixel_7_1A2B3C`), and the path may leave out the
+/// storage name (`Internal shared storage`).
+#[cfg(target_os = "linux")]
+fn resolve_mtp(location: &str) -> io::Result<PathBuf> {
+    let (device, path) = location.split_once('/').unwrap_or((location, ""));
+    let device_mount = match find_mtp_mount(device)? {
+        Some(mount) => mount,
+        None => {
+            mount_mtp_device(device)?;
+            find_mtp_mount(device)?.ok_or_else(|| {
+                io::Error::other(format!("MTP device '{}' is not connected", device))
+            })?
+        }
+    };
+
+    let path = path.trim_matches('/');
+    if path.is_empty() || device_mount.join(path).exists() {
+        return Ok(device_mount.join(path));
+    }
+
+    // Look for the path inside each storage of the device
+    for storage in std::fs::read_dir(&device_mount)?.flatten() {
+        let candidate = storage.path().join(path);
+        if candidate.exists() {
+            return Ok(candidate);
+        }
+    }
+    Err(io::Error::other(format!(
+        "'{}' not found on MTP device '{}'",
+        path, device
+    )))
+}
+
+/// Lowercase alphanumerics only, so device names can be compared loosely
+#[cfg(target_os = "linux")]
+fn normalize_device_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn gvfs_dir() -> Option<PathBuf> {
+    dirs::runtime_dir().map(|dir| dir.join("gvfs"))
+}
+
+#[cfg(target_os = "linux")]
+fn find_mtp_mount(device: &str) -> io::Result<Option<PathBuf>> {
+    let Some(gvfs) = gvfs_dir().filter(|dir| dir.is_dir()) else {
+        return Ok(None);
+    };
+    let wanted = normalize_device_name(device);
+
+    for entry in std::fs::read_dir(gvfs)?.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if let Some(host) = name.strip_prefix("mtp:host=")
+            && normalize_device_name(host).contains(&wanted)
+        {
+            return Ok(Some(entry.path()));
+        }
+    }
+    Ok(None)
+}
+
+/// Ask GVFS to mount the matching MTP device through `gio mount`
+#[cfg(target_os = "linux")]
+fn mount_mtp_device(device: &str) -> io::Result<()> {
+    let not_found = || io::Error::other(format!("MTP device '{}' is not connected", device));
+
+    let listing = std::process::Command::new("gio")
+        .args(["mount", "-li"])
+        .output()
+        .map_err(|e| io::Error::other(format!("Cannot run gio to mount MTP devices: {}", e)))?;
+    let wanted = normalize_device_name(device);
+    let activation_root = String::from_utf8_lossy(&listing.stdout)
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("activation_root="))
+        .find(|root| {
+            root.strip_prefix("mtp://")
+                .is_some_and(|id| normalize_device_name(id).contains(&wanted))
+        })
+        .map(str::to_string)
+        .ok_or_else(not_found)?;
+
+    let status = std::process::Command::new("gio")
+        .args(["mount", &activation_root])
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "gio could not mount {}",
+            activation_root
+        )));
+    }
+    Ok(())
+}
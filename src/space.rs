@@ -0,0 +1,37 @@
+use std::path::Path;
+
+/// Free space available to the current user on the volume holding `path`.
+/// The destination usually doesn't exist before the first run, so the
+/// nearest existing ancestor is queried instead.
+pub fn available_space(path: &Path) -> std::io::Result<u64> {
+    let mut candidate = path;
+    while !candidate.exists() {
+        match candidate.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => candidate = parent,
+            _ => {
+                candidate = Path::new(".");
+                break;
+            }
+        }
+    }
+
+    fs4::available_space(candidate)
+}
+
+/// Human readable byte count, e.g. `1.5 GB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
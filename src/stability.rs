@@ -0,0 +1,62 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Check whether a file has been left alone long enough to be processed safely.
+/// Cameras and sync clients create files before they finish writing them, so a
+/// file is only considered settled when:
+/// - its mtime is at least `min_age` in the past
+/// - its size and mtime don't change during `stable_for`. A file last modified
+///   longer ago than that has already shown it, only younger ones are waited on.
+/// - on Windows, no other process holds it open for writing
+pub fn is_file_settled(
+    path: &Path,
+    min_age: Option<Duration>,
+    stable_for: Option<Duration>,
+) -> std::io::Result<bool> {
+    let before = fs::metadata(path)?;
+    let age = SystemTime::now()
+        .duration_since(before.modified()?)
+        .unwrap_or(Duration::ZERO);
+
+    if min_age.is_some_and(|min_age| age < min_age) {
+        return Ok(false);
+    }
+
+    if let Some(stable_for) = stable_for
+        && age < stable_for
+        && changes_within(path, &before, stable_for - age)?
+    {
+        return Ok(false);
+    }
+
+    #[cfg(windows)]
+    {
+        if stable_for.is_some() && is_locked_by_writer(path) {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Whether the file's size or mtime moves on from `before` within `wait`
+fn changes_within(path: &Path, before: &fs::Metadata, wait: Duration) -> std::io::Result<bool> {
+    std::thread::sleep(wait);
+    let after = fs::metadata(path)?;
+    Ok(before.len() != after.len() || before.modified()? != after.modified()?)
+}
+
+/// Probe for an exclusive handle, writers on Windows keep files open without sharing
+#[cfg(windows)]
+fn is_locked_by_writer(path: &Path) -> bool {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    // ERROR_SHARING_VIOLATION
+    const SHARING_VIOLATION: i32 = 32;
+
+    match fs::OpenOptions::new().read(true).share_mode(0).open(path) {
+        Ok(_) => false,
+        Err(e) => e.raw_os_error() == Some(SHARING_VIOLATION),
+    }
+}
@@ -1,12 +1,89 @@
-use std::sync::atomic::{AtomicUsize, Ordering};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use crate::error::ErrorCategory;
+use crate::space::format_bytes;
+
+/// Number of most recent error messages kept for status reporting
+const RECENT_ERRORS: usize = 20;
 
 pub struct Stats {
     pub total: AtomicUsize,
     pub processed: AtomicUsize,
     pub exif_count: AtomicUsize,
     pub fallback_count: AtomicUsize,
+    /// Fallback files that carry no date tags at all
+    pub no_date: AtomicUsize,
+    /// Fallback files exiftool could not read
+    pub extraction_failed: AtomicUsize,
     pub skipped: AtomicUsize,
+    /// Skipped files that are online-only cloud placeholders
+    pub cloud_placeholders: AtomicUsize,
+    pub received: AtomicUsize,
+    /// Files given a suffix because another file of the run or one already at the
+    /// destination has their name
+    pub name_conflicts: AtomicUsize,
     pub errors: AtomicUsize,
+    /// `errors` by `ErrorCategory`, in the order of its variants
+    error_categories: [AtomicUsize; 5],
+    /// Combined size of the media files found in the source
+    pub bytes_total: AtomicU64,
+    pub bytes_transferred: AtomicU64,
+    recent_errors: Mutex<VecDeque<String>>,
+}
+
+/// Point-in-time copy of `Stats`, for serializing while a run is in progress
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsSnapshot {
+    pub total: usize,
+    pub processed: usize,
+    pub exif_count: usize,
+    pub fallback_count: usize,
+    pub no_date: usize,
+    pub extraction_failed: usize,
+    pub skipped: usize,
+    pub cloud_placeholders: usize,
+    pub received: usize,
+    pub name_conflicts: usize,
+    pub errors: usize,
+    pub error_categories: ErrorCounts,
+    pub bytes_total: u64,
+    pub bytes_transferred: u64,
+    pub recent_errors: Vec<String>,
+}
+
+/// How many errors fell into each `ErrorCategory`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ErrorCounts {
+    pub metadata: usize,
+    pub io: usize,
+    pub permission_denied: usize,
+    pub timeout: usize,
+    pub other: usize,
+}
+
+impl ErrorCounts {
+    /// The non-zero counts with a label each, in a fixed order
+    pub fn labeled(&self) -> Vec<(&'static str, usize)> {
+        [
+            ("Metadata extraction", self.metadata),
+            ("Disk or transfer (IO)", self.io),
+            ("Permission denied", self.permission_denied),
+            ("Timed out", self.timeout),
+            ("Other", self.other),
+        ]
+        .into_iter()
+        .filter(|(_, count)| *count > 0)
+        .collect()
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Stats {
@@ -16,8 +93,98 @@ impl Stats {
             processed: AtomicUsize::new(0),
             exif_count: AtomicUsize::new(0),
             fallback_count: AtomicUsize::new(0),
+            no_date: AtomicUsize::new(0),
+            extraction_failed: AtomicUsize::new(0),
             skipped: AtomicUsize::new(0),
+            cloud_placeholders: AtomicUsize::new(0),
+            received: AtomicUsize::new(0),
+            name_conflicts: AtomicUsize::new(0),
             errors: AtomicUsize::new(0),
+            error_categories: Default::default(),
+            bytes_total: AtomicU64::new(0),
+            bytes_transferred: AtomicU64::new(0),
+            recent_errors: Mutex::new(VecDeque::with_capacity(RECENT_ERRORS)),
+        }
+    }
+
+    /// Zero every counter, used between scheduled runs sharing one `Stats`
+    pub fn reset(&self) {
+        for counter in [
+            &self.total,
+            &self.processed,
+            &self.exif_count,
+            &self.fallback_count,
+            &self.no_date,
+            &self.extraction_failed,
+            &self.skipped,
+            &self.cloud_placeholders,
+            &self.received,
+            &self.name_conflicts,
+            &self.errors,
+        ]
+        .into_iter()
+        .chain(&self.error_categories)
+        {
+            counter.store(0, Ordering::SeqCst);
+        }
+        self.bytes_total.store(0, Ordering::SeqCst);
+        self.bytes_transferred.store(0, Ordering::SeqCst);
+        self.recent_errors.lock().unwrap().clear();
+    }
+
+    /// Count a failed file under its category and remember its message for status
+    /// reporting
+    pub fn record_error(&self, category: ErrorCategory, message: String) {
+        self.errors.fetch_add(1, Ordering::SeqCst);
+        self.error_categories[category as usize].fetch_add(1, Ordering::SeqCst);
+        self.remember_error(message);
+    }
+
+    /// Count a file whose metadata couldn't be read and remember why. The file itself
+    /// is still placed by its fallback date, so it isn't counted as an error.
+    pub fn record_extraction_failure(&self, message: String) {
+        self.extraction_failed.fetch_add(1, Ordering::SeqCst);
+        self.remember_error(message);
+    }
+
+    fn remember_error(&self, message: String) {
+        let mut recent = self.recent_errors.lock().unwrap();
+        if recent.len() == RECENT_ERRORS {
+            recent.pop_front();
+        }
+        recent.push_back(message);
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            total: self.total.load(Ordering::SeqCst),
+            processed: self.processed.load(Ordering::SeqCst),
+            exif_count: self.exif_count.load(Ordering::SeqCst),
+            fallback_count: self.fallback_count.load(Ordering::SeqCst),
+            no_date: self.no_date.load(Ordering::SeqCst),
+            extraction_failed: self.extraction_failed.load(Ordering::SeqCst),
+            skipped: self.skipped.load(Ordering::SeqCst),
+            cloud_placeholders: self.cloud_placeholders.load(Ordering::SeqCst),
+            received: self.received.load(Ordering::SeqCst),
+            name_conflicts: self.name_conflicts.load(Ordering::SeqCst),
+            errors: self.errors.load(Ordering::SeqCst),
+            error_categories: self.error_counts(),
+            bytes_total: self.bytes_total.load(Ordering::SeqCst),
+            bytes_transferred: self.bytes_transferred.load(Ordering::SeqCst),
+            recent_errors: self.recent_errors.lock().unwrap().iter().cloned().collect(),
+        }
+    }
+
+    fn error_counts(&self) -> ErrorCounts {
+        let count = |category: ErrorCategory| {
+            self.error_categories[category as usize].load(Ordering::SeqCst)
+        };
+        ErrorCounts {
+            metadata: count(ErrorCategory::Metadata),
+            io: count(ErrorCategory::Io),
+            permission_denied: count(ErrorCategory::PermissionDenied),
+            timeout: count(ErrorCategory::Timeout),
+            other: count(ErrorCategory::Other),
         }
     }
 
@@ -26,13 +193,23 @@ impl Stats {
         let processed = self.processed.load(Ordering::SeqCst);
         let exif_count = self.exif_count.load(Ordering::SeqCst);
         let fallback_count = self.fallback_count.load(Ordering::SeqCst);
+        let no_date = self.no_date.load(Ordering::SeqCst);
+        let extraction_failed = self.extraction_failed.load(Ordering::SeqCst);
         let skipped = self.skipped.load(Ordering::SeqCst);
+        let cloud_placeholders = self.cloud_placeholders.load(Ordering::SeqCst);
+        let received = self.received.load(Ordering::SeqCst);
+        let name_conflicts = self.name_conflicts.load(Ordering::SeqCst);
         let errors = self.errors.load(Ordering::SeqCst);
+        let bytes_total = self.bytes_total.load(Ordering::SeqCst);
+        let bytes_transferred = self.bytes_transferred.load(Ordering::SeqCst);
 
         println!("\n=== SUMMARY ===");
         println!("Total files: {}", total);
         println!("Successfully processed: {}", processed);
         println!("Skipped: {}", skipped);
+        if cloud_placeholders > 0 {
+            println!("  - Online-only in the cloud: {}", cloud_placeholders);
+        }
 
         if processed > 0 {
             let exif_percentage = (exif_count as f64 / processed as f64) * 100.0;
@@ -46,8 +223,66 @@ impl Stats {
                 "  - Using fallback (ModTime): {} ({:.1}%)",
                 fallback_count, fallback_percentage
             );
+            if fallback_count > 0 {
+                println!("    - No date tags: {}", no_date);
+                println!("    - Metadata unreadable: {}", extraction_failed);
+            }
         }
 
+        if received > 0 {
+            println!("Received via messaging apps: {}", received);
+        }
+        if name_conflicts > 0 {
+            println!("Renamed to avoid a name clash: {}", name_conflicts);
+        }
+
+        println!(
+            "Data transferred: {} of {}",
+            format_bytes(bytes_transferred),
+            format_bytes(bytes_total)
+        );
         println!("Errors: {}", errors);
+        for (label, count) in self.error_counts().labeled() {
+            println!("  - {}: {}", label, count);
+        }
+    }
+
+    /// One-line progress by file count and by bytes, e.g.
+    /// `120/5000 files (2.4%), 3.2 GB/45.0 GB (7.1%)`
+    pub fn progress_line(&self) -> String {
+        let snapshot = self.snapshot();
+        format!(
+            "{}/{} files ({:.1}%), {}/{} ({:.1}%)",
+            snapshot.files_done(),
+            snapshot.total,
+            snapshot.progress_percent(),
+            format_bytes(snapshot.bytes_transferred),
+            format_bytes(snapshot.bytes_total),
+            snapshot.bytes_progress_percent()
+        )
+    }
+}
+
+impl StatsSnapshot {
+    /// Files that went through the run, whatever their outcome
+    pub fn files_done(&self) -> usize {
+        (self.processed + self.skipped).min(self.total)
+    }
+
+    pub fn progress_percent(&self) -> f64 {
+        percent(self.files_done() as u64, self.total as u64)
+    }
+
+    /// Progress by size, which a few huge videos can't skew like the file count
+    pub fn bytes_progress_percent(&self) -> f64 {
+        percent(self.bytes_transferred, self.bytes_total)
+    }
+}
+
+fn percent(done: u64, total: u64) -> f64 {
+    if total > 0 {
+        (done.min(total) as f64 / total as f64) * 100.0
+    } else {
+        0.0
     }
 }
@@ -0,0 +1,188 @@
+use serde::Serialize;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+
+use crate::stats::{Stats, StatsSnapshot};
+
+#[derive(Serialize)]
+struct StatusReport {
+    progress_percent: f64,
+    bytes_progress_percent: f64,
+    stats: StatsSnapshot,
+}
+
+/// Serve a read-only status of the run on `addr` from a background thread.
+/// `GET /` or `GET /status` returns the current stats and the most recent errors as JSON,
+/// `GET /metrics` returns the same counters in Prometheus text format.
+/// The listener lives until the process exits.
+pub fn serve_status(addr: SocketAddr, stats: Arc<Stats>) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // A misbehaving client must never take the run down with it
+            let _ = handle_connection(stream, &stats);
+        }
+    });
+
+    Ok(())
+}
+
+fn handle_connection(mut stream: TcpStream, stats: &Stats) -> std::io::Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("");
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/") | ("GET", "/status") => (
+            "200 OK",
+            "application/json",
+            status_json(stats).map_err(std::io::Error::other)?,
+        ),
+        ("GET", "/metrics") => (
+            "200 OK",
+            "text/plain; version=0.0.4",
+            prometheus_metrics(stats),
+        ),
+        ("GET", _) => ("404 Not Found", "text/plain", "Not Found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Method Not Allowed\n".to_string(),
+        ),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+fn status_json(stats: &Stats) -> serde_json::Result<String> {
+    let snapshot = stats.snapshot();
+    serde_json::to_string_pretty(&StatusReport {
+        progress_percent: snapshot.progress_percent(),
+        bytes_progress_percent: snapshot.bytes_progress_percent(),
+        stats: snapshot,
+    })
+}
+
+/// Render the stats in the Prometheus text exposition format
+fn prometheus_metrics(stats: &Stats) -> String {
+    let snapshot = stats.snapshot();
+    let finished = snapshot.processed + snapshot.skipped + snapshot.errors;
+    let queue_depth = snapshot.total.saturating_sub(finished);
+
+    let metrics: [(&str, &str, &str, u64); 13] = [
+        (
+            "timekeeper_files_discovered",
+            "gauge",
+            "Media files found in the source",
+            snapshot.total as u64,
+        ),
+        (
+            "timekeeper_files_processed_total",
+            "counter",
+            "Files processed",
+            snapshot.processed as u64,
+        ),
+        (
+            "timekeeper_files_exif_total",
+            "counter",
+            "Files dated from EXIF metadata",
+            snapshot.exif_count as u64,
+        ),
+        (
+            "timekeeper_files_fallback_total",
+            "counter",
+            "Files dated from the fallback date",
+            snapshot.fallback_count as u64,
+        ),
+        (
+            "timekeeper_files_no_date_total",
+            "counter",
+            "Fallback files without any date tags",
+            snapshot.no_date as u64,
+        ),
+        (
+            "timekeeper_files_extraction_failed_total",
+            "counter",
+            "Fallback files whose metadata could not be read",
+            snapshot.extraction_failed as u64,
+        ),
+        (
+            "timekeeper_files_skipped_total",
+            "counter",
+            "Files skipped",
+            snapshot.skipped as u64,
+        ),
+        (
+            "timekeeper_files_cloud_placeholder_total",
+            "counter",
+            "Files skipped as online-only cloud placeholders",
+            snapshot.cloud_placeholders as u64,
+        ),
+        (
+            "timekeeper_files_received_total",
+            "counter",
+            "Files received through messaging apps",
+            snapshot.received as u64,
+        ),
+        (
+            "timekeeper_errors_total",
+            "counter",
+            "Files that failed to process",
+            snapshot.errors as u64,
+        ),
+        (
+            "timekeeper_bytes_discovered",
+            "gauge",
+            "Combined size of the media files found in the source",
+            snapshot.bytes_total,
+        ),
+        (
+            "timekeeper_bytes_transferred_total",
+            "counter",
+            "Bytes copied or moved into the destination",
+            snapshot.bytes_transferred,
+        ),
+        (
+            "timekeeper_queue_depth",
+            "gauge",
+            "Files still waiting to be processed",
+            queue_depth as u64,
+        ),
+    ];
+
+    let mut out = String::new();
+    for (name, kind, help, value) in metrics {
+        out.push_str(&format!(
+            "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n"
+        ));
+    }
+
+    let name = "timekeeper_errors_by_category_total";
+    out.push_str(&format!(
+        "# HELP {name} Files that failed to process, by likely cause\n# TYPE {name} counter\n"
+    ));
+    let categories = &snapshot.error_categories;
+    for (category, value) in [
+        ("metadata", categories.metadata),
+        ("io", categories.io),
+        ("permission_denied", categories.permission_denied),
+        ("timeout", categories.timeout),
+        ("other", categories.other),
+    ] {
+        out.push_str(&format!("{name}{{category=\"{category}\"}} {value}\n"));
+    }
+    out
+}
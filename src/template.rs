@@ -0,0 +1,466 @@
+use chrono::{DateTime, Datelike, Local, Timelike};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::layout::{FileMeta, Layout};
+
+/// A destination template such as `{year}/{month:02}` or `{date}_{camera|lower}`.
+///
+/// Tokens are written `{name}`, optionally followed by a zero-padding width
+/// (`{month:02}`) and modifiers applied left to right
+/// (`{camera|lower|replace(' ','_')}`). Used as a layout, every `/` starts a folder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Token {
+        token: Token,
+        width: Option<usize>,
+        modifiers: Vec<Modifier>,
+    },
+}
+
+/// Values a template can use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Decade,
+    Year,
+    Month,
+    MonthName,
+    Quarter,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    /// Milliseconds, telling apart burst shots taken within the same second
+    Subsec,
+    Date,
+    Camera,
+    Serial,
+    Lens,
+    FocalLength,
+    Orientation,
+    Rating,
+    Keywords,
+    OrigStem,
+    Ext,
+    Seq,
+    Parent,
+}
+
+impl Token {
+    fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "decade" => Token::Decade,
+            "year" => Token::Year,
+            "month" => Token::Month,
+            "month_name" => Token::MonthName,
+            "quarter" => Token::Quarter,
+            "day" => Token::Day,
+            "hour" => Token::Hour,
+            "minute" => Token::Minute,
+            "second" => Token::Second,
+            "subsec" => Token::Subsec,
+            "date" => Token::Date,
+            "camera" => Token::Camera,
+            "serial" => Token::Serial,
+            "lens" => Token::Lens,
+            "focal_length" => Token::FocalLength,
+            "orientation" => Token::Orientation,
+            "rating" => Token::Rating,
+            "keywords" => Token::Keywords,
+            "orig_stem" => Token::OrigStem,
+            "ext" => Token::Ext,
+            "seq" => Token::Seq,
+            "parent" => Token::Parent,
+            _ => return Err(format!("unknown template token '{{{}}}'", name)),
+        })
+    }
+
+    /// The token's value for a file, empty when the file doesn't have it
+    fn value(&self, meta: &FileMeta) -> String {
+        let datetime = &meta.datetime;
+        match self {
+            Token::Decade => decade(datetime),
+            Token::Year => datetime.year().to_string(),
+            Token::Month => datetime.month().to_string(),
+            Token::MonthName => datetime.format("%B").to_string(),
+            Token::Quarter => quarter(datetime).to_string(),
+            Token::Day => datetime.day().to_string(),
+            Token::Hour => datetime.hour().to_string(),
+            Token::Minute => datetime.minute().to_string(),
+            Token::Second => datetime.second().to_string(),
+            Token::Subsec => (datetime.nanosecond() / 1_000_000).to_string(),
+            Token::Date => datetime.format("%Y-%m-%d").to_string(),
+            Token::Camera => meta.camera().unwrap_or_default().to_string(),
+            Token::Serial => meta.serial().unwrap_or_default().to_string(),
+            Token::Lens => meta.lens().unwrap_or_default().to_string(),
+            // Millimeters without the unit, so `{focal_length:03}mm` sorts
+            Token::FocalLength => meta
+                .focal_length()
+                .map(|mm| mm.to_string())
+                .unwrap_or_default(),
+            Token::Rating => meta
+                .rating()
+                .map(|rating| rating.to_string())
+                .unwrap_or_default(),
+            // Several keywords stay one folder or name
+            Token::Keywords => meta.keywords().join(", "),
+            Token::Orientation => meta
+                .orientation()
+                .map(|orientation| orientation.to_string())
+                .unwrap_or_default(),
+            Token::OrigStem => meta
+                .original_name()
+                .as_deref()
+                .and_then(|name| Path::new(name).file_stem())
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            Token::Ext => meta
+                .source
+                .extension()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            Token::Seq => meta.seq.map(|seq| seq.to_string()).unwrap_or_default(),
+            Token::Parent => meta.parent_name().unwrap_or_default(),
+        }
+    }
+
+    /// Padding used when the template doesn't give one
+    fn default_width(&self) -> Option<usize> {
+        match self {
+            Token::Seq | Token::Subsec => Some(3),
+            _ => None,
+        }
+    }
+}
+
+/// Transformations applied to a token's value
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Modifier {
+    Lower,
+    Upper,
+    Trim,
+    /// Keep at most this many characters
+    Trunc(usize),
+    Replace(String, String),
+    /// Value used when the token is empty
+    Default(String),
+}
+
+impl Modifier {
+    fn parse(name: &str, args: Vec<String>) -> Result<Self, String> {
+        let count = |n: usize| {
+            if args.len() == n {
+                Ok(())
+            } else {
+                Err(format!(
+                    "modifier '{}' takes {} argument{}",
+                    name,
+                    n,
+                    if n == 1 { "" } else { "s" }
+                ))
+            }
+        };
+        match name {
+            "lower" => count(0).map(|()| Modifier::Lower),
+            "upper" => count(0).map(|()| Modifier::Upper),
+            "trim" => count(0).map(|()| Modifier::Trim),
+            "trunc" => {
+                count(1)?;
+                args[0]
+                    .parse()
+                    .map(Modifier::Trunc)
+                    .map_err(|_| format!("trunc needs a number, got '{}'", args[0]))
+            }
+            "replace" => {
+                count(2)?;
+                Ok(Modifier::Replace(args[0].clone(), args[1].clone()))
+            }
+            "default" => {
+                count(1)?;
+                Ok(Modifier::Default(args[0].clone()))
+            }
+            _ => Err(format!("unknown template modifier '{}'", name)),
+        }
+    }
+
+    fn apply(&self, value: String) -> String {
+        match self {
+            Modifier::Lower => value.to_lowercase(),
+            Modifier::Upper => value.to_uppercase(),
+            Modifier::Trim => value.trim().to_string(),
+            Modifier::Trunc(n) => value.chars().take(*n).collect(),
+            Modifier::Replace(from, to) => value.replace(from.as_str(), to),
+            Modifier::Default(default) if value.is_empty() => default.clone(),
+            Modifier::Default(_) => value,
+        }
+    }
+}
+
+impl std::str::FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' => {
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    let mut body = String::new();
+                    let mut quote = None;
+                    loop {
+                        match chars.next() {
+                            None => return Err(format!("unclosed '{{' in template '{}'", s)),
+                            Some('}') if quote.is_none() => break,
+                            Some(c @ ('\'' | '"')) if quote.is_none() => {
+                                quote = Some(c);
+                                body.push(c);
+                            }
+                            Some(c) if Some(c) == quote => {
+                                quote = None;
+                                body.push(c);
+                            }
+                            Some(c) => body.push(c),
+                        }
+                    }
+                    segments.push(parse_token(&body)?);
+                }
+                '}' => return Err(format!("unmatched '}}' in template '{}'", s)),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Template {
+            source: s.to_string(),
+            segments,
+        })
+    }
+}
+
+/// Parse the inside of `{...}`: `name[:0N][|modifier[(args)]]...`
+fn parse_token(body: &str) -> Result<Segment, String> {
+    let mut parts = split_outside_quotes(body, '|').into_iter();
+    let head = parts.next().unwrap_or_default();
+    let (name, width) = match head.split_once(':') {
+        Some((name, spec)) => {
+            let width = spec
+                .strip_prefix('0')
+                .and_then(|w| w.parse().ok())
+                .ok_or_else(|| {
+                    format!("bad width ':{}' for '{}', expected e.g. :02", spec, name)
+                })?;
+            (name, Some(width))
+        }
+        None => (head.as_str(), None),
+    };
+    let token = Token::parse(name.trim())?;
+
+    let modifiers = parts
+        .map(|part| {
+            let part = part.trim();
+            match part.split_once('(') {
+                Some((name, rest)) => {
+                    let args = rest
+                        .strip_suffix(')')
+                        .ok_or_else(|| format!("missing ')' in modifier '{}'", part))?;
+                    let args = split_outside_quotes(args, ',')
+                        .iter()
+                        .map(|arg| unquote(arg.trim()))
+                        .collect();
+                    Modifier::parse(name.trim(), args)
+                }
+                None => Modifier::parse(part, Vec::new()),
+            }
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(Segment::Token {
+        token,
+        width,
+        modifiers,
+    })
+}
+
+fn split_outside_quotes(s: &str, separator: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut quote = None;
+    for c in s.chars() {
+        match c {
+            '\'' | '"' if quote.is_none() => quote = Some(c),
+            c if Some(c) == quote => quote = None,
+            c if c == separator && quote.is_none() => {
+                parts.push(String::new());
+                continue;
+            }
+            _ => {}
+        }
+        parts.last_mut().unwrap().push(c);
+    }
+    parts
+}
+
+fn unquote(arg: &str) -> String {
+    for quote in ['\'', '"'] {
+        if let Some(inner) = arg.strip_prefix(quote).and_then(|a| a.strip_suffix(quote)) {
+            return inner.to_string();
+        }
+    }
+    arg.to_string()
+}
+
+impl Template {
+    /// Fill in the template for a file. Token values never add folders: a `/` in a
+    /// value (such as a camera model) becomes `-`.
+    pub fn render(&self, meta: &FileMeta) -> String {
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => rendered.push_str(text),
+                Segment::Token {
+                    token,
+                    width,
+                    modifiers,
+                } => {
+                    let mut value = token.value(meta);
+                    // A missing value stays empty so `default(...)` still applies
+                    if let Some(width) = width.or(token.default_width())
+                        && !value.is_empty()
+                    {
+                        value = format!("{:0>width$}", value, width = width);
+                    }
+                    let value = modifiers
+                        .iter()
+                        .fold(value, |value, modifier| modifier.apply(value));
+                    rendered.push_str(&value.replace(['/', '\\'], "-"));
+                }
+            }
+        }
+        rendered
+    }
+}
+
+/// Decade folder name such as `1990s`
+pub(crate) fn decade(datetime: &DateTime<Local>) -> String {
+    format!("{}s", datetime.year().div_euclid(10) * 10)
+}
+
+/// Calendar quarter, 1 to 4
+pub(crate) fn quarter(datetime: &DateTime<Local>) -> u32 {
+    datetime.month0() / 3 + 1
+}
+
+impl Template {
+    /// Whether the template numbers files with `{seq}`
+    pub fn uses_sequence(&self) -> bool {
+        self.segments.iter().any(|segment| {
+            matches!(
+                segment,
+                Segment::Token {
+                    token: Token::Seq,
+                    ..
+                }
+            )
+        })
+    }
+}
+
+impl std::fmt::Display for Template {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Layout for Template {
+    fn dest_for(&self, meta: &FileMeta) -> PathBuf {
+        // Empty folder names (a token without a value) are left out
+        self.render(meta)
+            .split('/')
+            .filter(|folder| !folder.trim().is_empty())
+            .collect()
+    }
+
+    fn depth(&self) -> usize {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.matches('/').count(),
+                Segment::Token { .. } => 0,
+            })
+            .sum::<usize>()
+            + 1
+    }
+}
+
+/// What restarts `{seq}` numbering at 1
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SequenceScope {
+    /// Every destination folder has its own numbering
+    #[default]
+    Folder,
+    /// Every capture day has its own numbering, within its folder
+    Day,
+    /// One numbering for the whole run
+    Run,
+}
+
+impl SequenceScope {
+    pub(crate) fn key(&self, dest_dir: &Path, datetime: &DateTime<Local>) -> String {
+        match self {
+            SequenceScope::Folder => dest_dir.to_string_lossy().into_owned(),
+            SequenceScope::Day => format!(
+                "{}\0{}",
+                dest_dir.to_string_lossy(),
+                datetime.format("%Y-%m-%d")
+            ),
+            SequenceScope::Run => String::new(),
+        }
+    }
+}
+
+impl std::str::FromStr for SequenceScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "folder" => Ok(SequenceScope::Folder),
+            "day" => Ok(SequenceScope::Day),
+            "run" => Ok(SequenceScope::Run),
+            _ => Err(format!(
+                "unknown sequence scope '{}' (expected folder, day or run)",
+                s
+            )),
+        }
+    }
+}
+
+/// The `{seq}` counters of a run. Numbers are handed out as files are planned, which
+/// happens in capture order whenever a template uses `{seq}`, whatever the `--order`.
+#[derive(Default)]
+pub struct Sequences {
+    pub(crate) scope: SequenceScope,
+    pub(crate) counters: Mutex<HashMap<String, usize>>,
+}
+
+impl Sequences {
+    pub fn new(scope: SequenceScope) -> Self {
+        Sequences {
+            scope,
+            counters: Mutex::new(HashMap::new()),
+        }
+    }
+}
@@ -0,0 +1,399 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::manifest::modified_secs;
+use crate::space::format_bytes;
+
+/// Bytes read and written at a time, small enough to notice a stop request within
+/// moments even on slow network shares
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// Extension of a copy in progress, which no run takes for a media file
+const PARTIAL_EXTENSION: &str = "timekeeper-partial";
+
+/// Extension of the journal noting how much of a partial copy is safely on disk
+const JOURNAL_EXTENSION: &str = "timekeeper-journal";
+
+/// Bytes copied between two points a later run can resume from
+const CHECKPOINT_INTERVAL: u64 = 64 << 20;
+
+/// What a copy reports to and listens to while it runs
+pub(crate) struct CopyWatch<'a> {
+    /// Stops the copy between two chunks once set
+    pub terminate_flag: Option<&'a AtomicBool>,
+    /// Called with the size of every chunk written
+    pub on_chunk: &'a dyn Fn(u64),
+}
+
+impl Default for CopyWatch<'_> {
+    fn default() -> Self {
+        CopyWatch {
+            terminate_flag: None,
+            on_chunk: &|_| {},
+        }
+    }
+}
+
+/// Copy `source` to `dest` a chunk at a time, returning the bytes copied. Unlike
+/// `fs::copy` a multi-gigabyte video reports its progress and can be stopped part
+/// way.
+///
+/// The copy is written under a temporary name next to `dest`, flushed to disk and
+/// only then renamed into place, so a crash or power cut never leaves a truncated
+/// file under the final name. Holes in sparse files (disk images, some video
+/// containers) stay holes.
+///
+/// Every `CHECKPOINT_INTERVAL` the copy is flushed and its length noted in a
+/// journal next to it. A copy that fails or is stopped after a checkpoint is kept,
+/// and the next copy of the same, unchanged source resumes from the checkpoint
+/// instead of from zero. Anything else that fails is removed again.
+pub(crate) fn copy_file(source: &Path, dest: &Path, watch: &CopyWatch) -> io::Result<u64> {
+    let mut reader = File::open(source)?;
+    let metadata = reader.metadata()?;
+    let partial = side_path(dest, PARTIAL_EXTENSION);
+    let journal = side_path(dest, JOURNAL_EXTENSION);
+
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut writer = None;
+    let result = match data_ranges(&reader) {
+        Some(ranges) => File::create(&partial).and_then(|file| {
+            copy_sparse(
+                &mut reader,
+                writer.insert(file),
+                &ranges,
+                &mut buffer,
+                watch,
+            )
+        }),
+        None => copy_resumable(
+            &mut reader,
+            &metadata,
+            (&partial, &journal),
+            &mut writer,
+            &mut buffer,
+            watch,
+        ),
+    }
+    .and_then(|bytes| match &writer {
+        Some(file) => file.sync_all().map(|()| bytes),
+        None => Ok(bytes),
+    })
+    .and_then(|bytes| fs::set_permissions(&partial, metadata.permissions()).map(|()| bytes))
+    .and_then(|bytes| fs::rename(&partial, dest).map(|()| bytes));
+    drop(writer);
+    if result.is_err() {
+        if !journal.exists() {
+            let _ = fs::remove_file(&partial);
+        }
+        return result;
+    }
+    let _ = fs::remove_file(&journal);
+    // The rename itself only survives a crash once the folder is on disk. The file
+    // is already in place by now, so failing to flush the folder doesn't fail it.
+    if let Err(e) = sync_dir(dest) {
+        eprintln!(
+            "[WARN] Could not flush the folder of {} to disk: {}",
+            dest.display(),
+            e
+        );
+    }
+    result
+}
+
+/// `.IMG_1234.jpg.<extension>` next to `IMG_1234.jpg`
+fn side_path(dest: &Path, extension: &str) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(dest.file_name().unwrap_or_default());
+    name.push(".");
+    name.push(extension);
+    dest.with_file_name(name)
+}
+
+/// How far a partial copy got, as last flushed to disk
+struct Checkpoint {
+    offset: u64,
+    /// Size and modification time of the source, which must not have changed
+    size: u64,
+    modified: Option<i64>,
+    /// blake3 of the source's first `offset` bytes
+    hash: String,
+}
+
+impl Checkpoint {
+    fn load(path: &Path) -> Option<Self> {
+        let text = fs::read_to_string(path).ok()?;
+        let mut fields = text.trim_end().split('\t');
+        Some(Checkpoint {
+            offset: fields.next()?.parse().ok()?,
+            size: fields.next()?.parse().ok()?,
+            modified: match fields.next()? {
+                "" => None,
+                secs => Some(secs.parse().ok()?),
+            },
+            hash: fields.next()?.to_string(),
+        })
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let line = format!(
+            "{}\t{}\t{}\t{}\n",
+            self.offset,
+            self.size,
+            self.modified
+                .map(|secs| secs.to_string())
+                .unwrap_or_default(),
+            self.hash
+        );
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, line)?;
+        fs::rename(&tmp_path, path)
+    }
+}
+
+/// Copy a file without holes into `partial`, resuming from the journal's
+/// checkpoint when it still matches the source, and saving new checkpoints as the
+/// copy goes
+fn copy_resumable(
+    reader: &mut File,
+    metadata: &fs::Metadata,
+    (partial, journal): (&Path, &Path),
+    writer: &mut Option<File>,
+    buffer: &mut [u8],
+    watch: &CopyWatch,
+) -> io::Result<u64> {
+    let mut hasher = blake3::Hasher::new();
+    let resumed = resume_point(reader, metadata, partial, journal, &mut hasher, buffer)?;
+    let file = match resumed {
+        Some(offset) => {
+            println!(
+                "[INFO] Resuming {} from {}",
+                partial.display(),
+                format_bytes(offset)
+            );
+            let mut file = OpenOptions::new().write(true).open(partial)?;
+            // Anything after the checkpoint may not have reached the disk whole
+            file.set_len(offset)?;
+            file.seek(SeekFrom::Start(offset))?;
+            (watch.on_chunk)(offset);
+            file
+        }
+        None => {
+            hasher.reset();
+            reader.seek(SeekFrom::Start(0))?;
+            match fs::remove_file(journal) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+            File::create(partial)?
+        }
+    };
+    let writer = writer.insert(file);
+
+    let mut checkpoint = Checkpoint {
+        offset: resumed.unwrap_or(0),
+        size: metadata.len(),
+        modified: modified_secs(metadata),
+        hash: String::new(),
+    };
+    let copied = copy_chunks_with(reader, writer, buffer, watch, &mut |writer, data| {
+        hasher.update(data);
+        (watch.on_chunk)(data.len() as u64);
+        let offset = checkpoint.offset + data.len() as u64;
+        if offset / CHECKPOINT_INTERVAL > checkpoint.offset / CHECKPOINT_INTERVAL {
+            writer.sync_data()?;
+            checkpoint.hash = hasher.finalize().to_hex().to_string();
+            checkpoint.offset = offset;
+            checkpoint.save(journal)?;
+        } else {
+            checkpoint.offset = offset;
+        }
+        Ok(())
+    })?;
+    Ok(resumed.unwrap_or(0) + copied)
+}
+
+/// The checkpoint to resume from, when the journal and partial copy left by an
+/// earlier run match the source as it is now. `hasher` ends up holding the
+/// source's bytes up to the checkpoint, and `reader` positioned right after them.
+fn resume_point(
+    reader: &mut File,
+    metadata: &fs::Metadata,
+    partial: &Path,
+    journal: &Path,
+    hasher: &mut blake3::Hasher,
+    buffer: &mut [u8],
+) -> io::Result<Option<u64>> {
+    let Some(checkpoint) = Checkpoint::load(journal) else {
+        return Ok(None);
+    };
+    let partial_len = fs::metadata(partial).map_or(0, |partial| partial.len());
+    if checkpoint.size != metadata.len()
+        || checkpoint.modified != modified_secs(metadata)
+        || checkpoint.offset == 0
+        || checkpoint.offset > partial_len
+    {
+        return Ok(None);
+    }
+
+    // Hashing the source again is local and cheap next to sending it again
+    let mut prefix = (&mut *reader).take(checkpoint.offset);
+    loop {
+        let read = prefix.read(buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok((hasher.finalize().to_hex().as_str() == checkpoint.hash).then_some(checkpoint.offset))
+}
+
+/// Flush the folder holding `path`, making a rename into it durable. Network and
+/// FUSE file systems that can't flush a folder are left to make it durable their way.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> io::Result<()> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    match File::open(dir)?.sync_all() {
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::InvalidInput | io::ErrorKind::Unsupported
+            ) =>
+        {
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Windows can't open a folder as a file, NTFS journals renames itself
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Copy only the data of a sparse file, seeking over the holes between `ranges`
+fn copy_sparse(
+    reader: &mut File,
+    writer: &mut File,
+    ranges: &[(u64, u64)],
+    buffer: &mut [u8],
+    watch: &CopyWatch,
+) -> io::Result<u64> {
+    let len = reader.metadata()?.len();
+    let mut position = 0;
+    for &(start, end) in ranges {
+        // A hole counts as done without writing a byte
+        (watch.on_chunk)(start - position);
+        reader.seek(SeekFrom::Start(start))?;
+        writer.seek(SeekFrom::Start(start))?;
+        copy_chunks(&mut (&mut *reader).take(end - start), writer, buffer, watch)?;
+        position = end;
+    }
+    (watch.on_chunk)(len.saturating_sub(position));
+    // A trailing hole has nothing to write, the length puts it back
+    writer.set_len(len)?;
+    Ok(len)
+}
+
+/// Where a sparse file has data, as `(start, end)` offsets, or `None` for a file
+/// without holes or a file system that can't tell
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "macos"
+))]
+fn data_ranges(file: &File) -> Option<Vec<(u64, u64)>> {
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::io::AsRawFd;
+
+    let metadata = file.metadata().ok()?;
+    // Fully allocated files skip the probing
+    if metadata.blocks() * 512 >= metadata.len() {
+        return None;
+    }
+
+    let fd = file.as_raw_fd();
+    let len = metadata.len() as libc::off_t;
+    let mut ranges = Vec::new();
+    let mut position = 0;
+    while position < len {
+        // SAFETY: lseek only moves the offset of a descriptor `file` keeps open
+        let start = unsafe { libc::lseek(fd, position, libc::SEEK_DATA) };
+        if start < 0 {
+            // ENXIO: only a hole is left; anything else: holes aren't supported here
+            let error = io::Error::last_os_error();
+            if error.raw_os_error() == Some(libc::ENXIO) {
+                break;
+            }
+            return None;
+        }
+        // SAFETY: as above
+        let end = unsafe { libc::lseek(fd, start, libc::SEEK_HOLE) };
+        if end < 0 {
+            return None;
+        }
+        ranges.push((start as u64, end as u64));
+        position = end;
+    }
+    Some(ranges)
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "macos"
+)))]
+fn data_ranges(_file: &File) -> Option<Vec<(u64, u64)>> {
+    None
+}
+
+fn copy_chunks(
+    reader: &mut dyn Read,
+    writer: &mut File,
+    buffer: &mut [u8],
+    watch: &CopyWatch,
+) -> io::Result<u64> {
+    copy_chunks_with(reader, writer, buffer, watch, &mut |_, data| {
+        (watch.on_chunk)(data.len() as u64);
+        Ok(())
+    })
+}
+
+/// Copy until the end of `reader`, handing every chunk written to `written`
+fn copy_chunks_with(
+    reader: &mut dyn Read,
+    writer: &mut File,
+    buffer: &mut [u8],
+    watch: &CopyWatch,
+    written: &mut dyn FnMut(&mut File, &[u8]) -> io::Result<()>,
+) -> io::Result<u64> {
+    let mut copied = 0;
+    loop {
+        if watch
+            .terminate_flag
+            .is_some_and(|flag| flag.load(Ordering::SeqCst))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "copy stopped before it finished",
+            ));
+        }
+        let read = match reader.read(buffer) {
+            Ok(0) => return Ok(copied),
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        writer.write_all(&buffer[..read])?;
+        copied += read as u64;
+        written(writer, &buffer[..read])?;
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::HashSet;
+use std::hash::{BuildHasher, RandomState};
+use std::path::PathBuf;
+
+use crate::Organizer;
+use crate::error::Result;
+use crate::visited::OrganizedFolders;
+
+/// A part of a run's files to try its settings on before the full run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trial {
+    /// The first files found, in walk order
+    First(usize),
+    /// Files drawn at random from all of them, so every folder and year can show up
+    Sample(usize),
+}
+
+impl Trial {
+    /// Pick the files to try from the sources of `organizers`, which are walked once
+    pub fn select(&self, organizers: &[Organizer]) -> Result<HashSet<PathBuf>> {
+        let wanted = match *self {
+            Trial::First(count) | Trial::Sample(count) => count,
+        };
+        let mut files = organizers
+            .iter()
+            .map(|organizer| organizer.trial_candidates())
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten();
+
+        Ok(match self {
+            Trial::First(_) => files.take(wanted).collect(),
+            // Reservoir sampling: the n-th file replaces a kept one with a chance of
+            // wanted/n, which keeps every file equally likely without holding them all
+            Trial::Sample(_) => {
+                let keys = RandomState::new();
+                let mut kept: Vec<PathBuf> = files.by_ref().take(wanted).collect();
+                for (index, file) in files.enumerate() {
+                    let seen = wanted + index + 1;
+                    let slot = (keys.hash_one(index) % seen as u64) as usize;
+                    if slot < wanted {
+                        kept[slot] = file;
+                    }
+                }
+                kept.into_iter().collect()
+            }
+        })
+    }
+}
+
+impl Organizer {
+    /// The media files a run of this organizer would go through, in walk order
+    fn trial_candidates(&self) -> Result<Box<dyn Iterator<Item = PathBuf> + Send>> {
+        if !self.source.is_dir() {
+            return Ok(Box::new(std::iter::once(self.source.clone())));
+        }
+        Ok(Box::new(
+            self.media_files(
+                &self.extractor_chain()?,
+                &OrganizedFolders::default(),
+                false,
+            )
+            .filter_map(|entry| entry.ok())
+            .map(ignore::DirEntry::into_path),
+        ))
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Files and folders already reached during one walk, by device and inode. Symlinks
+/// and bind mounts can lead to the same physical file through several paths, or
+/// back into a folder being walked; each is only visited the first time.
+#[derive(Debug, Default)]
+pub(crate) struct VisitedInodes {
+    #[cfg_attr(not(unix), allow(dead_code))]
+    seen: Mutex<HashSet<(u64, u64)>>,
+}
+
+impl VisitedInodes {
+    /// Mark `path` (or what it links to) as visited, returning whether this is the
+    /// first time. Paths that can't be read count as new so their error shows up.
+    #[cfg(unix)]
+    pub(crate) fn first_visit(&self, path: &Path) -> bool {
+        use std::os::unix::fs::MetadataExt;
+
+        match std::fs::metadata(path) {
+            Ok(metadata) => self
+                .seen
+                .lock()
+                .unwrap()
+                .insert((metadata.dev(), metadata.ino())),
+            Err(_) => true,
+        }
+    }
+
+    /// Windows has no stable file id in std, so nothing is tracked there
+    #[cfg(not(unix))]
+    pub(crate) fn first_visit(&self, _path: &Path) -> bool {
+        true
+    }
+}
+
+/// Folders `skip_organized` found already following the layout. The counting walk
+/// samples each folder and notes the organized ones, the walk feeding the pipeline
+/// reuses the notes instead of sampling every folder again.
+#[derive(Clone, Default)]
+pub(crate) struct OrganizedFolders {
+    found: Arc<Mutex<HashSet<PathBuf>>>,
+    /// Set once a walk has sampled every folder
+    known: bool,
+}
+
+impl OrganizedFolders {
+    /// The notes of a finished walk, for the next walk to reuse
+    pub(crate) fn known(self) -> Self {
+        OrganizedFolders {
+            known: true,
+            ..self
+        }
+    }
+
+    /// Whether `dir` is organized, asking `sample` unless an earlier walk has
+    pub(crate) fn contains(&self, dir: &Path, sample: impl FnOnce() -> bool) -> bool {
+        if self.known {
+            return self.found.lock().unwrap().contains(dir);
+        }
+        let organized = sample();
+        if organized {
+            self.found.lock().unwrap().insert(dir.to_path_buf());
+        }
+        organized
+    }
+}
+
+/// Whether a walk error is a symlink back into a folder being walked, which is
+/// skipped like any other folder reached before
+pub(crate) fn is_walk_loop(error: &ignore::Error) -> bool {
+    match error {
+        ignore::Error::Loop { .. } => true,
+        ignore::Error::WithPath { err, .. }
+        | ignore::Error::WithDepth { err, .. }
+        | ignore::Error::WithLineNumber { err, .. } => is_walk_loop(err),
+        _ => false,
+    }
+}
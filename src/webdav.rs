@@ -0,0 +1,184 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use ureq::http::{self, Response, StatusCode};
+
+use crate::remote::{Attempt, RemoteStore, uri_encode, with_retries};
+
+/// A WebDAV collection such as a Nextcloud or ownCloud folder, addressed as
+/// `davs://[user@]host/remote.php/dav/files/user/Photos` (`dav://` for plain HTTP).
+/// The user comes from the URL or `WEBDAV_USER`, the password (ideally an app
+/// password) from `WEBDAV_PASSWORD`.
+pub struct WebDavStore {
+    root: PathBuf,
+    base_url: String,
+    authorization: Option<String>,
+    /// Collections known to exist, so each is only created once per run
+    created: Mutex<HashSet<String>>,
+    agent: ureq::Agent,
+}
+
+impl WebDavStore {
+    pub fn from_url(url: &str) -> io::Result<Self> {
+        let (scheme, location) = match url.split_once("://") {
+            Some(("dav", location)) => ("http", location),
+            Some(("davs", location)) => ("https", location),
+            _ => {
+                return Err(io::Error::other(format!(
+                    "Not a dav:// or davs:// URL: '{}'",
+                    url
+                )));
+            }
+        };
+        let (authority, path) = location.split_once('/').unwrap_or((location, ""));
+        let (user, host) = match authority.rsplit_once('@') {
+            Some((user, host)) => (Some(user.to_string()), host),
+            None => (std::env::var("WEBDAV_USER").ok(), authority),
+        };
+        if host.is_empty() {
+            return Err(io::Error::other(format!("No host in '{}'", url)));
+        }
+
+        let authorization = user.map(|user| {
+            let password = std::env::var("WEBDAV_PASSWORD").unwrap_or_default();
+            format!("Basic {}", BASE64.encode(format!("{}:{}", user, password)))
+        });
+
+        let path = path.trim_matches('/');
+        let base_url = if path.is_empty() {
+            format!("{}://{}", scheme, host)
+        } else {
+            format!("{}://{}/{}", scheme, host, uri_encode(path, false))
+        };
+
+        // MKCOL is a WebDAV extension method
+        let agent = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .allow_non_standard_methods(true)
+            .build()
+            .into();
+
+        Ok(WebDavStore {
+            root: PathBuf::from(url),
+            base_url,
+            authorization,
+            created: Mutex::new(HashSet::new()),
+            agent,
+        })
+    }
+
+    /// Send one request. Server errors (including 507 Insufficient Storage) and rate
+    /// limiting are retryable, honoring `Retry-After`; any other response is handed
+    /// back for the caller to judge.
+    fn send(
+        &self,
+        method: &str,
+        key: &str,
+        body: Option<&Path>,
+    ) -> Result<Response<ureq::Body>, Attempt> {
+        let url = format!("{}/{}", self.base_url, uri_encode(key, false));
+        let mut request = http::Request::builder().method(method).uri(url);
+        if let Some(authorization) = &self.authorization {
+            request = request.header("authorization", authorization);
+        }
+
+        let fail = |e: http::Error| Attempt::Fail(io::Error::other(e));
+        let result = match body {
+            None => self.agent.run(request.body(()).map_err(fail)?),
+            Some(path) => {
+                let file = File::open(path).map_err(Attempt::Fail)?;
+                self.agent.run(request.body(file).map_err(fail)?)
+            }
+        };
+
+        let response = result.map_err(|e| Attempt::Retry(io::Error::other(e)))?;
+        let status = response.status();
+        if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            let error = io::Error::other(format!("WebDAV server returned {}", status));
+            return Err(Attempt::retry_after(error, retry_after(&response)));
+        }
+        if status == StatusCode::UNAUTHORIZED {
+            return Err(Attempt::Fail(io::Error::other(
+                "WebDAV server rejected the credentials (check WEBDAV_USER and WEBDAV_PASSWORD)",
+            )));
+        }
+        Ok(response)
+    }
+}
+
+/// Delay requested by a `Retry-After: <seconds>` header
+fn retry_after(response: &Response<ureq::Body>) -> Option<Duration> {
+    response
+        .headers()
+        .get("retry-after")
+        .and_then(|value| value.to_str().ok()?.trim().parse().ok())
+        .map(Duration::from_secs)
+}
+
+fn expect_success(response: Response<ureq::Body>) -> Result<Response<ureq::Body>, Attempt> {
+    if response.status().is_success() {
+        Ok(response)
+    } else {
+        Err(Attempt::Fail(io::Error::other(format!(
+            "WebDAV server returned {}",
+            response.status()
+        ))))
+    }
+}
+
+impl RemoteStore for WebDavStore {
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn stat(&self, key: &str) -> io::Result<Option<u64>> {
+        with_retries(|| {
+            let response = self.send("HEAD", key, None)?;
+            if response.status() == StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            let response = expect_success(response)?;
+            Ok(response
+                .headers()
+                .get("content-length")
+                .and_then(|value| value.to_str().ok()?.parse().ok()))
+        })
+    }
+
+    fn upload(&self, local: &Path, key: &str) -> io::Result<()> {
+        with_retries(|| expect_success(self.send("PUT", key, Some(local))?).map(|_| ()))
+    }
+
+    /// Create each missing collection with MKCOL, starting at the URL's own folder
+    fn create_dir_all(&self, key: &str) -> io::Result<()> {
+        let mut collection = String::new();
+        let parts = std::iter::once("").chain(key.split('/').filter(|part| !part.is_empty()));
+        for part in parts {
+            if !part.is_empty() {
+                if !collection.is_empty() {
+                    collection.push('/');
+                }
+                collection.push_str(part);
+            }
+            if self.created.lock().unwrap().contains(&collection) {
+                continue;
+            }
+
+            with_retries(|| {
+                let response = self.send("MKCOL", &collection, None)?;
+                // 405 Method Not Allowed: the collection already exists
+                if response.status() == StatusCode::METHOD_NOT_ALLOWED {
+                    return Ok(());
+                }
+                expect_success(response).map(|_| ())
+            })?;
+            self.created.lock().unwrap().insert(collection.clone());
+        }
+        Ok(())
+    }
+}
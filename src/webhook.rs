@@ -0,0 +1,33 @@
+use serde::Serialize;
+use std::path::Path;
+
+use crate::RunOutcome;
+use crate::stats::{Stats, StatsSnapshot};
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    outcome: RunOutcome,
+    source: &'a Path,
+    destination: &'a Path,
+    stats: StatsSnapshot,
+}
+
+/// POST the run summary as JSON to `url`, e.g. an ntfy topic, a Slack-compatible
+/// relay or a Healthchecks ping URL.
+pub fn post_summary(
+    url: &str,
+    outcome: RunOutcome,
+    source: &Path,
+    destination: &Path,
+    stats: &Stats,
+) -> Result<(), ureq::Error> {
+    let payload = WebhookPayload {
+        outcome,
+        source,
+        destination,
+        stats: stats.snapshot(),
+    };
+
+    ureq::post(url).send_json(&payload)?;
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use regex::Regex;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+lazy_static::lazy_static! {
+    static ref RATING: Regex = Regex::new(r#"xmp:Rating(?:="|>)\s*(-?\d+)"#).unwrap();
+    static ref SUBJECT: Regex = Regex::new(r"(?s)<dc:subject>(.*?)</dc:subject>").unwrap();
+    static ref LIST_ITEM: Regex = Regex::new(r"(?s)<rdf:li[^>]*>(.*?)</rdf:li>").unwrap();
+}
+
+/// How far into a file its embedded XMP packet is looked for. Cameras and editors
+/// write it in the header, never after the image data.
+const EMBEDDED_SEARCH_BYTES: u64 = 512 * 1024;
+
+/// What photo managers record in a file's XMP
+#[derive(Debug, Clone, Default)]
+pub struct XmpInfo {
+    /// Star rating, 0 to 5 (-1 marks a rejected file)
+    pub rating: Option<i32>,
+    /// `dc:subject` keywords, in the order they were written
+    pub keywords: Vec<String>,
+}
+
+/// Contents of the XMP sidecars next to a file, named either `IMG_1234.jpg.xmp`
+/// or `IMG_1234.xmp`
+pub fn sidecars(path: &Path) -> Vec<String> {
+    let (Some(name), Some(stem)) = (path.file_name(), path.file_stem()) else {
+        return Vec::new();
+    };
+
+    let mut found = Vec::new();
+    for base in [name, stem] {
+        for ext in ["xmp", "XMP"] {
+            let mut sidecar_name = base.to_os_string();
+            sidecar_name.push(format!(".{}", ext));
+            if let Ok(xmp) = fs::read_to_string(path.with_file_name(sidecar_name)) {
+                found.push(xmp);
+            }
+        }
+    }
+    found
+}
+
+/// The XMP packet embedded in the file itself (JPEG, TIFF, DNG, PNG, ...)
+pub fn embedded(path: &Path) -> Option<String> {
+    let mut head = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(EMBEDDED_SEARCH_BYTES)
+        .read_to_end(&mut head)
+        .ok()?;
+
+    let start = find(&head, b"<x:xmpmeta")?;
+    let end = start + find(&head[start..], b"</x:xmpmeta>")?;
+    Some(String::from_utf8_lossy(&head[start..end]).into_owned())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Read a file's rating and keywords. A sidecar wins over the embedded packet,
+/// as that is where photo managers write edits to files they don't modify.
+pub fn read(path: &Path) -> XmpInfo {
+    sidecars(path)
+        .into_iter()
+        .chain(embedded(path))
+        .map(|xmp| parse(&xmp))
+        .find(|info| info.rating.is_some() || !info.keywords.is_empty())
+        .unwrap_or_default()
+}
+
+fn parse(xmp: &str) -> XmpInfo {
+    XmpInfo {
+        rating: RATING
+            .captures(xmp)
+            .and_then(|captures| captures[1].parse().ok()),
+        keywords: SUBJECT
+            .captures(xmp)
+            .map(|subject| {
+                LIST_ITEM
+                    .captures_iter(&subject[1])
+                    .map(|item| unescape(item[1].trim()))
+                    .filter(|keyword| !keyword.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default(),
+    }
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,95 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+
+use timekeeper::dump::MetadataDump;
+use timekeeper::layout::FileNaming;
+use timekeeper::stats::Stats;
+use timekeeper::{Organizer, ProcessingOrder};
+
+/// Files named against their capture order, so path order and date order differ
+const FILES: [(&str, &str); 3] = [
+    ("a.jpg", "2021:03:05 10:00:00"),
+    ("b.jpg", "2021:03:03 10:00:00"),
+    ("c.jpg", "2021:03:04 10:00:00"),
+];
+
+/// A fresh source tree holding `FILES` and an exiftool dump dating them
+fn source_tree(name: &str) -> (PathBuf, MetadataDump) {
+    let root = std::env::temp_dir().join(format!("timekeeper-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    let source = root.join("source");
+    fs::create_dir_all(&source).unwrap();
+
+    let mut records = Vec::new();
+    for (file, date) in FILES {
+        fs::write(source.join(file), file).unwrap();
+        records.push(serde_json::json!({
+            "SourceFile": source.join(file).canonicalize().unwrap(),
+            "DateTimeOriginal": date,
+        }));
+    }
+    let dump_path = root.join("dump.json");
+    fs::write(&dump_path, serde_json::to_vec(&records).unwrap()).unwrap();
+    let dump = MetadataDump::load(&dump_path, &source).unwrap();
+    (root, dump)
+}
+
+/// Dry-run `organizer` and return each source file's planned name
+fn planned_names(organizer: Organizer) -> Vec<(String, String)> {
+    let (outcomes, finished) = crossbeam_channel::unbounded();
+    organizer
+        .run_with_outcomes(
+            Arc::new(Stats::new()),
+            Arc::new(AtomicBool::new(false)),
+            outcomes,
+        )
+        .unwrap();
+    let name = |path: &Path| path.file_name().unwrap().to_string_lossy().into_owned();
+    let mut names: Vec<_> = finished
+        .try_iter()
+        .filter_map(|outcome| Some((name(&outcome.source), name(&outcome.destination?))))
+        .collect();
+    names.sort();
+    names
+}
+
+fn numbered(root: &Path, dump: MetadataDump) -> Organizer {
+    Organizer::new(root.join("source"), root.join("organized"), true)
+        .with_metadata_dump(dump)
+        .with_naming(FileNaming::Template("{seq}".parse().unwrap()))
+}
+
+fn chronological() -> Vec<(String, String)> {
+    [
+        ("a.jpg", "003.jpg"),
+        ("b.jpg", "001.jpg"),
+        ("c.jpg", "002.jpg"),
+    ]
+    .iter()
+    .map(|(source, name)| (source.to_string(), name.to_string()))
+    .collect()
+}
+
+#[test]
+fn seq_numbers_follow_capture_date() {
+    let (root, dump) = source_tree("seq-default");
+    let names = planned_names(numbered(&root, dump));
+    let _ = fs::remove_dir_all(&root);
+    assert_eq!(names, chronological());
+}
+
+#[test]
+fn seq_numbers_ignore_other_orders() {
+    for order in [
+        ProcessingOrder::Path,
+        ProcessingOrder::Size,
+        ProcessingOrder::Random,
+    ] {
+        let (root, dump) = source_tree(&format!("seq-{:?}", order));
+        let names = planned_names(numbered(&root, dump).with_order(order));
+        let _ = fs::remove_dir_all(&root);
+        assert_eq!(names, chronological(), "{:?} order", order);
+    }
+}